@@ -0,0 +1,414 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg};
+use std::str::FromStr;
+
+/// What happens when the result of integer arithmetic does not fit in an `Int`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// The result is promoted to an arbitrary-precision `BigInt`
+    #[default]
+    Promote,
+    /// The operation fails with `ProgramError::IntegerOverflow`
+    Error,
+}
+
+/// An arbitrary-precision integer
+///
+/// The value is stored as its sign and magnitude, where the magnitude is a list of
+/// 32-bit digits with the least significant digit first. The magnitude never has
+/// leading zero digits, and zero is never negative, so that equal values are
+/// always represented the same way.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+/// Number of bits in a digit of the magnitude
+const DIGIT_BITS: u32 = 32;
+
+/// Largest power of ten that fits in a digit, used to convert to and from decimal
+const DECIMAL_CHUNK: u32 = 1_000_000_000;
+
+/// Number of decimal digits in `DECIMAL_CHUNK`
+const DECIMAL_CHUNK_DIGITS: usize = 9;
+
+/// Largest number of bits of a power computed by `checked_pow`, about 300000 decimal digits
+const MAX_POWER_BITS: u64 = 1_000_000;
+
+impl BigInt {
+    /// Builds a value from its sign and magnitude, restoring the invariants.
+    fn from_parts(negative: bool, mut magnitude: Vec<u32>) -> Self {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        let negative = negative && !magnitude.is_empty();
+        Self { negative, magnitude }
+    }
+
+    /// Checks whether the value is zero.
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    /// Checks whether the value is less than zero.
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Returns `-1`, `0` or `1` depending on the sign of the value.
+    pub fn signum(&self) -> i128 {
+        match (self.negative, self.is_zero()) {
+            (_, true) => 0,
+            (true, _) => -1,
+            (false, _) => 1,
+        }
+    }
+
+    /// Returns the absolute value.
+    pub fn abs(self) -> Self {
+        Self { negative: false, magnitude: self.magnitude }
+    }
+
+    /// Converts the value to an `i128`.
+    ///
+    /// # Returns
+    ///
+    /// The value, or `None` if it does not fit.
+    ///
+    pub fn to_i128(&self) -> Option<i128> {
+        if self.magnitude.len() > 4 {
+            return None
+        }
+        let magnitude = self.magnitude.iter().rev().fold(0u128, |acc, &digit| (acc << DIGIT_BITS) | digit as u128);
+        if self.negative {
+            0i128.checked_sub_unsigned(magnitude)
+        } else {
+            i128::try_from(magnitude).ok()
+        }
+    }
+
+    /// Converts the value to the nearest `f64`, which is infinite if the value is too large.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.magnitude.iter().rev().fold(0.0, |acc, &digit| acc * 4294967296.0 + digit as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    /// Returns the number of bits of the magnitude, which is 0 for zero.
+    pub fn bits(&self) -> u64 {
+        match self.magnitude.last() {
+            Some(last) => self.magnitude.len() as u64 * DIGIT_BITS as u64 - last.leading_zeros() as u64,
+            None => 0
+        }
+    }
+
+    /// Raises the value to the power of `exponent`, unless the power is too large.
+    ///
+    /// # Returns
+    ///
+    /// The power, or `None` if it would have more than `MAX_POWER_BITS` bits.
+    ///
+    pub fn checked_pow(&self, exponent: u32) -> Option<Self> {
+        // the power has more than `(bits - 1) * exponent` bits, which is 0 for -1, 0 and 1
+        let bits = self.bits().saturating_sub(1).saturating_mul(exponent as u64);
+        (bits <= MAX_POWER_BITS).then(|| self.pow(exponent))
+    }
+
+    /// Raises the value to the power of `exponent` by repeated squaring.
+    pub fn pow(&self, mut exponent: u32) -> Self {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// Divides the value by `other`, rounding towards zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The divisor, which must not be zero.
+    ///
+    /// # Returns
+    ///
+    /// The quotient and the remainder, where the remainder has the sign of `self`.
+    ///
+    pub fn div_rem(&self, other: &BigInt) -> (BigInt, BigInt) {
+        let (quotient, remainder) = divide_magnitudes(&self.magnitude, &other.magnitude);
+        (BigInt::from_parts(self.negative != other.negative, quotient), BigInt::from_parts(self.negative, remainder))
+    }
+}
+
+impl From<i128> for BigInt {
+    /// Converts an `i128` to a `BigInt`.
+    fn from(value: i128) -> Self {
+        let mut magnitude = value.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push(magnitude as u32);
+            magnitude >>= DIGIT_BITS;
+        }
+        BigInt::from_parts(value < 0, digits)
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = ();
+
+    /// Parses a decimal integer with an optional sign.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(())` if the string contains anything but digits after the sign
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(())
+        }
+        // convert nine digits at a time, starting with the possibly shorter leading chunk
+        let mut magnitude = Vec::new();
+        let mut start = 0;
+        let mut end = match digits.len() % DECIMAL_CHUNK_DIGITS {
+            0 => DECIMAL_CHUNK_DIGITS,
+            n => n,
+        };
+        while start < digits.len() {
+            let chunk = &digits[start..end];
+            mul_add_small(&mut magnitude, 10u32.pow(chunk.len() as u32), chunk.parse().unwrap());
+            start = end;
+            end += DECIMAL_CHUNK_DIGITS;
+        }
+        Ok(BigInt::from_parts(negative, magnitude))
+    }
+}
+
+impl fmt::Display for BigInt {
+    /// Formats the value in decimal.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0")
+        }
+        let mut chunks = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            chunks.push(div_rem_small(&mut magnitude, DECIMAL_CHUNK));
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for BigInt {
+    /// Compares two values numerically.
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitudes(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    /// Returns the value with the opposite sign.
+    fn neg(self) -> Self::Output {
+        BigInt::from_parts(!self.negative, self.magnitude)
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    /// Adds two values.
+    fn add(self, other: &BigInt) -> Self::Output {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitudes(&self.magnitude, &other.magnitude))
+        }
+        // the signs differ, so subtract the smaller magnitude from the larger one
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::from_parts(other.negative, sub_magnitudes(&other.magnitude, &self.magnitude)),
+            _ => BigInt::from_parts(self.negative, sub_magnitudes(&self.magnitude, &other.magnitude)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    /// Subtracts `other` from `self`.
+    fn sub(self, other: &BigInt) -> Self::Output {
+        self + &-other.clone()
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    /// Multiplies two values.
+    fn mul(self, other: &BigInt) -> Self::Output {
+        let mut product = vec![0u32; self.magnitude.len() + other.magnitude.len()];
+        for (i, &x) in self.magnitude.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &y) in other.magnitude.iter().enumerate() {
+                let sum = product[i + j] as u64 + x as u64 * y as u64 + carry;
+                product[i + j] = sum as u32;
+                carry = sum >> DIGIT_BITS;
+            }
+            product[i + other.magnitude.len()] = carry as u32;
+        }
+        BigInt::from_parts(self.negative != other.negative, product)
+    }
+}
+
+impl Add for BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: BigInt) -> Self::Output {
+        &self + &other
+    }
+}
+
+impl Sub for BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: BigInt) -> Self::Output {
+        &self - &other
+    }
+}
+
+impl Mul for BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: BigInt) -> Self::Output {
+        &self * &other
+    }
+}
+
+/// Compares two magnitudes.
+fn compare_magnitudes(x: &[u32], y: &[u32]) -> Ordering {
+    x.len().cmp(&y.len()).then_with(|| x.iter().rev().cmp(y.iter().rev()))
+}
+
+/// Adds two magnitudes.
+fn add_magnitudes(x: &[u32], y: &[u32]) -> Vec<u32> {
+    let (long, short) = if x.len() >= y.len() { (x, y) } else { (y, x) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &digit) in long.iter().enumerate() {
+        let total = digit as u64 + short.get(i).copied().unwrap_or(0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> DIGIT_BITS;
+    }
+    sum.push(carry as u32);
+    sum
+}
+
+/// Subtracts the magnitude `y` from the magnitude `x`, which must not be smaller.
+fn sub_magnitudes(x: &[u32], y: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(x.len());
+    let mut borrow = 0i64;
+    for (i, &digit) in x.iter().enumerate() {
+        let mut total = digit as i64 - y.get(i).copied().unwrap_or(0) as i64 - borrow;
+        borrow = if total < 0 { 1 } else { 0 };
+        if total < 0 {
+            total += 1 << DIGIT_BITS;
+        }
+        difference.push(total as u32);
+    }
+    difference
+}
+
+/// Multiplies the magnitude by `factor` and adds `addend`, in place.
+fn mul_add_small(magnitude: &mut Vec<u32>, factor: u32, addend: u32) {
+    let mut carry = addend as u64;
+    for digit in magnitude.iter_mut() {
+        let total = *digit as u64 * factor as u64 + carry;
+        *digit = total as u32;
+        carry = total >> DIGIT_BITS;
+    }
+    if carry > 0 {
+        magnitude.push(carry as u32);
+    }
+}
+
+/// Divides the magnitude by `divisor` in place, and returns the remainder.
+fn div_rem_small(magnitude: &mut Vec<u32>, divisor: u32) -> u32 {
+    let mut remainder = 0u64;
+    for digit in magnitude.iter_mut().rev() {
+        let current = (remainder << DIGIT_BITS) | *digit as u64;
+        *digit = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    while magnitude.last() == Some(&0) {
+        magnitude.pop();
+    }
+    remainder as u32
+}
+
+/// Divides the magnitude `x` by the non-zero magnitude `y`, one bit at a time.
+///
+/// # Returns
+///
+/// The magnitudes of the quotient and the remainder.
+///
+fn divide_magnitudes(x: &[u32], y: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if compare_magnitudes(x, y) == Ordering::Less {
+        return (Vec::new(), x.to_vec())
+    }
+    if let [divisor] = y {
+        let mut quotient = x.to_vec();
+        let remainder = div_rem_small(&mut quotient, *divisor);
+        return (quotient, vec![remainder])
+    }
+    let mut quotient = vec![0u32; x.len()];
+    let mut remainder: Vec<u32> = Vec::with_capacity(y.len() + 1);
+    for bit in (0..x.len() * DIGIT_BITS as usize).rev() {
+        // shift the next bit of the dividend into the remainder
+        let incoming = (x[bit / DIGIT_BITS as usize] >> (bit % DIGIT_BITS as usize)) & 1;
+        let mut carry = incoming;
+        for digit in remainder.iter_mut() {
+            let next = *digit >> (DIGIT_BITS - 1);
+            *digit = (*digit << 1) | carry;
+            carry = next;
+        }
+        if carry > 0 {
+            remainder.push(carry);
+        }
+        if compare_magnitudes(&remainder, y) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, y);
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+            quotient[bit / DIGIT_BITS as usize] |= 1 << (bit % DIGIT_BITS as usize);
+        }
+    }
+    (quotient, remainder)
+}
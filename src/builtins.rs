@@ -0,0 +1,356 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f64::consts;
+use std::fmt;
+use std::ops::Not;
+use std::process;
+use std::rc::Rc;
+use crate::capabilities::Capability;
+use crate::error::ProgramError;
+use crate::interpreter::execute_loop;
+use crate::lists::{self, Collect, Fold};
+use crate::map;
+use crate::math;
+use crate::strings;
+use crate::template;
+use crate::rational::Division;
+use crate::regex;
+use crate::scope;
+use crate::state::State;
+use crate::token::Token;
+
+/// Native implementation of a bprog word
+///
+/// The function gets access to the whole state, and takes its operands from the stack
+/// itself. A returned token is pushed onto the stack.
+///
+pub type NativeFn = Rc<dyn Fn(&mut State) -> Result<Option<Token>, ProgramError>>;
+
+/// A word implemented in Rust
+#[derive(Clone)]
+pub struct Builtin {
+    /// Number of operands the word takes from the stack
+    pub arity: usize,
+    /// The implementation of the word
+    pub function: NativeFn,
+    /// The capability the state must have for the word to run, if any
+    pub capability: Option<Capability>,
+}
+
+impl Builtin {
+    /// Executes the word.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The stack, instruction list, list of functions and bindings
+    ///
+    /// # Errors
+    ///
+    /// Returns `CapabilityDenied` if the state lacks the capability of the word, and
+    /// `StackEmpty` without touching the stack if there are fewer than `arity`
+    /// operands, otherwise any error raised by the word itself
+    ///
+    pub fn call(&self, state: &mut State) -> Result<Option<Token>, ProgramError> {
+        if self.capability.is_some_and(|capability| !state.capabilities.allows(capability)) {
+            return Err(ProgramError::CapabilityDenied)
+        }
+        if state.stack.len() < self.arity {
+            return Err(ProgramError::StackEmpty)
+        }
+        (self.function)(state)
+    }
+}
+
+/// The words that are implemented in Rust, looked up by name
+///
+/// The registry of a state starts out with the standard builtins. Host applications
+/// can add their own words, or replace existing ones, with the `register` methods.
+///
+#[derive(Clone, Default)]
+pub struct Registry {
+    builtins: HashMap<String, Builtin>,
+}
+
+impl fmt::Debug for Registry {
+    /// Formats the registry as the sorted list of its word names.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.builtins.keys().collect();
+        names.sort();
+        f.debug_list().entries(names).finish()
+    }
+}
+
+impl Registry {
+    /// Creates a registry containing the standard builtins.
+    pub fn standard() -> Self {
+        let mut registry = Registry::default();
+
+        // stack operations
+        registry.register("swap", 2, |state| state.stack_swap());
+        registry.register("dup", 1, |state| state.stack_dup());
+        registry.register("over", 2, |state| state.stack_over());
+        registry.register("rot", 3, |state| state.stack_rot());
+        registry.register_unary("pop", |_, _| Ok(None));
+
+        // arithmetic, comparison and boolean operations
+        registry.register_binary("+", |left, right, state| state.check_overflow(left + right));
+        registry.register_binary("-", |left, right, state| state.check_overflow(left - right));
+        registry.register_binary("*", |left, right, state| state.check_overflow(left * right));
+        registry.register_binary("/", |left, right, state| match (&left, &right, state.division) {
+            (Token::Decimal(_), _, _) | (_, Token::Decimal(_), _) | (_, _, Division::Float) => left.decimal_div(right, state.decimals),
+            (_, _, Division::Exact) => left.exact_div(right),
+        });
+        registry.register_binary("div", |left, right, state| state.check_overflow(left.int_div(right)));
+        registry.register_binary("mod", |left, right, state| state.check_overflow(left.modulo(right)));
+        registry.register_binary("rem", |left, right, state| state.check_overflow(left % right));
+        registry.register_binary("pow", |left, right, state| state.check_overflow(left.pow(right)));
+        registry.register_binary("**", |left, right, state| state.check_overflow(left.pow(right)));
+        registry.register_binary("min", |left, right, _| left.min(right));
+        registry.register_binary("max", |left, right, _| left.max(right));
+        registry.register_binary("rational", |left, right, _| left.rational(right));
+        registry.register_unary("toFloat", |token, _| token.to_float());
+        registry.register_unary("toInt", |token, state| state.check_overflow(math::round(token, f64::trunc)));
+        registry.register_unary("neg", |token, state| state.check_overflow(-token));
+        registry.register_unary("abs", |token, state| state.check_overflow(token.abs()));
+        registry.register_unary("sign", |token, _| token.sign());
+        registry.register_binary("<", |left, right, _| left.compare(right, Ordering::Less));
+        registry.register_binary(">", |left, right, _| left.compare(right, Ordering::Greater));
+        registry.register_binary("==", |left, right, _| left.compare(right, Ordering::Equal));
+        registry.register_binary("&&", |left, right, _| left.and(right));
+        registry.register_binary("||", |left, right, _| left.or(right));
+        registry.register_unary("not", |token, _| token.not());
+
+        // math functions, see the math module for how NaN and infinity are handled
+        registry.register_unary("sqrt", |token, _| math::unary(token, f64::sqrt));
+        registry.register_unary("exp", |token, _| math::unary(token, f64::exp));
+        registry.register_unary("ln", |token, _| math::unary(token, f64::ln));
+        registry.register_unary("log10", |token, _| math::unary(token, f64::log10));
+        registry.register_unary("sin", |token, _| math::unary(token, f64::sin));
+        registry.register_unary("cos", |token, _| math::unary(token, f64::cos));
+        registry.register_unary("tan", |token, _| math::unary(token, f64::tan));
+        registry.register_binary("atan2", |y, x, _| math::binary(y, x, f64::atan2));
+        registry.register_unary("floor", |token, state| state.check_overflow(math::round(token, f64::floor)));
+        registry.register_unary("ceil", |token, state| state.check_overflow(math::round(token, f64::ceil)));
+        registry.register_unary("round", |token, state| state.check_overflow(math::round(token, f64::round)));
+        registry.register_unary("truncate", |token, state| state.check_overflow(math::round(token, f64::trunc)));
+        registry.register_unary("isNaN", |token, _| math::classify(token, false));
+        registry.register_unary("isInfinite", |token, _| math::classify(token, true));
+        registry.register("pi", 0, |_| Ok(Some(Token::Float(consts::PI))));
+        registry.register("e", 0, |_| Ok(Some(Token::Float(consts::E))));
+
+        // strings and lists
+        registry.register_unary("length", |token, _| token.len());
+        registry.register_unary("parseInteger", |token, state| state.check_overflow(token.parse_int()));
+        registry.register_unary("parseFloat", |token, _| token.parse_float());
+        registry.register_unary("parseDecimal", |token, _| token.parse_decimal());
+        registry.register_unary("words", |token, _| token.words());
+        registry.register_unary("empty", |token, _| token.empty());
+        registry.register_unary("head", |token, _| token.head());
+        registry.register_unary("tail", |token, _| token.tail());
+        registry.register_binary("cons", |item, list, _| list.cons(item));
+        registry.register_binary("append", |left, right, _| left.append(right));
+        registry.register_unary("reverse", |list, _| lists::reverse(list));
+        registry.register_unary("sort", |list, _| lists::sort(list));
+        registry.register_binary("zip", |left, right, _| lists::zip(left, right));
+        registry.register_binary("range", |start, end, state| lists::range(start, end, state.limits.max_collection_size));
+        registry.register_binary("take", |list, n, _| lists::take(list, n));
+        registry.register_binary("drop", |list, n, _| lists::drop(list, n));
+        registry.register_binary("nth", |list, n, _| lists::nth(list, n));
+        registry.register_unary("last", |list, _| lists::last(list));
+        registry.register_unary("flatten", |list, _| lists::flatten(list));
+        registry.register_unary("unique", |list, _| lists::unique(list));
+        registry.register_ternary("substring", |s, start, end, _| strings::substring(s, start, end));
+        registry.register_binary("indexOf", |s, pattern, _| match s {
+            Token::List(_) => lists::index_of(s, pattern),
+            _ => strings::index_of(s, pattern),
+        });
+        registry.register_binary("split", |s, separator, _| strings::split(s, separator));
+        registry.register_binary("join", |list, separator, _| strings::join(list, separator));
+        registry.register_unary("trim", |s, _| strings::transform(s, |s| s.trim().to_string()));
+        registry.register_unary("upper", |s, _| strings::transform(s, str::to_uppercase));
+        registry.register_unary("lower", |s, _| strings::transform(s, str::to_lowercase));
+        registry.register_ternary("replace", |s, from, to, _| strings::replace(s, from, to));
+        registry.register_binary("startsWith", |s, prefix, _| strings::test(s, prefix, |s, prefix| s.starts_with(prefix)));
+        registry.register_binary("endsWith", |s, suffix, _| strings::test(s, suffix, |s, suffix| s.ends_with(suffix)));
+        registry.register_binary("contains", |s, pattern, _| match s {
+            Token::List(_) => lists::contains(s, pattern),
+            _ => strings::test(s, pattern, |s, pattern| s.contains(pattern)),
+        });
+        registry.register_unary("chars", |s, _| strings::chars(s));
+        registry.register_unary("fromChars", |list, _| strings::from_chars(list));
+        registry.register_unary("toString", |token, _| strings::to_string(token));
+        registry.register("format", 1, template::format);
+
+        // regular expressions, compiled once per state, see the regex module for the syntax
+        registry.register_binary("matches?", |s, pattern, state| regex::is_match(s, pattern, &state.regexes));
+        registry.register_binary("match", |s, pattern, state| regex::first_match(s, pattern, &state.regexes));
+        registry.register_binary("matchAll", |s, pattern, state| regex::all_matches(s, pattern, &state.regexes));
+        registry.register_ternary("regexReplace", |s, pattern, replacement, state| regex::replace(s, pattern, replacement, &state.regexes));
+        registry.register_binary("regexSplit", |s, pattern, state| regex::split(s, pattern, &state.regexes));
+
+        // maps
+        registry.register_binary("get", |map, key, _| map::get(map, key));
+        registry.register_ternary("put", |map, key, value, _| map::put(map, key, value));
+        registry.register_binary("remove", |map, key, _| map::remove(map, key));
+        registry.register_binary("hasKey", |map, key, _| map::has_key(map, key));
+        registry.register_unary("keys", |map, _| map::keys(map));
+        registry.register_unary("values", |map, _| map::values(map));
+        registry.register_unary("entries", |map, _| map::entries(map));
+        registry.register_unary("size", |map, _| map::size(map));
+
+        // control flow
+        registry.register_unary("exec", |token, state| token.exec(state));
+        registry.register_unary("if", |token, state| token.if_exp(state));
+        registry.register_unary("map", |token, state| token.map(state));
+        registry.register_unary("each", |token, state| token.each(state));
+        registry.register_unary("times", |token, state| token.times(state));
+        registry.register("foldl", 2, |state| lists::fold(state, Fold::Left));
+        registry.register("foldr", 2, |state| lists::fold(state, Fold::Right));
+        registry.register("scanl", 2, |state| lists::fold(state, Fold::Scan));
+        registry.register("reduce", 1, |state| lists::fold(state, Fold::Reduce));
+        registry.register("foldWhile", 2, |state| lists::fold(state, Fold::While));
+        registry.register("filter", 1, |state| lists::apply(state, Collect::Filter));
+        registry.register("sortBy", 1, |state| lists::apply(state, Collect::SortBy));
+        registry.register("zipWith", 2, |state| lists::apply(state, Collect::ZipWith));
+        registry.register("find", 1, |state| lists::apply(state, Collect::Find));
+        registry.register("any", 1, |state| lists::apply(state, Collect::Any(false)));
+        registry.register("all", 1, |state| lists::apply(state, Collect::Any(true)));
+        registry.register("groupBy", 1, |state| lists::apply(state, Collect::GroupBy));
+        registry.register("partition", 1, |state| lists::apply(state, Collect::Partition));
+        registry.register("loop", 0, execute_loop);
+
+        // bindings and functions
+        registry.register("'", 0, |state| state.stack_add_unbound());
+        registry.register_binary(":=", |left, right, state| left.set_bind(right, state));
+        registry.register("let", 1, scope::define);
+        registry.register_unary("closure", scope::closure);
+        registry.register_binary("fun", |left, right, state| left.set_fun(right, state));
+
+        // IO and REPL commands
+        registry.register_unary("print", |token, state| token.print(state));
+        registry.register("read", 0, |state| state.read());
+        registry.register(":b", 0, |state| state.display(":b"));
+        registry.register(":f", 0, |state| state.display(":f"));
+        registry.register(":q", 0, |_| process::exit(0));
+        for name in ["print", "read", ":b", ":f"] {
+            registry.require(name, Capability::Io);
+        }
+        registry.require(":q", Capability::Exit);
+
+        // files and environment
+        registry.register_unary("readFile", |path, _| path.read_file());
+        registry.register_binary("writeFile", |contents, path, _| contents.write_file(path));
+        registry.register_unary("getEnv", |name, _| name.get_env());
+        registry.require("readFile", Capability::Files);
+        registry.require("writeFile", Capability::Files);
+        registry.require("getEnv", Capability::Env);
+
+        registry
+    }
+
+    /// Registers a word that works directly on the state.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `arity` - Number of operands the word takes from the stack. The word is only called
+    ///   if there are at least this many, and pops them itself
+    /// * `function` - The implementation of the word
+    ///
+    pub fn register<F>(&mut self, name: &str, arity: usize, function: F)
+        where F: Fn(&mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.builtins.insert(name.to_string(), Builtin { arity, function: Rc::new(function), capability: None });
+    }
+
+    /// Makes a word require a capability, so that it fails with `CapabilityDenied`
+    /// in states that do not allow it.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of a registered word
+    /// * `capability` - The capability the word needs
+    ///
+    /// # Returns
+    ///
+    /// `true` if the word is registered
+    ///
+    pub fn require(&mut self, name: &str, capability: Capability) -> bool {
+        match self.builtins.get_mut(name) {
+            Some(builtin) => {
+                builtin.capability = Some(capability);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Registers a word that takes one operand from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `function` - The implementation, called with the operand and the state
+    ///
+    pub fn register_unary<F>(&mut self, name: &str, function: F)
+        where F: Fn(Token, &mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.register(name, 1, move |state| {
+            let token = state.stack_pop()?;
+            function(token, state)
+        });
+    }
+
+    /// Registers a word that takes two operands from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `function` - The implementation, called with the deeper operand first, then the
+    ///   operand from the top of the stack, and the state
+    ///
+    pub fn register_binary<F>(&mut self, name: &str, function: F)
+        where F: Fn(Token, Token, &mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.register(name, 2, move |state| {
+            let right = state.stack_pop()?;
+            let left = state.stack_pop()?;
+            function(left, right, state)
+        });
+    }
+
+    /// Registers a word that takes three operands from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `function` - The implementation, called with the operands from the deepest to the
+    ///   top of the stack, and the state
+    ///
+    pub fn register_ternary<F>(&mut self, name: &str, function: F)
+        where F: Fn(Token, Token, Token, &mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.register(name, 3, move |state| {
+            let third = state.stack_pop()?;
+            let second = state.stack_pop()?;
+            let first = state.stack_pop()?;
+            function(first, second, third, state)
+        });
+    }
+
+    /// Looks up a word by name.
+    pub fn get(&self, name: &str) -> Option<&Builtin> {
+        self.builtins.get(name)
+    }
+
+    /// Checks whether a word with the given name is registered.
+    pub fn contains(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    /// Removes a word from the registry.
+    ///
+    /// # Returns
+    ///
+    /// `true` if the word was registered
+    ///
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.builtins.remove(name).is_some()
+    }
+}
@@ -0,0 +1,98 @@
+/// A kind of side effect that a program may be allowed to perform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// Console input and output: `print`, `read`, `:b` and `:f`
+    Io,
+    /// Terminating the process with `:q`
+    Exit,
+    /// Reading and writing files: `readFile` and `writeFile`
+    Files,
+    /// Reading environment variables: `getEnv`
+    Env,
+}
+
+impl Capability {
+    /// Every capability, in the order they are listed in the documentation
+    pub const ALL: [Capability; 4] = [Capability::Io, Capability::Exit, Capability::Files, Capability::Env];
+
+    /// Returns the name of the capability, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Io => "io",
+            Capability::Exit => "exit",
+            Capability::Files => "files",
+            Capability::Env => "env",
+        }
+    }
+
+    /// Looks up a capability by its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - One of `io`, `exit`, `files` or `env`
+    ///
+    pub fn from_name(name: &str) -> Option<Capability> {
+        Capability::ALL.into_iter().find(|capability| capability.name() == name)
+    }
+}
+
+/// The side effects a program may perform
+///
+/// Words that need a capability the state does not have fail with
+/// `ProgramError::CapabilityDenied`. By default programs may use the console and exit,
+/// but not touch files or environment variables, which have to be allowed explicitly.
+/// `Capabilities::pure` gives a sandbox for untrusted programs.
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    pub io: bool,
+    pub exit: bool,
+    pub files: bool,
+    pub env: bool,
+}
+
+impl Default for Capabilities {
+    /// Allows console input and output and exiting, and denies files and environment
+    /// variables.
+    fn default() -> Self {
+        Self { io: true, exit: true, files: false, env: false }
+    }
+}
+
+impl Capabilities {
+    /// Allows every side effect.
+    pub fn all() -> Self {
+        Self { io: true, exit: true, files: true, env: true }
+    }
+
+    /// Denies every side effect, so that a program can only compute a value.
+    pub fn pure() -> Self {
+        Self { io: false, exit: false, files: false, env: false }
+    }
+
+    /// Checks whether a capability is allowed.
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Io => self.io,
+            Capability::Exit => self.exit,
+            Capability::Files => self.files,
+            Capability::Env => self.env,
+        }
+    }
+
+    /// Allows or denies a capability.
+    ///
+    /// # Arguments
+    ///
+    /// * `capability` - The capability to change
+    /// * `allowed` - Whether programs may use it
+    ///
+    pub fn set(&mut self, capability: Capability, allowed: bool) {
+        match capability {
+            Capability::Io => self.io = allowed,
+            Capability::Exit => self.exit = allowed,
+            Capability::Files => self.files = allowed,
+            Capability::Env => self.env = allowed,
+        }
+    }
+}
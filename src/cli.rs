@@ -0,0 +1,205 @@
+use std::time::Duration;
+use bprog::{Capabilities, Capability, Division, LexerMode, Options, OutputFormat, Overflow, Prelude, Rounding};
+
+/// Usage text printed by `--help`
+pub const USAGE: &str = "\
+Usage: bprog [OPTIONS] [COMMAND]
+
+Commands:
+    (none)                  Run the program read from standard input
+    run <FILE> [ARGS...]    Run the program in FILE, binding ARGS to `args`
+    repl                    Start an interactive session
+    eval [-e|--] <CODE>     Run the program given as CODE, which may start with `-`
+    check [FILES...]        Report parse errors without running the programs
+    fmt [FILES...]          Print the programs with normalised indentation
+    test [PATHS...]         Run the test files in PATHS (default: tests)
+
+Options:
+    --prelude <FILE>        Load FILE instead of the bundled prelude
+    --no-prelude            Do not load a prelude
+    --lexer <words|chars>   Select the lexer (default: words)
+    --trace                 Print every instruction and the stack to stderr
+    --max-steps <N>         Stop with StepLimitExceeded after N instructions
+    --max-stack <N>         Stop with StackLimitExceeded above N values on the stack
+    --max-collection <N>    Stop with CollectionLimitExceeded for lists or strings
+                            longer than N elements or bytes
+    --timeout <MS>          Stop with TimeLimitExceeded after MS milliseconds
+    --overflow <promote|error>
+                            Promote integers that overflow to big integers, or stop
+                            with IntegerOverflow (default: promote)
+    --division <float|exact>
+                            Divide integers with `/` into floats or exact
+                            rationals (default: float)
+    --decimal-scale <N>     Round quotients of decimals to N decimals (default: 10)
+    --decimal-rounding <MODE>
+                            Round quotients of decimals down, up, floor, ceiling,
+                            half-up, half-down or half-even (default: half-even)
+    --sandbox               Deny every capability (io, exit, files and env)
+    --allow <CAPS>          Allow the comma-separated capabilities, such as files
+                            and env, which are denied by default
+    --deny <CAPS>           Deny the comma-separated capabilities
+    --output <text|json>    Format of the printed result (default: text)
+    -h, --help              Print this help
+    -V, --version           Print the version
+";
+
+/// Subcommand selected on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Run the program read from standard input
+    Stdin,
+    /// Run a program from a file with arguments
+    Run { path: String, args: Vec<String> },
+    /// Start the Read-Eval-Print Loop
+    Repl,
+    /// Run a program given on the command line
+    Eval(String),
+    /// Parse programs without running them
+    Check(Vec<String>),
+    /// Format programs
+    Fmt(Vec<String>),
+    /// Run test files
+    Test(Vec<String>),
+    /// Print the usage text
+    Help,
+    /// Print the version
+    Version,
+}
+
+/// Parses the command line arguments into a command and its options.
+///
+/// Options may appear before or after the subcommand, except after `run <FILE>`,
+/// where every remaining argument is passed on to the program.
+///
+/// # Arguments
+///
+/// * `args` - The command line arguments, excluding the program name
+///
+/// # Errors
+///
+/// Returns a description of the problem if the arguments are invalid
+///
+pub fn parse_args(args: &[String]) -> Result<(Command, Options), String> {
+    let mut options = Options::default();
+    let mut command: Option<String> = None;
+    let mut positional: Vec<String> = Vec::new();
+    let mut code: Option<String> = None;
+    // set by `--`, after which the arguments of `eval` are never options
+    let mut separated = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if separated {
+            positional.push(arg.clone());
+            continue
+        }
+        let mut value = |name: &str| iter.next().cloned().ok_or(format!("{} expects a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok((Command::Help, options)),
+            "-V" | "--version" => return Ok((Command::Version, options)),
+            "--prelude" => options.prelude = Prelude::File(value(arg)?),
+            "--no-prelude" => options.prelude = Prelude::Disabled,
+            "--trace" => options.trace = true,
+            "--lexer" => options.lexer_mode = match value(arg)?.as_str() {
+                "words" => LexerMode::Words,
+                "chars" => LexerMode::Characters,
+                _ => return Err("--lexer expects either words or chars".to_string())
+            },
+            "--output" => options.output = match value(arg)?.as_str() {
+                "text" => OutputFormat::Text,
+                "json" => OutputFormat::Json,
+                _ => return Err("--output expects either text or json".to_string())
+            },
+            "--overflow" => options.overflow = match value(arg)?.as_str() {
+                "promote" => Overflow::Promote,
+                "error" => Overflow::Error,
+                _ => return Err("--overflow expects either promote or error".to_string())
+            },
+            "--division" => options.division = match value(arg)?.as_str() {
+                "float" => Division::Float,
+                "exact" => Division::Exact,
+                _ => return Err("--division expects either float or exact".to_string())
+            },
+            "--decimal-scale" => options.decimals.scale = number(arg, value(arg)?)?,
+            "--decimal-rounding" => options.decimals.rounding = Rounding::from_name(value(arg)?.as_str())
+                .ok_or("--decimal-rounding expects one of down, up, floor, ceiling, half-up, half-down or half-even".to_string())?,
+            "--max-steps" => options.limits.max_steps = Some(number(arg, value(arg)?)?),
+            "--max-stack" => options.limits.max_stack_depth = Some(number(arg, value(arg)?)?),
+            "--max-collection" => options.limits.max_collection_size = Some(number(arg, value(arg)?)?),
+            "--timeout" => options.limits.timeout = Some(Duration::from_millis(number(arg, value(arg)?)?)),
+            "--sandbox" => options.capabilities = Capabilities::pure(),
+            "--allow" => for capability in capabilities(arg, value(arg)?)? {
+                options.capabilities.set(capability, true);
+            },
+            "--deny" => for capability in capabilities(arg, value(arg)?)? {
+                options.capabilities.set(capability, false);
+            },
+            "--" if command.as_deref() == Some("eval") => separated = true,
+            "-e" if command.as_deref() == Some("eval") => code = Some(value(arg)?),
+            // a program may start with a negative number, such as `-1 abs`
+            _ if command.as_deref() == Some("eval") && code.is_none() && positional.is_empty() => positional.push(arg.clone()),
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option {}", arg)),
+            _ if command.is_none() => {
+                if !["run", "repl", "eval", "check", "fmt", "test"].contains(&arg.as_str()) {
+                    return Err(format!("unknown command {}", arg))
+                }
+                command = Some(arg.clone());
+            },
+            _ if command.as_deref() == Some("run") => {
+                // everything after the file belongs to the bprog program
+                let args = iter.cloned().collect();
+                return Ok((Command::Run { path: arg.clone(), args }, options))
+            },
+            _ => positional.push(arg.clone())
+        }
+    }
+
+    let command = match command.as_deref() {
+        None if positional.is_empty() => Command::Stdin,
+        None => return Err(format!("unknown command {}", positional[0])),
+        Some("run") => return Err("run expects a file path".to_string()),
+        Some("repl") if positional.is_empty() => Command::Repl,
+        Some("repl") => return Err("repl does not take any arguments".to_string()),
+        Some("eval") => match (code, positional.len()) {
+            (Some(code), 0) => Command::Eval(code),
+            (None, 1) => Command::Eval(positional.remove(0)),
+            _ => return Err("eval expects exactly one program".to_string())
+        },
+        Some("check") => Command::Check(positional),
+        Some("fmt") => Command::Fmt(positional),
+        Some(_) if positional.is_empty() => Command::Test(vec!["tests".to_string()]),
+        Some(_) => Command::Test(positional),
+    };
+    Ok((command, options))
+}
+
+/// Parses the numeric value of an option.
+///
+/// # Arguments
+///
+/// * `name` - The name of the option, for the error message
+/// * `value` - The value given on the command line
+///
+/// # Errors
+///
+/// Returns a description of the problem if the value is not a non-negative integer
+///
+fn number<T: std::str::FromStr>(name: &str, value: String) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} expects a number", name))
+}
+
+/// Parses a comma-separated list of capabilities.
+///
+/// # Arguments
+///
+/// * `name` - The name of the option, for the error message
+/// * `value` - The value given on the command line
+///
+/// # Errors
+///
+/// Returns a description of the problem if a name is not a capability
+///
+fn capabilities(name: &str, value: String) -> Result<Vec<Capability>, String> {
+    value.split(',')
+        .map(|s| Capability::from_name(s.trim()).ok_or(format!("{} expects capabilities out of io, exit, files and env", name)))
+        .collect()
+}
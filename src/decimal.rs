@@ -0,0 +1,319 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Neg};
+use std::str::FromStr;
+use crate::bigint::BigInt;
+use crate::rational::Rational;
+
+/// How the result of a decimal division is rounded to its scale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// Towards zero
+    Down,
+    /// Away from zero
+    Up,
+    /// Towards negative infinity
+    Floor,
+    /// Towards positive infinity
+    Ceiling,
+    /// To the nearest value, with ties away from zero
+    HalfUp,
+    /// To the nearest value, with ties towards zero
+    HalfDown,
+    /// To the nearest value, with ties to the even neighbour, also known as banker's rounding
+    #[default]
+    HalfEven,
+}
+
+impl Rounding {
+    /// Every rounding mode, in the order they are listed in the documentation
+    pub const ALL: [Rounding; 7] = [Rounding::Down, Rounding::Up, Rounding::Floor, Rounding::Ceiling,
+                                    Rounding::HalfUp, Rounding::HalfDown, Rounding::HalfEven];
+
+    /// Returns the name of the rounding mode, as used on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rounding::Down => "down",
+            Rounding::Up => "up",
+            Rounding::Floor => "floor",
+            Rounding::Ceiling => "ceiling",
+            Rounding::HalfUp => "half-up",
+            Rounding::HalfDown => "half-down",
+            Rounding::HalfEven => "half-even",
+        }
+    }
+
+    /// Looks up a rounding mode by its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - One of `down`, `up`, `floor`, `ceiling`, `half-up`, `half-down` or `half-even`
+    ///
+    pub fn from_name(name: &str) -> Option<Rounding> {
+        Rounding::ALL.into_iter().find(|rounding| rounding.name() == name)
+    }
+}
+
+/// Scale and rounding mode of the quotient when dividing decimals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecimalContext {
+    /// Number of digits after the decimal point
+    pub scale: u32,
+    /// How the quotient is rounded to `scale` digits
+    pub rounding: Rounding,
+}
+
+impl Default for DecimalContext {
+    fn default() -> Self {
+        Self { scale: 10, rounding: Rounding::default() }
+    }
+}
+
+/// A decimal number with a fixed number of digits after the decimal point
+///
+/// The value is `unscaled / 10^scale`. The scale is kept as written, so that `12.50`
+/// keeps both of its decimals, while comparison and equality look at the value only.
+/// Addition, subtraction and multiplication are exact, and the result has as many
+/// decimals as needed. Division rounds to the scale of a `DecimalContext`.
+///
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    unscaled: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+    /// Creates the decimal `unscaled / 10^scale`.
+    pub fn new(unscaled: BigInt, scale: u32) -> Self {
+        Self { unscaled, scale }
+    }
+
+    /// Returns the number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Checks whether the value is zero.
+    pub fn is_zero(&self) -> bool {
+        self.unscaled.is_zero()
+    }
+
+    /// Returns `-1`, `0` or `1` depending on the sign of the value.
+    pub fn signum(&self) -> i128 {
+        self.unscaled.signum()
+    }
+
+    /// Returns the absolute value.
+    pub fn abs(self) -> Self {
+        Self { unscaled: self.unscaled.abs(), scale: self.scale }
+    }
+
+    /// Converts the value to the nearest `f64`.
+    pub fn to_f64(&self) -> f64 {
+        format!("{}e-{}", self.unscaled, self.scale).parse().unwrap()
+    }
+
+    /// Converts the value to an exact fraction.
+    pub fn to_rational(&self) -> Rational {
+        Rational::new(self.unscaled.clone(), power_of_ten(self.scale)).unwrap()
+    }
+
+    /// Raises the value to the power of `exponent`, which multiplies the scale by `exponent`.
+    ///
+    /// # Returns
+    ///
+    /// The power, or `None` if the scale of the result is too large, or its digits are,
+    /// as for `BigInt::checked_pow`.
+    ///
+    pub fn pow(&self, exponent: u32) -> Option<Self> {
+        Some(Self { unscaled: self.unscaled.checked_pow(exponent)?, scale: self.scale.checked_mul(exponent)? })
+    }
+
+    /// Divides the value by `other`, rounding the quotient according to `context`.
+    ///
+    /// # Returns
+    ///
+    /// The quotient with `context.scale` decimals, or `None` if `other` is zero.
+    ///
+    pub fn div(&self, other: &Decimal, context: DecimalContext) -> Option<Self> {
+        if other.is_zero() {
+            return None
+        }
+        // (x / 10^a) / (y / 10^b) * 10^s = x * 10^(b + s) / (y * 10^a)
+        let numerator = &self.unscaled * &power_of_ten(other.scale + context.scale);
+        let denominator = &other.unscaled * &power_of_ten(self.scale);
+        Some(Self { unscaled: divide_rounded(&numerator, &denominator, context.rounding), scale: context.scale })
+    }
+
+    /// Divides the value by `other`, rounding towards zero to a whole number.
+    ///
+    /// # Returns
+    ///
+    /// The integer quotient and the remainder, where the remainder has the sign of `self`
+    /// and the larger scale of the two operands, or `None` if `other` is zero.
+    ///
+    pub fn div_rem(&self, other: &Decimal) -> Option<(BigInt, Decimal)> {
+        if other.is_zero() {
+            return None
+        }
+        let (x, y, scale) = align(self, other);
+        let (quotient, remainder) = x.div_rem(&y);
+        Some((quotient, Decimal::new(remainder, scale)))
+    }
+}
+
+impl From<BigInt> for Decimal {
+    /// Converts an integer to a decimal without decimals.
+    fn from(value: BigInt) -> Self {
+        Self { unscaled: value, scale: 0 }
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ();
+
+    /// Parses a number with an optional sign and an optional decimal point, e.g. `-12.50`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(())` if the string is not a decimal number
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let digits = integer.trim_start_matches(['-', '+']).len() + fraction.len();
+        if digits == 0 || fraction.starts_with(['-', '+']) {
+            return Err(())
+        }
+        let unscaled = format!("{}{}", integer, fraction).parse::<BigInt>()?;
+        Ok(Self { unscaled, scale: fraction.len() as u32 })
+    }
+}
+
+impl fmt::Display for Decimal {
+    /// Formats the value with exactly `scale` decimals, e.g. `12.50`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.unscaled.clone().abs().to_string();
+        let digits = format!("{:0>width$}", digits, width = self.scale as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - self.scale as usize);
+        let sign = if self.unscaled.is_negative() { "-" } else { "" };
+        if fraction.is_empty() {
+            write!(f, "{}{}", sign, integer)
+        } else {
+            write!(f, "{}{}.{}", sign, integer, fraction)
+        }
+    }
+}
+
+impl PartialEq for Decimal {
+    /// Compares the values, so that e.g. `1.5` equals `1.50`.
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Ord for Decimal {
+    /// Compares two values numerically.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (x, y, _) = align(self, other);
+        x.cmp(&y)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    /// Returns the value with the opposite sign.
+    fn neg(self) -> Self::Output {
+        Self { unscaled: -self.unscaled, scale: self.scale }
+    }
+}
+
+impl Add for &Decimal {
+    type Output = Decimal;
+
+    /// Adds two values. The sum has the larger scale of the two.
+    fn add(self, other: &Decimal) -> Self::Output {
+        let (x, y, scale) = align(self, other);
+        Decimal::new(&x + &y, scale)
+    }
+}
+
+impl Sub for &Decimal {
+    type Output = Decimal;
+
+    /// Subtracts `other` from `self`. The difference has the larger scale of the two.
+    fn sub(self, other: &Decimal) -> Self::Output {
+        let (x, y, scale) = align(self, other);
+        Decimal::new(&x - &y, scale)
+    }
+}
+
+impl Mul for &Decimal {
+    type Output = Decimal;
+
+    /// Multiplies two values. The scale of the product is the sum of the scales.
+    fn mul(self, other: &Decimal) -> Self::Output {
+        Decimal::new(&self.unscaled * &other.unscaled, self.scale + other.scale)
+    }
+}
+
+/// Helper function that returns `10^exponent`
+fn power_of_ten(exponent: u32) -> BigInt {
+    BigInt::from(10).pow(exponent)
+}
+
+/// Helper function that brings two decimals to the same scale
+///
+/// # Returns
+///
+/// The unscaled values of both decimals at the larger of their scales, and that scale.
+///
+fn align(x: &Decimal, y: &Decimal) -> (BigInt, BigInt, u32) {
+    let scale = x.scale.max(y.scale);
+    let x_unscaled = &x.unscaled * &power_of_ten(scale - x.scale);
+    let y_unscaled = &y.unscaled * &power_of_ten(scale - y.scale);
+    (x_unscaled, y_unscaled, scale)
+}
+
+/// Helper function that divides two integers, rounding the quotient to a whole number
+///
+/// # Arguments
+///
+/// * `numerator` - The dividend
+/// * `denominator` - The divisor, which must not be zero
+/// * `rounding` - How to round the quotient
+///
+fn divide_rounded(numerator: &BigInt, denominator: &BigInt, rounding: Rounding) -> BigInt {
+    let (quotient, remainder) = numerator.div_rem(denominator);
+    if remainder.is_zero() {
+        return quotient
+    }
+    let sign = numerator.signum() * denominator.signum();
+    // compare the remainder with half of the divisor, as 2 * |remainder| against |divisor|
+    let half = (&remainder.clone().abs() * &BigInt::from(2)).cmp(&denominator.clone().abs());
+    let away_from_zero = match rounding {
+        Rounding::Down => false,
+        Rounding::Up => true,
+        Rounding::Floor => sign < 0,
+        Rounding::Ceiling => sign > 0,
+        Rounding::HalfUp => half != Ordering::Less,
+        Rounding::HalfDown => half == Ordering::Greater,
+        Rounding::HalfEven => match half {
+            Ordering::Equal => !quotient.div_rem(&BigInt::from(2)).1.is_zero(),
+            ordering => ordering == Ordering::Greater,
+        },
+    };
+    if away_from_zero {
+        &quotient + &BigInt::from(sign)
+    } else {
+        quotient
+    }
+}
@@ -0,0 +1,355 @@
+use crate::bigint::Overflow;
+use crate::builtins::Registry;
+use crate::capabilities::Capabilities;
+use crate::error::{Error, ProgramError};
+use crate::limits::Limits;
+use crate::interpreter::{execute_program, start_runtime};
+use crate::parser::parse_string_to_instructions;
+use crate::prelude::{load_prelude, Prelude};
+use crate::rational::Division;
+use crate::decimal::DecimalContext;
+use crate::state::State;
+use crate::streams::Streams;
+use crate::token::Token;
+use crate::Options;
+
+/// An interpreter that can be embedded in other Rust programs
+///
+/// The interpreter keeps its stack, bindings and functions between calls, so a host
+/// program can push arguments, evaluate bprog code on them and pop the results. A clone
+/// gets copies of them, and only shares the streams with the original.
+///
+/// ```
+/// use bprog::Interpreter;
+///
+/// let mut bprog = Interpreter::new();
+/// bprog.define_function("square", "dup *").unwrap();
+/// bprog.push(7);
+/// assert_eq!(bprog.eval("square").unwrap(), 49.into());
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    state: State,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    /// Creates an interpreter with the bundled prelude loaded.
+    pub fn new() -> Self {
+        Self::with_options(&Options::default()).expect("the bundled prelude is valid")
+    }
+
+    /// Creates an interpreter configured by the given options, and loads its prelude.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - The prelude, lexer and execution settings. The output format is ignored
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Prelude` if the prelude cannot be loaded
+    ///
+    pub fn with_options(options: &Options) -> Result<Self, Error> {
+        let mut state = options.new_state();
+        load_prelude(&options.prelude, &mut state)?;
+        Ok(Self { state })
+    }
+
+    /// Loads an additional prelude on top of the current definitions.
+    ///
+    /// # Arguments
+    ///
+    /// * `prelude` - The prelude to load
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Prelude` if the prelude cannot be loaded
+    ///
+    pub fn load_prelude(&mut self, prelude: &Prelude) -> Result<(), Error> {
+        let stack = std::mem::take(&mut self.state.stack);
+        let result = load_prelude(prelude, &mut self.state);
+        self.state.stack = stack;
+        Ok(result?)
+    }
+
+    /// Evaluates a program that should leave exactly one value on the stack,
+    /// and removes that value from the stack.
+    ///
+    /// The program starts with the values already on the stack, so `eval` can be
+    /// used to apply bprog code to values pushed by the host. The step limit and
+    /// timeout apply to every call separately.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source code of the program
+    ///
+    /// # Returns
+    ///
+    /// The value the program left on the stack
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Parser` if the program cannot be parsed, and `Error::Program` if it
+    /// fails or does not leave exactly one value. The stack is left as it was at the failure
+    ///
+    pub fn eval(&mut self, source: &str) -> Result<Token, Error> {
+        parse_string_to_instructions(source, &mut self.state)?;
+        self.state.start_clock();
+        match execute_program(&mut self.state) {
+            Ok(token) => {
+                self.state.stack.clear();
+                Ok(token)
+            },
+            Err(e) => {
+                self.state.instruction_set.clear();
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Executes a program and leaves its results on the stack, without requiring a single value.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The source code of the program
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Parser` if the program cannot be parsed, and `Error::Program` if it fails
+    ///
+    pub fn run(&mut self, source: &str) -> Result<(), Error> {
+        parse_string_to_instructions(source, &mut self.state)?;
+        self.state.start_clock();
+        start_runtime(&mut self.state).map_err(|e| {
+            self.state.instruction_set.clear();
+            e.into()
+        })
+    }
+
+    /// Replaces the resource limits that apply to `eval` and `run`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.state.limits = limits;
+    }
+
+    /// Returns the resource limits that apply to `eval` and `run`.
+    pub fn limits(&self) -> &Limits {
+        &self.state.limits
+    }
+
+    /// Selects what happens when integer arithmetic overflows an `Int`.
+    pub fn set_overflow(&mut self, overflow: Overflow) {
+        self.state.overflow = overflow;
+    }
+
+    /// Returns what happens when integer arithmetic overflows an `Int`.
+    pub fn overflow(&self) -> Overflow {
+        self.state.overflow
+    }
+
+    /// Selects whether `/` divides integers into floats or exact rationals.
+    pub fn set_division(&mut self, division: Division) {
+        self.state.division = division;
+    }
+
+    /// Returns whether `/` divides integers into floats or exact rationals.
+    pub fn division(&self) -> Division {
+        self.state.division
+    }
+
+    /// Replaces the scale and rounding mode of the quotient when dividing decimals.
+    pub fn set_decimal_context(&mut self, context: DecimalContext) {
+        self.state.decimals = context;
+    }
+
+    /// Returns the scale and rounding mode of the quotient when dividing decimals.
+    pub fn decimal_context(&self) -> DecimalContext {
+        self.state.decimals
+    }
+
+    /// Replaces the side effects that programs may perform.
+    ///
+    /// Use `Capabilities::pure()` to run untrusted programs without access to the console,
+    /// files, the environment or the process.
+    ///
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.state.capabilities = capabilities;
+    }
+
+    /// Returns the side effects that programs may perform.
+    pub fn capabilities(&self) -> Capabilities {
+        self.state.capabilities
+    }
+
+    /// Replaces the streams used by `print`, `read`, `:b`, `:f` and tracing.
+    ///
+    /// ```
+    /// use std::io::{self, Cursor};
+    /// use bprog::{Interpreter, SharedBuffer, Streams};
+    ///
+    /// let output = SharedBuffer::new();
+    /// let mut bprog = Interpreter::new();
+    /// bprog.set_streams(Streams::new(Cursor::new("world"), output.clone(), io::sink()));
+    /// bprog.run("read print").unwrap();
+    /// assert_eq!(output.contents(), "\"world\"\n");
+    /// ```
+    ///
+    pub fn set_streams(&mut self, streams: Streams) {
+        self.state.streams = streams;
+    }
+
+    /// Returns the streams used by `print`, `read`, `:b`, `:f` and tracing.
+    pub fn streams(&self) -> &Streams {
+        &self.state.streams
+    }
+
+    /// Pushes a value onto the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Anything that converts into a `Token`, such as integers, strings or vectors
+    ///
+    pub fn push<T: Into<Token>>(&mut self, value: T) {
+        self.state.stack.push(value.into());
+    }
+
+    /// Pops the value on top of the stack and converts it to a Rust type.
+    ///
+    /// # Returns
+    ///
+    /// The converted value, e.g. `i64`, `f64`, `bool`, `String`, `Vec<Token>` or `Token`
+    ///
+    /// # Errors
+    ///
+    /// Returns `StackEmpty` if the stack is empty, or the conversion error if the value
+    /// has a different type. In that case the value stays on the stack
+    ///
+    pub fn pop<T: TryFrom<Token>>(&mut self) -> Result<T, Error>
+        where ProgramError: From<T::Error> {
+        let token = self.state.stack.last().cloned().ok_or(ProgramError::StackEmpty)?;
+        let value = T::try_from(token).map_err(ProgramError::from)?;
+        self.state.stack.pop();
+        Ok(value)
+    }
+
+    /// Returns the value on top of the stack, if any.
+    pub fn peek(&self) -> Option<&Token> {
+        self.state.stack.last()
+    }
+
+    /// Returns the stack, with the top of the stack last.
+    pub fn stack(&self) -> &[Token] {
+        &self.state.stack
+    }
+
+    /// Removes all values from the stack.
+    pub fn clear_stack(&mut self) {
+        self.state.stack.clear();
+    }
+
+    /// Binds a value to a symbol, like `:=` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The symbol to bind
+    /// * `value` - Anything that converts into a `Token`
+    ///
+    pub fn set_binding<T: Into<Token>>(&mut self, name: &str, value: T) {
+        self.state.scopes.set_global(name, value.into());
+    }
+
+    /// Returns the value bound to a symbol, if any.
+    pub fn binding(&self, name: &str) -> Option<Token> {
+        self.state.scopes.global(name)
+    }
+
+    /// Defines a function, like `fun` does.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the function
+    /// * `body` - Source code of the function body, without the surrounding braces
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Parser` if the body cannot be parsed
+    ///
+    pub fn define_function(&mut self, name: &str, body: &str) -> Result<(), Error> {
+        let mut temp = State::new();
+        temp.lexer_mode = self.state.lexer_mode;
+        parse_string_to_instructions(body, &mut temp)?;
+        self.state.functions.insert(name.to_string(), Token::Block(temp.get_instructions().into()));
+        Ok(())
+    }
+
+    /// Returns the body of a function, if it is defined.
+    pub fn function(&self, name: &str) -> Option<&Token> {
+        self.state.functions.get(name)
+    }
+
+    /// Registers a native word that works directly on the state, like `Registry::register`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `arity` - Number of operands the word takes from the stack
+    /// * `function` - The implementation of the word, which pops its operands itself
+    ///
+    pub fn register<F>(&mut self, name: &str, arity: usize, function: F)
+        where F: Fn(&mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.builtins_mut().register(name, arity, function);
+    }
+
+    /// Registers a native word that takes one operand from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `function` - The implementation, called with the operand and the state
+    ///
+    pub fn register_unary<F>(&mut self, name: &str, function: F)
+        where F: Fn(Token, &mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.builtins_mut().register_unary(name, function);
+    }
+
+    /// Registers a native word that takes two operands from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `function` - The implementation, called with the deeper operand first, then the
+    ///   operand from the top of the stack, and the state
+    ///
+    pub fn register_binary<F>(&mut self, name: &str, function: F)
+        where F: Fn(Token, Token, &mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.builtins_mut().register_binary(name, function);
+    }
+
+    /// Registers a native word that takes three operands from the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the word
+    /// * `function` - The implementation, called with the operands from the deepest to the
+    ///   top of the stack, and the state
+    ///
+    pub fn register_ternary<F>(&mut self, name: &str, function: F)
+        where F: Fn(Token, Token, Token, &mut State) -> Result<Option<Token>, ProgramError> + 'static {
+        self.builtins_mut().register_ternary(name, function);
+    }
+
+    /// Returns the words implemented in Rust.
+    pub fn builtins(&self) -> &Registry {
+        self.state.builtins()
+    }
+
+    /// Returns the words implemented in Rust for modification, e.g. to remove a builtin.
+    pub fn builtins_mut(&mut self) -> &mut Registry {
+        self.state.builtins_mut()
+    }
+}
@@ -1,3 +1,7 @@
+use std::error;
+use std::fmt;
+use crate::parser::Span;
+
 /// Error types that may propagate during interpretation
 #[derive(Debug)]
 pub enum ProgramError {
@@ -11,17 +15,236 @@ pub enum ProgramError {
     ExpectedQuotation,
     ExpectedString,
     ExpectedList,
+    ExpectedMap,
+    ExpectedKey,
     ExpectedVariable,
     ExpectedSymbol,
     DivisionByZero,
     ProgramFinishedWithMultipleValues,
     NumberConversionError,
+    IntegerOverflow,
+    DomainError,
+    IndexOutOfRange,
+    KeyNotFound,
+    InvalidFormat,
+    InvalidRegex,
+    StepLimitExceeded,
+    StackLimitExceeded,
+    CollectionLimitExceeded,
+    TimeLimitExceeded,
+    Cancelled,
+    CapabilityDenied,
+    IoError,
+    /// Wraps any of the errors above with the operation and source location it occurred at
+    Located(Box<ProgramError>, Box<ErrorContext>),
+}
+
+/// Describes where a runtime error occurred
+#[derive(Debug)]
+pub struct ErrorContext {
+    /// Name of the operation that failed
+    pub operation: String,
+    /// Location of the operation in the source code
+    pub span: Span,
+    /// Types of the operands that were on top of the stack, deepest first
+    pub found: Vec<&'static str>,
+}
+
+impl ProgramError {
+    /// Returns the underlying error without any location information.
+    ///
+    /// # Returns
+    ///
+    /// The wrapped error if `self` is `Located`, otherwise `self`.
+    ///
+    pub fn kind(&self) -> &ProgramError {
+        match self {
+            ProgramError::Located(e, _) => e.kind(),
+            e => e
+        }
+    }
+
+    /// Annotates the error with the operation that raised it.
+    ///
+    /// Errors that already carry a location are left untouched, so that the innermost
+    /// operation is reported. The same goes for operations without a known location,
+    /// which leaves the annotation to an enclosing operation instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - Name of the operation that failed
+    /// * `span` - Location of the operation in the source code
+    /// * `found` - Types of the operands found on the stack
+    ///
+    /// # Returns
+    ///
+    /// The annotated error.
+    ///
+    pub fn locate(self, operation: &str, span: &Span, found: Vec<&'static str>) -> ProgramError {
+        match self {
+            ProgramError::Located(_, _) => self,
+            _ if !span.is_known() => self,
+            e => {
+                let context = ErrorContext { operation: operation.to_string(), span: span.clone(), found };
+                ProgramError::Located(Box::new(e), Box::new(context))
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProgramError {
+    /// Formats the error for display.
+    ///
+    /// Located errors name the operation, the location, the operand types and show
+    /// the offending line of source code.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgramError::Located(e, context) => {
+                write!(f, "{} in `{}` at {}", e, context.operation, context.span)?;
+                if !context.found.is_empty() {
+                    write!(f, " (found {})", context.found.join(", "))?;
+                }
+                write!(f, "{}", context.span.snippet())
+            },
+            e => write!(f, "{:?}", e)
+        }
+    }
+}
+
+impl error::Error for ProgramError {
+    /// Returns the underlying error of a located error.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ProgramError::Located(e, _) => Some(e.as_ref()),
+            _ => None
+        }
+    }
 }
 
 /// Error types that may propagate during parsing
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ParserError {
-    IncompleteString,
-    IncompleteList,
-    IncompleteQuotation
+    IncompleteString(Span),
+    IncompleteList(Span),
+    IncompleteQuotation(Span),
+    IncompleteMap(Span),
+    InvalidMapKey(Span),
+    InvalidEscape(Span),
+    IncompleteComment(Span),
+}
+
+impl ParserError {
+    /// Returns the location of the offending part of the source code.
+    pub fn span(&self) -> &Span {
+        match self {
+            ParserError::IncompleteString(span) => span,
+            ParserError::IncompleteList(span) => span,
+            ParserError::IncompleteQuotation(span) => span,
+            ParserError::IncompleteMap(span) => span,
+            ParserError::InvalidMapKey(span) => span,
+            ParserError::InvalidEscape(span) => span,
+            ParserError::IncompleteComment(span) => span,
+        }
+    }
+
+    /// Returns the name of the error without its location.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParserError::IncompleteString(_) => "IncompleteString",
+            ParserError::IncompleteList(_) => "IncompleteList",
+            ParserError::IncompleteQuotation(_) => "IncompleteQuotation",
+            ParserError::IncompleteMap(_) => "IncompleteMap",
+            ParserError::InvalidMapKey(_) => "InvalidMapKey",
+            ParserError::InvalidEscape(_) => "InvalidEscape",
+            ParserError::IncompleteComment(_) => "IncompleteComment",
+        }
+    }
+}
+
+impl fmt::Display for ParserError {
+    /// Formats the error for display, including the offending line of source code.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}{}", self.name(), self.span(), self.span().snippet())
+    }
+}
+
+impl error::Error for ParserError {}
+
+/// Error types that may propagate while loading the prelude
+#[derive(Debug)]
+pub enum PreludeError {
+    Unreadable(String),
+    Parser(ParserError),
+    Program(ProgramError),
+}
+
+impl fmt::Display for PreludeError {
+    /// Formats the prelude error for display, naming the stage that failed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreludeError::Unreadable(path) => write!(f, "could not read prelude file {}", path),
+            PreludeError::Parser(e) => write!(f, "prelude: {}", e),
+            PreludeError::Program(e) => write!(f, "prelude: {}", e),
+        }
+    }
+}
+
+impl error::Error for PreludeError {
+    /// Returns the error that made the prelude fail to parse or execute.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            PreludeError::Unreadable(_) => None,
+            PreludeError::Parser(e) => Some(e),
+            PreludeError::Program(e) => Some(e),
+        }
+    }
+}
+
+/// Any error returned by the `Interpreter` API
+#[derive(Debug)]
+pub enum Error {
+    Parser(ParserError),
+    Program(ProgramError),
+    Prelude(PreludeError),
+}
+
+impl fmt::Display for Error {
+    /// Formats the wrapped error for display.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parser(e) => write!(f, "{}", e),
+            Error::Program(e) => write!(f, "{}", e),
+            Error::Prelude(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    /// Returns the wrapped error.
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Parser(e) => Some(e),
+            Error::Program(e) => Some(e),
+            Error::Prelude(e) => Some(e),
+        }
+    }
+}
+
+impl From<ParserError> for Error {
+    fn from(e: ParserError) -> Self {
+        Error::Parser(e)
+    }
+}
+
+impl From<ProgramError> for Error {
+    fn from(e: ProgramError) -> Self {
+        Error::Program(e)
+    }
+}
+
+impl From<PreludeError> for Error {
+    fn from(e: PreludeError) -> Self {
+        Error::Prelude(e)
+    }
 }
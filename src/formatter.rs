@@ -0,0 +1,97 @@
+use crate::error::ParserError;
+use crate::parser::{lex, lex_characters, parse_string_to_instructions, LexerMode};
+use crate::state::State;
+
+/// Number of spaces per level of nesting
+const INDENT: usize = 4;
+
+/// Formats bprog source code
+///
+/// Every line is indented by `INDENT` spaces per list or quotation it is nested in,
+/// where a line starting with closing brackets or braces is indented like the line
+/// that opened them. Trailing whitespace and repeated blank lines are removed. Words,
+/// comments and strings are left untouched, and lines that are part of a string
+/// spanning several lines are kept exactly as they are.
+///
+/// # Arguments
+///
+/// * `input` - The source code to be formatted
+/// * `lexer_mode` - How the source code is split into words
+///
+/// # Errors
+///
+/// Returns ParseError if the source code cannot be parsed
+///
+pub fn format_source(input: &str, lexer_mode: LexerMode) -> Result<String, ParserError> {
+    // make sure that the brackets and braces are balanced before relying on them
+    let mut state = State::new();
+    state.lexer_mode = lexer_mode;
+    parse_string_to_instructions(input, &mut state)?;
+    let words = match lexer_mode {
+        LexerMode::Words => lex(input)?,
+        LexerMode::Characters => lex_characters(input)?,
+    };
+
+    let lines: Vec<&str> = input.split('\n').map(|line| line.trim_end_matches('\r')).collect();
+    // change in nesting on every line, and the number of closing words it starts with
+    let mut nesting = vec![0i64; lines.len()];
+    let mut leading_closers = vec![0i64; lines.len()];
+    let mut verbatim = vec![false; lines.len()];
+    let mut in_string = false;
+    let mut previous_line = None;
+    let mut at_line_start = false;
+    for word in words.iter() {
+        let line = word.span.line - 1;
+        if previous_line != Some(line) {
+            at_line_start = true;
+            previous_line = Some(line);
+        }
+        if word.literal.is_some() {
+            // lines after the first one of a multiline string are kept verbatim
+            let continued = word.text.matches('\n').count();
+            verbatim[line + 1..=line + continued].fill(true);
+        }
+        let change = match word.text {
+            _ if word.literal.is_some() => 0,
+            "\"" => {
+                in_string = !in_string;
+                0
+            },
+            _ if in_string => 0,
+            "[" | "{" | "#{" => 1,
+            "]" | "}" => -1,
+            _ => 0
+        };
+        if change < 0 && at_line_start {
+            leading_closers[line] += 1;
+        } else {
+            at_line_start = false;
+        }
+        nesting[line] += change;
+    }
+
+    let mut result = String::new();
+    let mut depth: i64 = 0;
+    let mut previous_blank = true;
+    for (i, line) in lines.iter().enumerate() {
+        if verbatim[i] {
+            result.push_str(line);
+            result.push('\n');
+            previous_blank = false;
+        } else if line.trim().is_empty() {
+            if !previous_blank {
+                result.push('\n');
+            }
+            previous_blank = true;
+        } else {
+            let indent = (depth - leading_closers[i]).max(0) as usize * INDENT;
+            result.push_str(format!("{}{}\n", " ".repeat(indent), line.trim()).as_str());
+            previous_blank = false;
+        }
+        depth += nesting[i];
+    }
+    while result.ends_with("\n\n") {
+        result.pop();
+    }
+    Ok(result)
+}
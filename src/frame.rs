@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+use std::rc::Rc;
+use crate::error::{ErrorContext, ProgramError};
+use crate::lists::{Apply, Folding};
+use crate::state::State;
+use crate::token::Token;
+
+/// An entry of the call stack of a state
+///
+/// Running a block pushes a frame that points into the block instead of copying its
+/// instructions, and words that call quotations, such as `map`, wait in a frame of
+/// their own for the results. The interpreter works off the innermost frame, so the
+/// depth of a program is limited by memory rather than by the native stack.
+///
+#[derive(Debug, Clone)]
+pub(crate) enum Frame {
+    /// A block being run, with the index of its next instruction
+    Code {
+        body: Rc<[Token]>,
+        ip: usize,
+        /// Whether the block entered a local scope, which is left when it is done
+        scoped: bool,
+        /// Whether the block is a program of its own, whose words may not take
+        /// instructions from the frames below it
+        boundary: bool,
+    },
+    /// A word waiting for a quotation it called
+    Task {
+        task: Box<Task>,
+        /// The stack of the word, if the quotation runs on a stack of its own
+        saved: Option<Vec<Token>>,
+        /// The word that started the task, to locate its errors
+        origin: Option<Rc<ErrorContext>>,
+    },
+}
+
+/// What a task does after it is resumed
+#[derive(Debug, Clone)]
+pub(crate) enum Step {
+    /// Calls a quotation with arguments, on a stack of its own if `isolated`, in which
+    /// case the task is resumed with the single value the quotation leaves
+    Call { quotation: Token, arguments: Vec<Token>, isolated: bool },
+    /// Finishes the word with an optional result
+    Done(Option<Token>),
+}
+
+/// A word that calls quotations, written as a state machine that the interpreter
+/// resumes every time a quotation returns
+#[derive(Debug, Clone)]
+pub(crate) enum Task {
+    /// `map` and the list words that call a quotation once per element
+    Apply(Apply),
+    /// `foldl` and the other folds
+    Fold(Folding),
+    /// `each`, which runs the quotation on the stack of the program
+    Each { quotation: Token, items: VecDeque<Token> },
+    /// `times`
+    Times { quotation: Token, count: i128 },
+    /// `loop`, which alternates between the condition and the body
+    Loop { condition: Token, body: Token, testing: bool },
+}
+
+impl Task {
+    /// Resumes the task, first with no result and then after every quotation it called.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state the task runs in
+    /// * `result` - The value left by an isolated quotation
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of the word, such as `ExpectedBool` if a condition does not
+    /// give a boolean
+    ///
+    pub(crate) fn resume(&mut self, state: &mut State, result: Option<Token>) -> Result<Step, ProgramError> {
+        match self {
+            Task::Apply(apply) => apply.resume(result),
+            Task::Fold(folding) => folding.resume(result),
+            Task::Each { quotation, items } => Ok(match items.pop_front() {
+                Some(item) => Step::Call { quotation: quotation.clone(), arguments: vec![item], isolated: false },
+                None => Step::Done(None)
+            }),
+            Task::Times { quotation, count } => Ok(if *count > 0 {
+                *count -= 1;
+                Step::Call { quotation: quotation.clone(), arguments: Vec::new(), isolated: false }
+            } else {
+                Step::Done(None)
+            }),
+            Task::Loop { condition, body, testing } => {
+                if !*testing {
+                    *testing = true;
+                    return Ok(Step::Call { quotation: condition.clone(), arguments: Vec::new(), isolated: false })
+                }
+                match state.stack_pop()? {
+                    Token::Bool(true) => Ok(Step::Done(None)),
+                    Token::Bool(false) => {
+                        *testing = false;
+                        Ok(Step::Call { quotation: body.clone(), arguments: Vec::new(), isolated: false })
+                    },
+                    _ => Err(ProgramError::ExpectedBool)
+                }
+            }
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use std::cmp::Ordering;
+use crate::builtins::Builtin;
 use crate::state::State;
-use std::collections::VecDeque;
-use std::process;
+use std::mem;
+use std::rc::Rc;
 use crate::token::Token;
-use crate::error::ProgramError;
+use crate::map::Map;
+use crate::error::{ErrorContext, ProgramError};
+use crate::frame::{Frame, Step, Task};
+use crate::scope;
 
 /// Entry point for the interpreter
 ///
@@ -32,10 +35,15 @@ pub fn execute_program(state: &mut State) -> Result<Token, ProgramError> {
 
 /// Execution component of the interpreter
 ///
-/// As long as there are instructions left, it will pop them off one by one
-/// and determine what kind it is. If it is a operation, it will send it to
-/// the dispatcher. If it is a list, then it will try to replace bound items and
-/// then add to the stack. For all other items, they are immediately placed on the stack
+/// The instruction set is run as a block of its own. As long as there are frames left,
+/// it will take the next instruction of the innermost block and determine what kind it
+/// is. If it is a operation, it will send it to the dispatcher. If it is a list or map,
+/// then it will try to replace bound items and then add to the stack. For all other
+/// items, they are immediately placed on the stack. Words that called a quotation are
+/// resumed once the quotation is done. Errors raised by an operation are annotated with
+/// its name, location and operands, and leave the frames of the program behind.
+/// Every instruction is checked against the resource limits of the state, and is written
+/// to the error stream if tracing is enabled
 ///
 /// # Arguments
 ///
@@ -46,163 +54,167 @@ pub fn execute_program(state: &mut State) -> Result<Token, ProgramError> {
 /// Returns ProgramError if the operation cannot complete
 ///
 pub fn start_runtime(state: &mut State) -> Result<(), ProgramError> {
-    while !state.instruction_set.is_empty() {
-        if let Some(item) = state.instruction_set.pop_front() {
-            match item {
-                Token::Symbol(op) => match dispatch_operation(state, op.as_str())? {
-                    Some(token) => state.stack_push(token),
-                    None => continue
+    let base = state.frames.len();
+    let body: Rc<[Token]> = state.instruction_set.drain(..).collect();
+    state.frames.push(Frame::Code { body, ip: 0, scoped: false, boundary: true });
+    let result = run_frames(state, base);
+    if result.is_err() {
+        unwind(state, base);
+    }
+    result
+}
+
+/// Helper function that runs the frames above `base` until they are all done
+fn run_frames(state: &mut State, base: usize) -> Result<(), ProgramError> {
+    while state.frames.len() > base {
+        let Some(item) = state.next_instruction() else {
+            match state.frames.last() {
+                Some(Frame::Task { .. }) => {
+                    state.count_step()?;
+                    resume(state)?;
                 },
-                Token::List(_) => {
-                    match replace_items_with_bindings(state, item.clone())? {
-                        Some(token) => state.stack_push(token),
-                        None => continue
+                _ => state.pop_frame()
+            }
+            continue
+        };
+        state.count_step()?;
+        if state.trace {
+            let line = format!("trace : {:<16} stack : {}", item.to_string(), state);
+            state.streams.write_error_line(line.as_str()).map_err(|_| ProgramError::IoError)?;
+        }
+        match item {
+            Token::Symbol(op, span) => {
+                // user definitions shadow builtins of the same name
+                let builtin = if state.is_bound(op.as_str()) {
+                    None
+                } else {
+                    state.builtins.get(op.as_str()).cloned()
+                };
+                let found = state.stack_types(builtin.as_ref().map_or(0, |b| b.arity));
+                let result = dispatch_operation(state, builtin, op.as_str()).and_then(|token| {
+                    if let Some(token) = token {
+                        state.stack_push(token);
                     }
-                },
-                _ => state.stack_push(item)
+                    state.check_size()
+                });
+                match result {
+                    Err(e) => return Err(e.locate(op.as_str(), &span, found)),
+                    // a word that started a task is blamed for the errors of the task
+                    Ok(()) => if let Some(Frame::Task { origin: origin @ None, .. }) = state.frames.last_mut() {
+                        *origin = Some(Rc::new(ErrorContext { operation: op, span, found }));
+                    }
+                }
+            },
+            Token::List(_) | Token::Map(_) => {
+                if let Some(token) = replace_items_with_bindings(state, item.clone())? {
+                    state.stack_push(token);
+                    state.check_size()?;
+                }
+            },
+            // blocks that refer to local variables keep the variables they see
+            Token::Block(body) if state.scopes.is_local() => {
+                state.stack_push(scope::enclose(body, state));
+                state.check_size()?;
+            },
+            _ => {
+                state.stack_push(item);
+                state.check_size()?;
             }
         }
     }
     Ok(())
 }
 
-/// Primary dispatcher for operations
+/// Helper function that resumes the task in the innermost frame
 ///
-/// It will forward the operation depending on how many arguments it takes. Some operations
-/// interact entirely with the stack and takes no arguments.
-///
-/// # Arguments
-///
-/// * `state` - The stack, instruction list, list of functions and bindings
-/// * `op` - The operation in string form to be executed
-///
-/// # Errors
-///
-/// Returns ProgramError if the operation cannot complete
-///
-fn dispatch_operation(state: &mut State, op: &str) -> Result<Option<Token>, ProgramError> {
-    let unary_ops = ["not", "length", "parseInteger", "parseFloat", "print", "words", "pop",
-                     "empty", "head", "tail", "exec", "map", "each", "times", "if", "print"];
-    let binary_ops = ["+", "-", "*", "/", "<", ">", "==", "&&", "||", "div", "append", "cons",
-                      "foldl", ":=", "fun"];
-
-    if unary_ops.contains(&op) {
-        dispatch_unary_operation(state, op)
-    } else if binary_ops.contains(&op) {
-        dispatch_binary_operation(state, op)
-    } else {
-        dispatch_nullary_operation(state, op)
-    }
-}
-
-/// Secondary dispatcher for binary operations
-///
-/// This function takes two arguments from the stack before passing to the respective function
-///
-/// # Arguments
-///
-/// * `state` - The stack, instruction list, list of functions and bindings
-/// * `op` - The operation in string form to be executed
+/// If the task called a quotation on a stack of its own, the single value the
+/// quotation left is passed to the task and the stack of the task is restored.
+/// The task then either calls its next quotation or finishes with its result.
 ///
 /// # Errors
 ///
-/// Returns ProgramError if the operation cannot complete
-///
-fn dispatch_binary_operation(state: &mut State, op: &str) -> Result<Option<Token>, ProgramError> {
-    let right = state.stack_pop()?;
-    let left = state.stack_pop()?;
-
-    match op {
-        "+" => left + right,
-        "-" => left - right,
-        "*" => left * right,
-        "/" => left / right,
-        "div" => left.int_div(right),
-        "<" => left.compare(right, Ordering::Less),
-        ">" => left.compare(right, Ordering::Greater),
-        "==" => left.compare(right, Ordering::Equal),
-        "&&" => left.and(right),
-        "||" => left.or(right),
-        "cons" => right.cons(left),
-        "append" => left.append(right),
-        "foldl" => left.foldl(right, state),
-        ":=" => left.set_bind(right, state),
-        "fun" => left.set_fun(right, state),
-        _ => Err(ProgramError::UnknownSymbol)
+/// Returns the errors of the task, located at the word that started it, and
+/// `StackEmpty` or `ProgramFinishedWithMultipleValues` unless the quotation left
+/// exactly one value
+///
+fn resume(state: &mut State) -> Result<(), ProgramError> {
+    let Some(Frame::Task { mut task, saved, origin }) = state.frames.pop() else {
+        return Ok(())
+    };
+    let locate = |e: ProgramError| match &origin {
+        Some(context) => e.locate(context.operation.as_str(), &context.span, context.found.clone()),
+        None => e
+    };
+    let result = match saved {
+        Some(saved) => {
+            let values = mem::replace(&mut state.stack, saved);
+            match values.len() {
+                0 => return Err(locate(ProgramError::StackEmpty)),
+                1 => values.into_iter().next(),
+                _ => return Err(locate(ProgramError::ProgramFinishedWithMultipleValues))
+            }
+        },
+        None => None
+    };
+    match task.resume(state, result).map_err(locate)? {
+        Step::Call { quotation, arguments, isolated } => {
+            let saved = isolated.then(|| mem::take(&mut state.stack));
+            state.stack.extend(arguments);
+            state.frames.push(Frame::Task { task, saved, origin: origin.clone() });
+            state.call(quotation).map_err(locate)
+        },
+        Step::Done(token) => {
+            if let Some(token) = token {
+                state.stack_push(token);
+                state.check_size().map_err(locate)?;
+            }
+            Ok(())
+        }
     }
-
 }
 
-/// Secondary dispatcher for unary operations
-///
-/// This function takes one argument from the stack before passing to the respective function
-///
-/// # Arguments
-///
-/// * `state` - The stack, instruction list, list of functions and bindings
-/// * `op` - The operation in string form to be executed
-///
-/// # Errors
-///
-/// Returns ProgramError if the operation cannot complete
-///
-fn dispatch_unary_operation(state: &mut State, op: &str) -> Result<Option<Token>, ProgramError> {
-    let left = state.stack_pop()?;
-    match op {
-        "pop" => Ok(None),
-        "not" => left.not(),
-        "length" => left.len(),
-        "parseInteger" => left.parse_int(),
-        "parseFloat" => left.parse_float(),
-        "print" => left.print(),
-        "words" => left.words(),
-        "empty" => left.empty(),
-        "head" => left.head(),
-        "tail" => left.tail(),
-        "if" => left.if_exp(state),
-        "map" => left.map(state),
-        "exec" => left.exec(state),
-        "each" => left.each(state),
-        "times" => left.times(state),
-        _ => Err(ProgramError::UnknownSymbol)
+/// Helper function that removes the frames above `base` after an error, leaving their
+/// local scopes and giving back the stack of any word that was waiting for a quotation
+fn unwind(state: &mut State, base: usize) {
+    while state.frames.len() > base {
+        if let Some(Frame::Task { saved: Some(saved), .. }) = state.frames.last_mut() {
+            state.stack = mem::take(saved);
+        }
+        state.pop_frame();
     }
 }
 
-/// Secondary dispatcher for nullary operations
+/// Dispatcher for operations
 ///
-/// This function takes zero arguments from the stack before passing to the respective function
+/// Builtins are executed directly. Any other symbol is resolved to the function or
+/// binding it names, or pushed onto the stack if it is unknown.
 ///
 /// # Arguments
 ///
 /// * `state` - The stack, instruction list, list of functions and bindings
+/// * `builtin` - The builtin registered under the name of the operation, if any
 /// * `op` - The operation in string form to be executed
 ///
 /// # Errors
 ///
 /// Returns ProgramError if the operation cannot complete
 ///
-fn dispatch_nullary_operation(state: &mut State, op: &str) -> Result<Option<Token>, ProgramError> {
-    match op {
-        "swap" => state.stack_swap(),
-        "dup" =>  state.stack_dup(),
-        "'" => state.stack_add_unbound(),
-        "read" => state.read(),
-        ":b" => state.display(op),
-        ":f" => state.display(op),
-        ":q" => process::exit(0),
-        "loop" => execute_loop(state),
-        x => state.resolve_symbol(x, true),
+fn dispatch_operation(state: &mut State, builtin: Option<Builtin>, op: &str) -> Result<Option<Token>, ProgramError> {
+    match builtin {
+        Some(builtin) => builtin.call(state),
+        None => state.resolve_symbol(op, true)
     }
 }
 
-/// Transforms a list by replacing any known bindings
+/// Transforms a list, or the values of a map, by replacing any known bindings
 ///
-/// If no known bindings are found, no transformation is made to the item
+/// If no known bindings are found, no transformation is made to the item. Blocks in
+/// the list or map become closures inside a local scope, like blocks on their own.
 ///
 /// # Arguments
 ///
 /// * `state` - The stack, instruction list, list of functions and bindings
-/// * `t` - The list token
+/// * `t` - The list or map token
 ///
 /// # Errors
 ///
@@ -214,21 +226,36 @@ fn replace_items_with_bindings(state: &mut State, t: Token) -> Result<Option<Tok
             let mut updated_list = Vec::<Token>::new();
             for item in items {
                 let token = match item {
-                    Token::Symbol(op) => state.resolve_symbol(op.as_str(), true)?.unwrap(),
-                    Token::List(_) => replace_items_with_bindings(state, item)?.unwrap(),
+                    Token::Symbol(op, _) => state.resolve_symbol(op.as_str(), false)?.unwrap(),
+                    Token::Block(body) if state.scopes.is_local() => scope::enclose(body, state),
+                    Token::List(_) | Token::Map(_) => replace_items_with_bindings(state, item)?.unwrap(),
                     _ => item
                 };
                 updated_list.push(token);
             }
             Ok(Some(Token::List(updated_list)))
         },
+        Token::Map(entries) => {
+            let mut updated_map = Map::new();
+            for (key, value) in entries {
+                let value = match value {
+                    Token::Symbol(op, _) => state.resolve_symbol(op.as_str(), false)?.unwrap(),
+                    Token::Block(body) if state.scopes.is_local() => scope::enclose(body, state),
+                    Token::List(_) | Token::Map(_) => replace_items_with_bindings(state, value)?.unwrap(),
+                    _ => value
+                };
+                updated_map.insert(key, value)?;
+            }
+            Ok(Some(Token::Map(updated_map)))
+        },
         _ => Err(ProgramError::ExpectedList)
     }
 }
 
 /// Execute Conditional loops for the program.
 ///
-/// The condition is taken from the instruction list, not the stack
+/// The condition and the body are taken from the instruction list, not the stack, and
+/// run on the stack of the program until the condition gives `True`
 ///
 /// # Arguments
 ///
@@ -238,36 +265,13 @@ fn replace_items_with_bindings(state: &mut State, t: Token) -> Result<Option<Tok
 ///
 /// Returns ProgramError if the operation cannot complete
 ///
-fn execute_loop(state: &mut State) -> Result<Option<Token>, ProgramError> {
-    let break_condition = state.instruction_pop(true)?;
-    let block = state.instruction_pop(true)?;
-    let break_eval = vec![break_condition.clone(), Token::Symbol("exec".to_string())];
-    let code_block = vec![block.clone(), Token::Symbol("exec".to_string())];
-
-    match break_condition {
-        Token::Block(_) => {
-            let mut temp_state = state.clone();
-            temp_state.instruction_set = VecDeque::from(break_eval.clone());
-
-            loop {
-                start_runtime(&mut temp_state)?;
-                match temp_state.stack_pop()? {
-                    Token::Bool(true) => {
-                        // take the resulting stack and return
-                        state.stack = temp_state.stack.clone();
-                        return Ok(None)
-                    },
-                    Token::Bool(false) => {
-                        // run the code block and then evaluate again
-                        let mut both: Vec<Token> = vec![];
-                        both.extend(code_block.clone());
-                        both.extend(break_eval.clone());
-                        temp_state.instruction_set = VecDeque::from(both);
-                        continue
-                    },
-                    _ => return Err(ProgramError::ExpectedBool)
-                }
-            }
+pub(crate) fn execute_loop(state: &mut State) -> Result<Option<Token>, ProgramError> {
+    let condition = state.instruction_pop(false)?;
+    let body = state.instruction_pop(false)?;
+    match condition {
+        Token::Block(_) | Token::Closure(_) => {
+            state.start_task(Task::Loop { condition, body, testing: false });
+            Ok(None)
         },
         _ => Err(ProgramError::ExpectedQuotation)
     }
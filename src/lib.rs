@@ -3,84 +3,511 @@ mod interpreter;
 mod token;
 mod state;
 mod error;
+mod prelude;
+mod formatter;
+mod testing;
+mod embed;
+mod builtins;
+mod limits;
+mod capabilities;
+mod streams;
+mod bigint;
+mod math;
+mod rational;
+mod decimal;
+mod strings;
+mod template;
+mod regex;
+mod map;
+mod lists;
+mod scope;
+mod frame;
 
-use state::State;
+pub use embed::Interpreter;
+pub use builtins::{Builtin, NativeFn, Registry};
+pub use state::State;
+pub use limits::{CancelFlag, Limits};
+pub use capabilities::{Capabilities, Capability};
+pub use streams::{Input, Output, SharedBuffer, Streams};
+pub use prelude::Prelude;
+pub use parser::{LexerMode, Span};
+pub use token::Token;
+pub use bigint::{BigInt, Overflow};
+pub use rational::{Division, Rational};
+pub use decimal::{Decimal, DecimalContext, Rounding};
+pub use map::Map;
+pub use scope::Closure;
+pub use error::{Error, ErrorContext, ParserError, PreludeError, ProgramError};
+use prelude::load_prelude;
 use interpreter::execute_program;
-use std::io::{self, BufRead, Write};
-use std::process;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
 use parser::parse_string_to_instructions;
 
+/// Output format for the value a program leaves on the stack
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// The compact notation used by the interpreter, e.g. `[1,2,3]`
+    #[default]
+    Text,
+    /// JSON, for consumption by other programs
+    Json,
+}
+
+/// Settings that control how programs are parsed and executed
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The prelude to load before the program
+    pub prelude: Prelude,
+    /// How the program is split into words
+    pub lexer_mode: LexerMode,
+    /// Print every instruction and the stack to stderr while executing
+    pub trace: bool,
+    /// Resource limits for the program
+    pub limits: Limits,
+    /// What happens when integer arithmetic overflows an `Int`
+    pub overflow: Overflow,
+    /// What `/` gives when dividing two integers
+    pub division: Division,
+    /// Scale and rounding mode of the quotient when dividing decimals
+    pub decimals: DecimalContext,
+    /// Side effects the program may perform
+    pub capabilities: Capabilities,
+    /// Format of the printed result
+    pub output: OutputFormat,
+}
+
+impl Options {
+    /// Creates a new `State` configured according to the options. The prelude is not loaded.
+    fn new_state(&self) -> State {
+        let mut state = State::new();
+        state.lexer_mode = self.lexer_mode;
+        state.trace = self.trace;
+        state.limits = self.limits.clone();
+        state.overflow = self.overflow;
+        state.division = self.division;
+        state.decimals = self.decimals;
+        state.capabilities = self.capabilities;
+        state
+    }
+
+    /// Formats a result according to the output format.
+    fn format_result(&self, token: &Token) -> String {
+        match self.output {
+            OutputFormat::Text => token.to_string(),
+            OutputFormat::Json => token.to_json(),
+        }
+    }
+}
+
 /// `repl_mode` starts a Read-Eval-Print Loop (REPL) that reads input lines, parses them as
 /// instructions, and executes the instructions using a `State` object. After each execution,
 /// it prints the current stack state. If an error occurs during execution, it prints the stack
 /// state and a warning message. If there's an error in parsing the input string to instructions,
 /// it prints the stack state, an error message, and clears the instruction set.
 ///
-pub fn repl_mode() {
-    let mut state = State::new();
+/// # Arguments
+///
+/// * `options` - The prelude, lexer and execution settings
+///
+pub fn repl_mode(options: &Options) {
+    let mut state = options.new_state();
+    // `read` asks for its input interactively
+    state.streams.prompt = Some("input".to_string());
+    if let Err(e) = load_prelude(&options.prelude, &mut state) {
+        println!("error : {}", e);
+    }
 
     loop {
         match parse_string_to_instructions(read_input("bprog").as_str(), &mut state) {
             // if successful, execute the tokens and print the result
             Ok(_) => {
+                state.start_clock();
                 match execute_program(&mut state) {
                     Ok(_) => println!("stack : {}", state),
-                    Err(e) => println!("stack : {}\nwarn  : {:?}", state, e)
+                    Err(e) => println!("stack : {}\nwarn  : {}", state, e)
                 }
             },
             // if unsuccessful, clear the instructions and print the error
             Err(e) => {
-                println!("stack : {}\nerror : {:?}", state, e);
+                println!("stack : {}\nerror : {}", state, e);
                 state.instruction_set.clear();
             }
         }
     }
 }
 
-/// `normal_mode` reads input lines from standard input, parses them as instructions,
+/// Process exit status of the interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// The program finished with a single value on the stack
+    Success,
+    /// The program or prelude could not be read, or the command line was invalid
+    Failure,
+    /// The program could not be parsed
+    ParseError,
+    /// The program failed during execution, or finished with an empty stack
+    RuntimeError,
+    /// The program finished with more than one value on the stack
+    MultipleValues,
+    /// At least one case in a test file did not produce the expected value
+    TestsFailed,
+}
+
+impl ExitStatus {
+    /// Returns the code that the process should exit with.
+    pub fn code(self) -> i32 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::Failure => 1,
+            ExitStatus::ParseError => 2,
+            ExitStatus::RuntimeError => 3,
+            ExitStatus::MultipleValues => 4,
+            ExitStatus::TestsFailed => 5,
+        }
+    }
+}
+
+/// `normal_mode` reads the entire standard input, parses it as instructions,
 /// and executes the instructions using a `State` object. If the execution is successful,
-/// it prints the result. If an error occurs during execution, it prints the error message.
-/// If there's an error in parsing the input string to instructions, it prints the error
-/// and exits the program with a status code of 1.
+/// it prints the result. Otherwise the error is printed to standard error.
 ///
+/// # Arguments
 ///
-pub fn normal_mode() {
-    let stdin = io::stdin();
-    let mut state = State::new();
-    for line in stdin.lock().lines() {
-        if let Err(e) = parse_string_to_instructions(line.unwrap().as_str(), &mut state) {
-            println!("{:?}", e);
-            process::exit(1);
+/// * `options` - The prelude, lexer and execution settings
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+pub fn normal_mode(options: &Options) -> ExitStatus {
+    match read_source(None) {
+        Ok(input) => run_source(input.as_str(), options, &mut options.new_state()),
+        Err(status) => status
+    }
+}
+
+/// `run_mode` reads a program from a file and executes it like `normal_mode`.
+///
+/// The remaining command line arguments are made available to the program as a
+/// list of strings bound to the symbol `args`.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file containing the program
+/// * `args` - Arguments passed on to the program
+/// * `options` - The prelude, lexer and execution settings
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+pub fn run_mode(path: &str, args: &[String], options: &Options) -> ExitStatus {
+    let input = match read_source(Some(path)) {
+        Ok(input) => input,
+        Err(status) => return status
+    };
+    let mut state = options.new_state();
+    let args = args.iter().map(|arg| Token::String(arg.clone())).collect();
+    state.scopes.set_global("args", Token::List(args));
+    run_source(input.as_str(), options, &mut state)
+}
+
+/// `eval_mode` executes a program given directly as a string, like `normal_mode`.
+///
+/// # Arguments
+///
+/// * `code` - The source code of the program
+/// * `options` - The prelude, lexer and execution settings
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+pub fn eval_mode(code: &str, options: &Options) -> ExitStatus {
+    run_source(code, options, &mut options.new_state())
+}
+
+/// `check_mode` parses programs without executing them, and reports any parse errors.
+///
+/// # Arguments
+///
+/// * `paths` - Files to check. Standard input is checked if there are none
+/// * `options` - The lexer settings
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+pub fn check_mode(paths: &[String], options: &Options) -> ExitStatus {
+    let mut status = ExitStatus::Success;
+    for path in sources(paths) {
+        let input = match read_source(path) {
+            Ok(input) => input,
+            Err(e) => return e
+        };
+        if let Err(e) = parse_string_to_instructions(input.as_str(), &mut options.new_state()) {
+            eprintln!("{}: {}", path.unwrap_or("stdin"), e);
+            status = ExitStatus::ParseError;
+        }
+    }
+    status
+}
+
+/// `fmt_mode` prints programs with normalised indentation to standard output.
+///
+/// Lines are indented by four spaces for every list or quotation they are nested in,
+/// and trailing whitespace and repeated blank lines are removed. Comments and strings
+/// are kept as they are.
+///
+/// # Arguments
+///
+/// * `paths` - Files to format. Standard input is formatted if there are none
+/// * `options` - The lexer settings
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+pub fn fmt_mode(paths: &[String], options: &Options) -> ExitStatus {
+    for path in sources(paths) {
+        let input = match read_source(path) {
+            Ok(input) => input,
+            Err(e) => return e
+        };
+        match formatter::format_source(input.as_str(), options.lexer_mode) {
+            Ok(formatted) => print!("{}", formatted),
+            Err(e) => {
+                eprintln!("{}: {}", path.unwrap_or("stdin"), e);
+                return ExitStatus::ParseError
+            }
+        }
+    }
+    ExitStatus::Success
+}
+
+/// `test_mode` runs bprog test files and reports which cases passed.
+///
+/// A test file is an ordinary bprog program, split into cases by comments of the form
+/// `# expect: <value>`. Every case runs on its own, and its result must match the
+/// expected value, or the name of the expected error. Directories are searched for
+/// files ending in `.bprog`.
+///
+/// # Arguments
+///
+/// * `paths` - Test files or directories containing them
+/// * `options` - The prelude, lexer and execution settings
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+pub fn test_mode(paths: &[String], options: &Options) -> ExitStatus {
+    let files = match testing::collect_test_files(paths) {
+        Ok(files) => files,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitStatus::Failure
+        }
+    };
+    let (mut passed, mut failed) = (0, 0);
+    for file in files {
+        let input = match read_source(file.to_str()) {
+            Ok(input) => input,
+            Err(e) => return e
+        };
+        for case in testing::parse_test_cases(input.as_str()) {
+            let mut state = options.new_state();
+            let result = match load_prelude(&options.prelude, &mut state) {
+                Ok(_) => testing::run_test_case(&case, &mut state),
+                Err(e) => e.to_string()
+            };
+            if result == case.expected {
+                println!("test {}:{} ... ok", file.display(), case.line);
+                passed += 1;
+            } else {
+                println!("test {}:{} ... FAILED\n    expected: {}\n    found   : {}",
+                         file.display(), case.line, case.expected, result);
+                failed += 1;
+            }
+        }
+    }
+    let summary = if failed == 0 { "ok" } else { "FAILED" };
+    println!("\ntest result: {}. {} passed; {} failed", summary, passed, failed);
+    if failed == 0 { ExitStatus::Success } else { ExitStatus::TestsFailed }
+}
+
+/// Lists the files a mode should read, where `None` stands for standard input.
+///
+/// # Arguments
+///
+/// * `paths` - The file paths given on the command line
+///
+fn sources(paths: &[String]) -> Vec<Option<&str>> {
+    if paths.is_empty() {
+        vec![None]
+    } else {
+        paths.iter().map(|path| Some(path.as_str())).collect()
+    }
+}
+
+/// Reads a program from a file, or from standard input if no path is given.
+///
+/// # Arguments
+///
+/// * `path` - Path to the file containing the program
+///
+/// # Errors
+///
+/// Prints the problem to standard error and returns `ExitStatus::Failure`
+/// if the source cannot be read
+///
+fn read_source(path: Option<&str>) -> Result<String, ExitStatus> {
+    let mut input = String::new();
+    let result = match path {
+        Some(path) => fs::read_to_string(path).map(|s| input = s),
+        None => io::stdin().lock().read_to_string(&mut input).map(|_| ())
+    };
+    match result {
+        Ok(_) => Ok(input),
+        Err(e) => {
+            eprintln!("could not read {}: {}", path.unwrap_or("stdin"), e);
+            Err(ExitStatus::Failure)
         }
     }
-    match execute_program(&mut state) {
-        Ok(token) => println!("{}", token),
-        Err(e) => println!("{:?}", e)
+}
+
+/// Loads the prelude, then parses and executes a program. The resulting value is
+/// printed to standard output, and any error to standard error.
+///
+/// # Arguments
+///
+/// * `input` - The source code of the program
+/// * `options` - The prelude and output settings
+/// * `state` - The state to run the program in
+///
+/// # Returns
+///
+/// The exit status the process should terminate with
+///
+fn run_source(input: &str, options: &Options, state: &mut State) -> ExitStatus {
+    if let Err(e) = load_prelude(&options.prelude, state) {
+        eprintln!("{}", e);
+        return ExitStatus::Failure
+    }
+    if let Err(e) = parse_string_to_instructions(input, state) {
+        eprintln!("{}", e);
+        return ExitStatus::ParseError
+    }
+    state.start_clock();
+    match execute_program(state) {
+        Ok(token) => {
+            println!("{}", options.format_result(&token));
+            ExitStatus::Success
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            match e.kind() {
+                ProgramError::ProgramFinishedWithMultipleValues => ExitStatus::MultipleValues,
+                _ => ExitStatus::RuntimeError
+            }
+        }
     }
 }
 
 /// Utility function used for integration testing
 ///
 /// Any tests in `/tests/tests.rs` will pass through here. It takes a string,
-/// parses it, executes it, and evaluates it against the test
+/// parses it, executes it, and evaluates it against the test. The bundled
+/// prelude is loaded first, just like when running the interpreter
 ///
 /// # Arguments
 ///
 /// * `input` - input string to be parsed and executed
 ///
 pub fn t(input: &str) -> String {
+    run_test(input, LexerMode::Words)
+}
+
+/// Utility function used for integration testing of the character lexer
+///
+/// Works like `t`, but splits the input with `LexerMode::Characters`
+///
+/// # Arguments
+///
+/// * `input` - input string to be parsed and executed
+///
+pub fn t_chars(input: &str) -> String {
+    run_test(input, LexerMode::Characters)
+}
+
+/// Parses and executes a test program with the given lexer mode
+///
+/// # Arguments
+///
+/// * `input` - input string to be parsed and executed
+/// * `lexer_mode` - How the input is split into words
+///
+fn run_test(input: &str, lexer_mode: LexerMode) -> String {
     let mut state = State::new();
+    state.lexer_mode = lexer_mode;
+    if let Err(e) = load_prelude(&Prelude::Bundled, &mut state) {
+        panic!("{}", e)
+    }
     match parse_string_to_instructions(input, &mut state) {
         Ok(_) => {
             match execute_program(&mut state) {
                 Ok(r) => format!("{}", r),
-                Err(e) => format!("{:?}", e)
+                Err(e) => format!("{:?}", e.kind())
             }},
         Err(e) => panic!("{:?}", e)
     }
 
 }
 
+/// Utility function used for integration testing of error messages
+///
+/// Works like `t`, but returns the full error report, including the location
+/// and source snippet, of a program that fails to parse or execute
+///
+/// # Arguments
+///
+/// * `input` - input string to be parsed and executed
+///
+pub fn t_err(input: &str) -> String {
+    let mut state = State::new();
+    if let Err(e) = load_prelude(&Prelude::Bundled, &mut state) {
+        panic!("{}", e)
+    }
+    match parse_string_to_instructions(input, &mut state) {
+        Ok(_) => match execute_program(&mut state) {
+            Ok(r) => panic!("program succeeded with {}", r),
+            Err(e) => format!("{}", e)
+        },
+        Err(e) => format!("{}", e)
+    }
+}
+
+/// Utility function used for integration testing of interpreter settings
+///
+/// Works like `t`, but evaluates the input with an `Interpreter` that is configured
+/// first, and gives the kind of a program error without its location
+///
+/// # Arguments
+///
+/// * `configure` - A function that changes the settings of the interpreter
+/// * `input` - input string to be parsed and executed
+///
+pub fn run_with(configure: impl FnOnce(&mut Interpreter), input: &str) -> String {
+    let mut bprog = Interpreter::new();
+    configure(&mut bprog);
+    match bprog.eval(input) {
+        Ok(token) => token.to_string(),
+        Err(Error::Program(e)) => format!("{:?}", e.kind()),
+        Err(e) => e.to_string()
+    }
+}
+
 /// Reads a line of input from the user and returns it as a `String`.
 ///
 /// This function prints a prompt to the user, reads a line of input from the user,
@@ -0,0 +1,53 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Resource limits for running untrusted programs
+///
+/// Every limit is disabled by default. A program that exceeds one of them stops with
+/// the corresponding `ProgramError`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    /// Maximum number of instructions a program may execute, see `StepLimitExceeded`
+    pub max_steps: Option<u64>,
+    /// Maximum number of values on the stack, see `StackLimitExceeded`
+    pub max_stack_depth: Option<usize>,
+    /// Maximum number of elements in a list or quotation, or bytes in a string,
+    /// see `CollectionLimitExceeded`
+    pub max_collection_size: Option<usize>,
+    /// Maximum wall-clock time a program may run, see `TimeLimitExceeded`
+    pub timeout: Option<Duration>,
+    /// Stops the program with `Cancelled` once set, e.g. from another thread
+    pub cancel: Option<CancelFlag>,
+}
+
+/// A flag that cancels a running program when set
+///
+/// Clones share the same flag, so a clone can be handed to another thread while the
+/// program runs.
+///
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    /// Creates a flag that is not set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the flag, which stops any program that watches it at its next instruction.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Clears the flag, so that programs can run again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Checks whether the flag is set.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
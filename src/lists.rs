@@ -0,0 +1,531 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::iter::once;
+use crate::error::ProgramError;
+use crate::frame::{Step, Task};
+use crate::map::Map;
+use crate::state::State;
+use crate::token::{rt, Token};
+
+/// Takes the quotation of a word
+///
+/// The quotation is the instruction after the word, as for `map`, and never the top of
+/// the stack, which may itself hold a block. It may be a block or closure, or a symbol
+/// such as `+` or the name of a function.
+///
+/// # Errors
+///
+/// Returns `InstructionListEmpty` if there is no quotation, and `ExpectedQuotation`
+/// if it is neither a block nor a symbol
+///
+fn quotation(state: &mut State) -> Result<Token, ProgramError> {
+    match state.instruction_pop(false)? {
+        quotation @ (Token::Block(_) | Token::Closure(_) | Token::Symbol(_, _)) => Ok(quotation),
+        _ => Err(ProgramError::ExpectedQuotation)
+    }
+}
+
+/// The list words that call a quotation once per element, which all share the
+/// implementation of `apply`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Collect {
+    /// `list map q`, gives the list of the results
+    Map,
+    /// `list filter q`, keeps the elements for which the quotation gives `True`
+    Filter,
+    /// `list sortBy q`, sorts the elements by the keys the quotation gives, stably
+    SortBy,
+    /// `left right zipWith q`, combines the elements of two lists pairwise, and is as
+    /// long as the shorter list
+    ZipWith,
+    /// `list find q`, gives a list with the first element for which the quotation gives
+    /// `True`, or an empty list if there is none
+    Find,
+    /// `list any q`, or `list all q` when set, stops at the first element that decides
+    /// the outcome
+    Any(bool),
+    /// `list groupBy q`, gives a map from every key the quotation gives to the list of
+    /// elements with that key, in their original order
+    GroupBy,
+    /// `list partition q`, gives a list of the elements for which the quotation gives
+    /// `True` and a list of the rest, both in their original order
+    Partition,
+}
+
+/// A list word that calls a quotation once per element, resumed after every call
+///
+/// Every call runs on a stack of its own, with the element as its only argument, and
+/// must leave exactly one value.
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Apply {
+    collect: Collect,
+    quotation: Token,
+    items: VecDeque<Token>,
+    /// The element the quotation was last called with
+    current: Option<Token>,
+    /// The value the quotation gave for every element so far, with the element
+    results: Vec<(Token, Token)>,
+}
+
+impl Apply {
+    /// Creates the word for a list of elements, or of `[x y]` pairs for `ZipWith`.
+    pub(crate) fn new(collect: Collect, quotation: Token, items: Vec<Token>) -> Self {
+        Self { collect, quotation, items: items.into(), current: None, results: Vec::new() }
+    }
+
+    /// Takes the value the quotation gave for the current element, if any, and calls
+    /// the quotation with the next one.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedBool` if a predicate does not give a boolean, `ExpectedKey` if
+    /// a key of `GroupBy` is not a string, integer or boolean, and the errors of `sort`
+    /// if the keys of `SortBy` cannot be compared
+    ///
+    pub(crate) fn resume(&mut self, result: Option<Token>) -> Result<Step, ProgramError> {
+        if let (Some(result), Some(item)) = (result, self.current.take()) {
+            let result = match self.collect {
+                Collect::Filter | Collect::Find | Collect::Any(_) | Collect::Partition => {
+                    Token::Bool(bool::try_from(result)?)
+                },
+                _ => result
+            };
+            match (self.collect, &result) {
+                (Collect::Find, Token::Bool(true)) => return Ok(Step::Done(Some(Token::List(vec![item])))),
+                (Collect::Any(every), Token::Bool(x)) if *x != every => return Ok(Step::Done(Some(Token::Bool(!every)))),
+                _ => self.results.push((result, item))
+            }
+        }
+        let Some(item) = self.items.pop_front() else {
+            return self.finish().map(Step::Done)
+        };
+        let arguments = match (self.collect, &item) {
+            (Collect::ZipWith, Token::List(pair)) => pair.clone(),
+            _ => vec![item.clone()]
+        };
+        self.current = Some(item);
+        Ok(Step::Call { quotation: self.quotation.clone(), arguments, isolated: true })
+    }
+
+    /// Helper function that builds the result once every element is done
+    fn finish(&mut self) -> Result<Option<Token>, ProgramError> {
+        let mut results = std::mem::take(&mut self.results);
+        let matching = |results: Vec<(Token, Token)>| results.into_iter()
+            .filter(|(result, _)| *result == Token::Bool(true))
+            .map(|(_, item)| item)
+            .collect();
+        match self.collect {
+            Collect::Map | Collect::ZipWith => rt(Token::List(results.into_iter().map(|(result, _)| result).collect())),
+            Collect::Filter => rt(Token::List(matching(results))),
+            Collect::SortBy => {
+                let mut error = None;
+                results.sort_by(|(x, _), (y, _)| compare(x, y).unwrap_or_else(|e| {
+                    error.get_or_insert(e);
+                    Ordering::Equal
+                }));
+                match error {
+                    Some(e) => Err(e),
+                    None => rt(Token::List(results.into_iter().map(|(_, item)| item).collect()))
+                }
+            },
+            Collect::Find => rt(Token::List(Vec::new())),
+            Collect::Any(every) => rt(Token::Bool(every)),
+            Collect::GroupBy => {
+                let mut groups: Map = Map::new();
+                for (key, item) in results {
+                    let mut group = match groups.remove(&key) {
+                        Some(group) => list(group)?,
+                        None => Vec::new()
+                    };
+                    group.push(item);
+                    groups.insert(key, Token::List(group))?;
+                }
+                rt(Token::Map(groups))
+            },
+            Collect::Partition => {
+                let (matching, rest): (Vec<_>, Vec<_>) = results.into_iter()
+                    .partition(|(result, _)| *result == Token::Bool(true));
+                let items = |results: Vec<(Token, Token)>| Token::List(results.into_iter().map(|(_, item)| item).collect());
+                rt(Token::List(vec![items(matching), items(rest)]))
+            }
+        }
+    }
+}
+
+/// Calls a quotation once for every element of a list, and combines the results
+///
+/// The quotation is taken with `quotation`, and the word is resumed by the
+/// interpreter after every call rather than running the calls itself.
+///
+/// # Arguments
+///
+/// * `state` - The state whose stack holds the list, or the two lists for `ZipWith`
+/// * `collect` - The word
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if an operand is not a list, and `ExpectedQuotation` if the
+/// quotation is neither a block nor a symbol
+///
+pub(crate) fn apply(state: &mut State, collect: Collect) -> Result<Option<Token>, ProgramError> {
+    let quotation = quotation(state)?;
+    let items = match collect {
+        Collect::ZipWith => {
+            let right = list(state.stack_pop()?)?;
+            let left = list(state.stack_pop()?)?;
+            left.into_iter().zip(right).map(|(x, y)| Token::List(vec![x, y])).collect()
+        },
+        _ => list(state.stack_pop()?)?
+    };
+    state.start_task(Task::Apply(Apply::new(collect, quotation, items)));
+    Ok(None)
+}
+
+/// The kinds of fold, which all share the implementation of `fold`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Fold {
+    /// `list initial foldl q`, calls `acc item q` from the first element to the last
+    Left,
+    /// `list initial foldr q`, calls `item acc q` from the last element to the first
+    Right,
+    /// `list initial scanl q`, like `Left` but gives every accumulator along the way
+    Scan,
+    /// `list reduce q`, like `Left` with the first element as the initial accumulator
+    Reduce,
+    /// `list initial foldWhile c q`, like `Left` but stops as soon as the condition `c`
+    /// gives `False` for the accumulator
+    While,
+}
+
+/// A fold in progress, resumed after every call of the quotation or the condition
+#[derive(Debug, Clone)]
+pub(crate) struct Folding {
+    kind: Fold,
+    condition: Option<Token>,
+    quotation: Token,
+    items: VecDeque<Token>,
+    accumulator: Option<Token>,
+    scanned: Vec<Token>,
+    /// Whether the condition was called last, rather than the quotation
+    testing: bool,
+}
+
+impl Folding {
+    /// Takes the value of the last call, if any, and calls the condition or the
+    /// quotation next.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedBool` if the condition does not give a boolean
+    ///
+    pub(crate) fn resume(&mut self, result: Option<Token>) -> Result<Step, ProgramError> {
+        match result {
+            Some(result) if self.testing => {
+                self.testing = false;
+                if !bool::try_from(result)? {
+                    self.items.clear();
+                }
+            },
+            result => {
+                if let Some(result) = result {
+                    if self.kind == Fold::Scan {
+                        self.scanned.push(result.clone());
+                    }
+                    self.accumulator = Some(result);
+                }
+                if let (Some(condition), false) = (&self.condition, self.items.is_empty()) {
+                    self.testing = true;
+                    let arguments = self.accumulator.clone().into_iter().collect();
+                    return Ok(Step::Call { quotation: condition.clone(), arguments, isolated: true })
+                }
+            }
+        }
+        let Some(item) = self.items.pop_front() else {
+            return Ok(Step::Done(match self.kind {
+                Fold::Scan => Some(Token::List(std::mem::take(&mut self.scanned))),
+                _ => self.accumulator.take()
+            }))
+        };
+        let accumulator = self.accumulator.take().into_iter();
+        let arguments = match self.kind {
+            Fold::Right => once(item).chain(accumulator).collect(),
+            _ => accumulator.chain(once(item)).collect(),
+        };
+        Ok(Step::Call { quotation: self.quotation.clone(), arguments, isolated: true })
+    }
+}
+
+/// Folds a list into a single value with the quotation
+///
+/// The accumulator may be a value of any type. The quotation is taken from the
+/// instructions after the word, and for `Fold::While` it is preceded by the condition.
+///
+/// # Arguments
+///
+/// * `state` - The state whose stack holds the list and the initial accumulator
+/// * `kind` - The kind of fold
+///
+/// # Returns
+///
+/// The final accumulator, or for `Fold::Scan` the list of the initial accumulator
+/// followed by the accumulator after every element.
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the list operand is not a list, `ExpectedQuotation` if
+/// the quotation is neither a block nor a symbol, `ExpectedEnumerable` when reducing an
+/// empty list, and `ExpectedBool` if the condition does not give a boolean
+///
+pub(crate) fn fold(state: &mut State, kind: Fold) -> Result<Option<Token>, ProgramError> {
+    let condition = match kind {
+        Fold::While => Some(quotation(state)?),
+        _ => None
+    };
+    let quotation = quotation(state)?;
+    let (mut items, accumulator) = match kind {
+        Fold::Reduce => {
+            let mut items = VecDeque::from(list(state.stack_pop()?)?);
+            match items.pop_front() {
+                Some(first) => (items, first),
+                None => return Err(ProgramError::ExpectedEnumerable)
+            }
+        },
+        _ => {
+            let initial = state.stack_pop()?;
+            (VecDeque::from(list(state.stack_pop()?)?), initial)
+        }
+    };
+    if kind == Fold::Right {
+        items.make_contiguous().reverse();
+    }
+    let scanned = vec![accumulator.clone()];
+    let folding = Folding { kind, condition, quotation, items, accumulator: Some(accumulator), scanned, testing: false };
+    state.start_task(Task::Fold(folding));
+    Ok(None)
+}
+
+/// Reverses a list
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the operand is not a list
+///
+pub(crate) fn reverse(token: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::List(list(token)?.into_iter().rev().collect()))
+}
+
+/// Sorts a list in ascending order
+///
+/// The sort is stable. Numbers of different types are compared by value, and lists
+/// are compared element by element.
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the operand is not a list, and `ExpectedBoolOrNumber` or
+/// `NumberConversionError` if two elements cannot be compared
+///
+pub(crate) fn sort(token: Token) -> Result<Option<Token>, ProgramError> {
+    let mut items = list(token)?;
+    let mut error = None;
+    items.sort_by(|x, y| compare(x, y).unwrap_or_else(|e| {
+        error.get_or_insert(e);
+        Ordering::Equal
+    }));
+    match error {
+        Some(e) => Err(e),
+        None => rt(Token::List(items))
+    }
+}
+
+/// Pairs up the elements of two lists into `[x y]` lists
+///
+/// The result is as long as the shorter list.
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if either operand is not a list
+///
+pub(crate) fn zip(left: Token, right: Token) -> Result<Option<Token>, ProgramError> {
+    let pairs = list(left)?.into_iter().zip(list(right)?).map(|(x, y)| Token::List(vec![x, y]));
+    rt(Token::List(pairs.collect()))
+}
+
+/// The largest list `range` creates when no collection limit is set
+pub(crate) const MAX_RANGE: usize = 10_000_000;
+
+/// Returns the integers from `start` up to, but not including, `end`
+///
+/// # Arguments
+///
+/// * `start` - The first integer
+/// * `end` - The integer after the last one
+/// * `max` - The largest number of elements allowed, or `None` for `MAX_RANGE`
+///
+/// # Errors
+///
+/// Returns `ExpectedNumber` or `NumberConversionError` if the operands are not integers,
+/// and `CollectionLimitExceeded` if the list would be longer than `max`, which is
+/// checked before the list is allocated
+///
+pub(crate) fn range(start: Token, end: Token, max: Option<usize>) -> Result<Option<Token>, ProgramError> {
+    let (start, end) = (i128::try_from(start)?, i128::try_from(end)?);
+    let length = end.saturating_sub(start).max(0);
+    if length > max.unwrap_or(MAX_RANGE) as i128 {
+        return Err(ProgramError::CollectionLimitExceeded)
+    }
+    rt(Token::List((start..end).map(Token::Int).collect()))
+}
+
+/// Returns the first `n` elements of a list, or the whole list if it is shorter
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the first operand is not a list, and `IndexOutOfRange`
+/// if `n` is negative
+///
+pub(crate) fn take(token: Token, n: Token) -> Result<Option<Token>, ProgramError> {
+    let n = index(n)?;
+    rt(Token::List(list(token)?.into_iter().take(n).collect()))
+}
+
+/// Returns a list without its first `n` elements
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the first operand is not a list, and `IndexOutOfRange`
+/// if `n` is negative
+///
+pub(crate) fn drop(token: Token, n: Token) -> Result<Option<Token>, ProgramError> {
+    let n = index(n)?;
+    rt(Token::List(list(token)?.into_iter().skip(n).collect()))
+}
+
+/// Returns the element of a list at an index, counting from zero
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the first operand is not a list, and `IndexOutOfRange`
+/// if there is no element at the index
+///
+pub(crate) fn nth(token: Token, n: Token) -> Result<Option<Token>, ProgramError> {
+    let n = index(n)?;
+    list(token)?.into_iter().nth(n).map_or(Err(ProgramError::IndexOutOfRange), rt)
+}
+
+/// Returns the last element of a list
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the operand is not a list, and `ExpectedEnumerable` if
+/// it is empty, like `head`
+///
+pub(crate) fn last(token: Token) -> Result<Option<Token>, ProgramError> {
+    list(token)?.pop().map_or(Err(ProgramError::ExpectedEnumerable), rt)
+}
+
+/// Splices the elements of nested lists into the list, one level deep
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the operand is not a list
+///
+pub(crate) fn flatten(token: Token) -> Result<Option<Token>, ProgramError> {
+    let mut result = Vec::new();
+    for item in list(token)? {
+        match item {
+            Token::List(items) => result.extend(items),
+            item => result.push(item),
+        }
+    }
+    rt(Token::List(result))
+}
+
+/// Removes repeated elements from a list, keeping the first occurrence of each
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the operand is not a list
+///
+pub(crate) fn unique(token: Token) -> Result<Option<Token>, ProgramError> {
+    let mut result: Vec<Token> = Vec::new();
+    for item in list(token)? {
+        if !result.contains(&item) {
+            result.push(item);
+        }
+    }
+    rt(Token::List(result))
+}
+
+/// Finds the first occurrence of an element in a list
+///
+/// # Returns
+///
+/// The index of the element, or `-1` if the list does not contain it.
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the first operand is not a list
+///
+pub(crate) fn index_of(token: Token, item: Token) -> Result<Option<Token>, ProgramError> {
+    let position = list(token)?.iter().position(|x| *x == item);
+    rt(Token::Int(position.map_or(-1, |p| p as i128)))
+}
+
+/// Checks whether a list contains an element
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the first operand is not a list
+///
+pub(crate) fn contains(token: Token, item: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::Bool(list(token)?.contains(&item)))
+}
+
+/// Helper function that extracts the elements of a list token
+fn list(token: Token) -> Result<Vec<Token>, ProgramError> {
+    Vec::<Token>::try_from(token)
+}
+
+/// Helper function that extracts a count or index from an integer token
+///
+/// # Errors
+///
+/// Returns `IndexOutOfRange` for negative integers, and the errors of
+/// `i128::try_from` for anything but an integer
+///
+fn index(token: Token) -> Result<usize, ProgramError> {
+    usize::try_from(i128::try_from(token)?).map_err(|_| ProgramError::IndexOutOfRange)
+}
+
+/// Helper function that orders two values for sorting
+///
+/// Numbers are coerced to a common type like for `<`, with NaN sorted after every
+/// other float. Strings, booleans and lists of such values can be compared as well.
+///
+/// # Errors
+///
+/// Returns `NumberConversionError` for values of different types that are not both
+/// numbers, and `ExpectedBoolOrNumber` for values that cannot be ordered at all
+///
+fn compare(x: &Token, y: &Token) -> Result<Ordering, ProgramError> {
+    match x.clone().type_coercion(y.clone())? {
+        (Token::Int(x), Token::Int(y)) => Ok(x.cmp(&y)),
+        (Token::BigInt(x), Token::BigInt(y)) => Ok(x.cmp(&y)),
+        (Token::Rational(x), Token::Rational(y)) => Ok(x.cmp(&y)),
+        (Token::Decimal(x), Token::Decimal(y)) => Ok(x.cmp(&y)),
+        (Token::Float(x), Token::Float(y)) => Ok(x.total_cmp(&y)),
+        (Token::Bool(x), Token::Bool(y)) => Ok(x.cmp(&y)),
+        (Token::String(x), Token::String(y)) => Ok(x.cmp(&y)),
+        (Token::List(x), Token::List(y)) => {
+            for (a, b) in x.iter().zip(y.iter()) {
+                match compare(a, b)? {
+                    Ordering::Equal => continue,
+                    ordering => return Ok(ordering)
+                }
+            }
+            Ok(x.len().cmp(&y.len()))
+        },
+        _ => Err(ProgramError::ExpectedBoolOrNumber)
+    }
+}
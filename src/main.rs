@@ -1,10 +1,17 @@
-use std::env;
-use bprog::{normal_mode, repl_mode};
+mod cli;
 
-/// The `main` function of the program. It checks for command line arguments to determine
-/// the mode of operation. If the command line argument contains "repl", it starts the program
-/// in REPL mode by calling `repl_mode`. If "repl" is not provided as an argument, the program
-/// defaults to normal mode by calling `normal_mode`.
+use std::{env, process};
+use bprog::{check_mode, eval_mode, fmt_mode, normal_mode, repl_mode, run_mode, test_mode, ExitStatus};
+use cli::{parse_args, Command, USAGE};
+
+/// The `main` function of the program. It parses the command line arguments with
+/// `parse_args` and starts the selected mode. Without a subcommand the program is read
+/// from standard input and executed by `normal_mode`. See `cli::USAGE` for the full
+/// list of subcommands and options.
+///
+/// The process exits with 0 if the program left a single value on the stack, 1 if the
+/// program could not be read or the command line was invalid, 2 on parse errors, 3 on
+/// runtime errors, 4 if more than one value was left and 5 if any test case failed.
 ///
 /// # Examples
 ///
@@ -18,10 +25,50 @@ use bprog::{normal_mode, repl_mode};
 /// $ cargo run -- repl
 /// ```
 ///
+/// To run a program from a file with arguments:
+/// ```
+/// $ cargo run -- run filename.bprog first second
+/// ```
+///
+/// To evaluate a program and print the result as JSON:
+/// ```
+/// $ cargo run -- --output json eval -e '[ 1 2 3 ] map { 10 * }'
+/// ```
+///
+/// To run the test files in the `tests` directory:
+/// ```
+/// $ cargo run -- test
+/// ```
+///
 fn main() {
-    if env::args().any(|arg| arg == "repl") {
-        repl_mode();
-    } else {
-        normal_mode();
-    }
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (command, options) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(ExitStatus::Failure.code());
+        }
+    };
+
+    let status = match command {
+        Command::Stdin => normal_mode(&options),
+        Command::Run { path, args } => run_mode(&path, &args, &options),
+        Command::Repl => {
+            repl_mode(&options);
+            ExitStatus::Success
+        },
+        Command::Eval(code) => eval_mode(&code, &options),
+        Command::Check(paths) => check_mode(&paths, &options),
+        Command::Fmt(paths) => fmt_mode(&paths, &options),
+        Command::Test(paths) => test_mode(&paths, &options),
+        Command::Help => {
+            print!("{}", USAGE);
+            ExitStatus::Success
+        },
+        Command::Version => {
+            println!("bprog {}", env!("CARGO_PKG_VERSION"));
+            ExitStatus::Success
+        },
+    };
+    process::exit(status.code());
 }
@@ -0,0 +1,264 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::mem;
+use crate::bigint::BigInt;
+use crate::error::ProgramError;
+use crate::token::{rt, Token};
+
+/// An associative container from keys to values
+///
+/// Keys are strings, integers or booleans. The entries are kept sorted by key, with
+/// booleans before integers before strings, so that maps with the same entries are
+/// equal no matter the order they were built in, and are always printed the same way.
+///
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+pub struct Map {
+    entries: Vec<(Token, Token)>,
+}
+
+impl Map {
+    /// Creates an empty map.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Checks whether the map has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Looks up the value of a key.
+    pub fn get(&self, key: &Token) -> Option<&Token> {
+        self.find(key).ok().map(|index| &self.entries[index].1)
+    }
+
+    /// Checks whether the map has an entry for a key.
+    pub fn contains_key(&self, key: &Token) -> bool {
+        self.find(key).is_ok()
+    }
+
+    /// Sets the value of a key, replacing any previous value.
+    ///
+    /// # Returns
+    ///
+    /// The previous value of the key, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedKey` if the key is not a string, integer or boolean
+    ///
+    pub fn insert(&mut self, key: Token, value: Token) -> Result<Option<Token>, ProgramError> {
+        match self.find(&key) {
+            Ok(index) => Ok(Some(mem::replace(&mut self.entries[index].1, value))),
+            Err(_) if !is_key(&key) => Err(ProgramError::ExpectedKey),
+            Err(index) => {
+                self.entries.insert(index, (key, value));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Removes the entry for a key.
+    ///
+    /// # Returns
+    ///
+    /// The value of the key, or `None` if the map has no entry for it.
+    ///
+    pub fn remove(&mut self, key: &Token) -> Option<Token> {
+        self.find(key).ok().map(|index| self.entries.remove(index).1)
+    }
+
+    /// Returns the entries in order of their keys.
+    pub fn iter(&self) -> impl Iterator<Item = &(Token, Token)> {
+        self.entries.iter()
+    }
+
+    /// Helper function that finds the position of a key with a binary search.
+    ///
+    /// # Returns
+    ///
+    /// The index of the entry, or the index to insert an entry for the key at.
+    ///
+    fn find(&self, key: &Token) -> Result<usize, usize> {
+        self.entries.binary_search_by(|(k, _)| compare_keys(k, key))
+    }
+}
+
+impl IntoIterator for Map {
+    type Item = (Token, Token);
+    type IntoIter = std::vec::IntoIter<(Token, Token)>;
+
+    /// Consumes the map, yielding its entries in order of their keys.
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl TryFrom<Vec<(Token, Token)>> for Map {
+    type Error = ProgramError;
+
+    /// Creates a map from key-value pairs. Later pairs replace earlier ones with the same key.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedKey` if any of the keys is not a string, integer or boolean
+    ///
+    fn try_from(pairs: Vec<(Token, Token)>) -> Result<Self, Self::Error> {
+        let mut map = Map::new();
+        for (key, value) in pairs {
+            map.insert(key, value)?;
+        }
+        Ok(map)
+    }
+}
+
+impl fmt::Display for Map {
+    /// Formats the map as its entries in order, e.g. `#{"a":1,"b":2}`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let entries: Vec<String> = self.entries.iter().map(|(k, v)| format!("{}:{}", k, v)).collect();
+        write!(f, "#{{{}}}", entries.join(","))
+    }
+}
+
+/// Checks whether a token can be used as the key of a map
+pub(crate) fn is_key(token: &Token) -> bool {
+    matches!(token, Token::String(_) | Token::Int(_) | Token::BigInt(_) | Token::Bool(_))
+}
+
+/// Looks up the value of a key
+///
+/// # Arguments
+///
+/// * `map` - The map
+/// * `key` - The key to look up
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the first operand is not a map, and `KeyNotFound` if the
+/// map has no entry for the key
+///
+pub(crate) fn get(map: Token, key: Token) -> Result<Option<Token>, ProgramError> {
+    Map::try_from(map)?.remove(&key).map_or(Err(ProgramError::KeyNotFound), rt)
+}
+
+/// Sets the value of a key, replacing any previous value
+///
+/// # Arguments
+///
+/// * `map` - The map
+/// * `key` - The key
+/// * `value` - The new value of the key
+///
+/// # Returns
+///
+/// The updated map.
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the first operand is not a map, and `ExpectedKey` if the
+/// key is not a string, integer or boolean
+///
+pub(crate) fn put(map: Token, key: Token, value: Token) -> Result<Option<Token>, ProgramError> {
+    let mut map = Map::try_from(map)?;
+    map.insert(key, value)?;
+    rt(Token::Map(map))
+}
+
+/// Removes the entry for a key, if there is one
+///
+/// # Returns
+///
+/// The updated map.
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the first operand is not a map
+///
+pub(crate) fn remove(map: Token, key: Token) -> Result<Option<Token>, ProgramError> {
+    let mut map = Map::try_from(map)?;
+    map.remove(&key);
+    rt(Token::Map(map))
+}
+
+/// Checks whether a map has an entry for a key
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the first operand is not a map
+///
+pub(crate) fn has_key(map: Token, key: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::Bool(Map::try_from(map)?.contains_key(&key)))
+}
+
+/// Returns the keys of a map as a list, in order
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the operand is not a map
+///
+pub(crate) fn keys(map: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::List(Map::try_from(map)?.into_iter().map(|(k, _)| k).collect()))
+}
+
+/// Returns the values of a map as a list, in order of their keys
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the operand is not a map
+///
+pub(crate) fn values(map: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::List(Map::try_from(map)?.into_iter().map(|(_, v)| v).collect()))
+}
+
+/// Returns the entries of a map as a list of `[key value]` lists, in order of their keys
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the operand is not a map
+///
+pub(crate) fn entries(map: Token) -> Result<Option<Token>, ProgramError> {
+    rt(entry_list(Token::Map(Map::try_from(map)?)))
+}
+
+/// Returns the number of entries of a map
+///
+/// # Errors
+///
+/// Returns `ExpectedMap` if the operand is not a map
+///
+pub(crate) fn size(map: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::Int(Map::try_from(map)?.len() as i128))
+}
+
+/// Turns a map into the list of its entries as `[key value]` lists, so that it can be
+/// iterated like a list. Any other token is returned unchanged.
+pub(crate) fn entry_list(token: Token) -> Token {
+    match token {
+        Token::Map(x) => Token::List(x.into_iter().map(|(k, v)| Token::List(vec![k, v])).collect()),
+        token => token
+    }
+}
+
+/// Helper function that orders keys, booleans first, then integers, then strings
+fn compare_keys(x: &Token, y: &Token) -> Ordering {
+    let rank = |token: &Token| match token {
+        Token::Bool(_) => 0,
+        Token::Int(_) | Token::BigInt(_) => 1,
+        Token::String(_) => 2,
+        _ => 3,
+    };
+    match (x, y) {
+        (Token::Bool(x), Token::Bool(y)) => x.cmp(y),
+        (Token::Int(x), Token::Int(y)) => x.cmp(y),
+        (Token::Int(_) | Token::BigInt(_), Token::Int(_) | Token::BigInt(_)) => {
+            BigInt::try_from(x.clone()).unwrap().cmp(&BigInt::try_from(y.clone()).unwrap())
+        },
+        (Token::String(x), Token::String(y)) => x.cmp(y),
+        _ => rank(x).cmp(&rank(y))
+    }
+}
@@ -0,0 +1,128 @@
+use std::cmp::Ordering;
+use crate::bigint::BigInt;
+use crate::error::ProgramError;
+use crate::token::{rt, Token};
+
+/// Applies a floating-point function to a number
+///
+/// Integers are converted to floats first. A NaN result is rejected with `DomainError`,
+/// unless the operand was NaN already, while infinite results are returned as they are.
+///
+/// # Arguments
+///
+/// * `token` - The operand
+/// * `function` - The function to apply
+///
+/// # Errors
+///
+/// Returns `ExpectedNumber` if the operand is not a number, and `DomainError` if the
+/// function is not defined for it, e.g. the square root of a negative number
+///
+pub(crate) fn unary(token: Token, function: fn(f64) -> f64) -> Result<Option<Token>, ProgramError> {
+    let x = f64::try_from(token)?;
+    checked(function(x), x.is_nan())
+}
+
+/// Applies a floating-point function to two numbers
+///
+/// The operands are coerced to a common type with `type_coercion`, and integers are then
+/// converted to floats. NaN results are treated as in `unary`.
+///
+/// # Arguments
+///
+/// * `left` - The deeper operand
+/// * `right` - The operand from the top of the stack
+/// * `function` - The function to apply
+///
+/// # Errors
+///
+/// Returns `NumberConversionError` if the operands are not numbers, and `DomainError` if
+/// the function is not defined for them
+///
+pub(crate) fn binary(left: Token, right: Token, function: fn(f64, f64) -> f64) -> Result<Option<Token>, ProgramError> {
+    let (x, y) = left.type_coercion(right)?;
+    let (x, y) = (f64::try_from(x).map_err(|_| ProgramError::NumberConversionError)?, f64::try_from(y).map_err(|_| ProgramError::NumberConversionError)?);
+    checked(function(x, y), x.is_nan() || y.is_nan())
+}
+
+/// Rounds a number to an integer
+///
+/// Integers are returned unchanged. Floats are rounded with `function`, and the result
+/// is converted to an `Int`, or a `BigInt` if it does not fit. Rationals and decimals
+/// are rounded exactly, by rounding their fractional part with `function`.
+///
+/// # Arguments
+///
+/// * `token` - The number to round
+/// * `function` - The rounding function, such as `f64::floor`
+///
+/// # Errors
+///
+/// Returns `ExpectedNumber` if the operand is not a number, and `NumberConversionError`
+/// if it is NaN or infinite
+///
+pub(crate) fn round(token: Token, function: fn(f64) -> f64) -> Result<Option<Token>, ProgramError> {
+    match token {
+        Token::Int(_) | Token::BigInt(_) => rt(token),
+        Token::Float(x) if x.is_finite() => {
+            let rounded = function(x);
+            if rounded.abs() < i128::MAX as f64 {
+                rt(Token::Int(rounded as i128))
+            } else {
+                // a finite float is printed exactly, including all of its integer digits
+                rt(format!("{:.0}", rounded).parse::<BigInt>().unwrap().into())
+            }
+        },
+        Token::Float(_) => Err(ProgramError::NumberConversionError),
+        Token::Decimal(x) => round(Token::from(x.to_rational()), function),
+        Token::Rational(x) => {
+            // the fractional part is between -1 and 1 but not 0, so it rounds to -1, 0 or 1
+            // by its sign and how it compares to a half, which is compared exactly rather
+            // than as a float that may lose the difference
+            let fract = x.fract();
+            let double = &fract.numerator().clone().abs() * &BigInt::from(2);
+            let magnitude = match double.cmp(fract.denominator()) {
+                Ordering::Less => 0.25,
+                Ordering::Equal => 0.5,
+                Ordering::Greater => 0.75,
+            };
+            let adjustment = function(fract.signum() as f64 * magnitude) as i128;
+            rt((&x.trunc() + &BigInt::from(adjustment)).into())
+        },
+        _ => Err(ProgramError::ExpectedNumber)
+    }
+}
+
+/// Checks whether a number is NaN, or infinite when `infinite` is set
+///
+/// # Arguments
+///
+/// * `token` - The number to check
+/// * `infinite` - Check for infinity instead of NaN
+///
+/// # Errors
+///
+/// Returns `ExpectedNumber` if the operand is not a number
+///
+pub(crate) fn classify(token: Token, infinite: bool) -> Result<Option<Token>, ProgramError> {
+    match token {
+        Token::Int(_) | Token::BigInt(_) | Token::Rational(_) | Token::Decimal(_) => rt(Token::Bool(false)),
+        Token::Float(x) => rt(Token::Bool(if infinite { x.is_infinite() } else { x.is_nan() })),
+        _ => Err(ProgramError::ExpectedNumber)
+    }
+}
+
+/// Helper function that rejects NaN results of operands that were not NaN
+///
+/// # Arguments
+///
+/// * `result` - The result of a floating-point function
+/// * `nan_operand` - Whether any of the operands was NaN
+///
+fn checked(result: f64, nan_operand: bool) -> Result<Option<Token>, ProgramError> {
+    if result.is_nan() && !nan_operand {
+        Err(ProgramError::DomainError)
+    } else {
+        rt(Token::Float(result))
+    }
+}
@@ -1,7 +1,112 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::decimal::Decimal;
+use crate::map::{self, Map};
 use crate::token::Token;
 use crate::state::State;
 use crate::error::ParserError;
 
+/// Location of a word in the source code
+///
+/// Lines and columns are counted from 1, and columns count characters rather than
+/// bytes. The span keeps a shared handle to the text of its line so that errors can
+/// show a snippet of the source without having access to the whole program.
+///
+/// Spans never take part in comparisons, so two tokens that only differ in where
+/// they were written are still considered equal.
+///
+#[derive(Clone, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    source_line: Option<Rc<str>>,
+}
+
+impl Span {
+    /// Returns true if the span points to an actual location in the source code.
+    ///
+    /// Tokens that are made up by the interpreter itself, such as the `exec` that
+    /// follows a function body, have no location.
+    ///
+    pub fn is_known(&self) -> bool {
+        self.line > 0
+    }
+
+    /// Renders the line of source code the span belongs to, with a caret
+    /// underneath the spanned word.
+    ///
+    /// # Returns
+    ///
+    /// The snippet, or an empty string if the span has no source line.
+    ///
+    pub fn snippet(&self) -> String {
+        let Some(text) = &self.source_line else { return String::new() };
+        let gutter = " ".repeat(self.line.to_string().len());
+        // keep tabs in the padding so that the caret lines up with the source
+        let padding: String = text.chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        format!("\n{} |\n{} | {}\n{} | {}{}",
+                gutter, self.line, text, gutter, padding, "^".repeat(self.len.max(1)))
+    }
+}
+
+impl fmt::Display for Span {
+    /// Formats the span as a human-readable location.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+impl fmt::Debug for Span {
+    /// Formats the span compactly as `line:column`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+impl PartialEq for Span {
+    /// Spans are ignored when comparing tokens.
+    fn eq(&self, _: &Span) -> bool {
+        true
+    }
+}
+
+impl PartialOrd for Span {
+    /// Spans are ignored when comparing tokens.
+    fn partial_cmp(&self, _: &Span) -> Option<Ordering> {
+        Some(Ordering::Equal)
+    }
+}
+
+/// A single lexed word together with its location in the source code
+///
+/// String literals read by the character lexer keep their raw source text in
+/// `text`, and their unescaped contents in `literal`.
+///
+#[derive(Debug, Clone)]
+pub struct Word<'a> {
+    pub text: &'a str,
+    pub span: Span,
+    pub literal: Option<String>,
+}
+
+/// Selects how source code is split into words
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LexerMode {
+    /// Splits on whitespace only, as described in the specification. Strings are
+    /// delimited by standalone `"` words and their words are joined by single spaces
+    #[default]
+    Words,
+    /// Scans the input character by character. Supports `"..."` string literals
+    /// with escape sequences, and keeps the whitespace inside strings intact.
+    /// Programs written for `Words` are still accepted
+    Characters,
+}
 
 /// Entry point for the parser
 ///
@@ -19,22 +124,375 @@ use crate::error::ParserError;
 /// Returns ParseError if the operation fails during the tokenization
 ///
 pub fn parse_string_to_instructions(input_string: &str, state: &mut State) -> Result<(), ParserError> {
-    tokenize_and_parse(&lex(input_string), state)
+    let words = match state.lexer_mode {
+        LexerMode::Words => lex(input_string)?,
+        LexerMode::Characters => lex_characters(input_string)?,
+    };
+    tokenize_and_parse(&words, state)
 }
 
 /// Lexer
 ///
 /// The lexer takes a string and separates it by whitespace, and returns a slice to
 /// a heap allocated array of words. We want to return a slice because the parser
-/// work by dynamically adjusting the slices when making collections
+/// work by dynamically adjusting the slices when making collections. Every word
+/// records the line and column it was found at. Comments are removed
 ///
 /// # Arguments
 ///
 /// * `input_string` - The string to be lexed and parsed
 ///
-pub fn lex(input_string: &str) -> Box<[&str]> {
-    let vec: Vec<&str> = input_string.split_whitespace().collect();
-    vec.into_boxed_slice()
+/// # Errors
+///
+/// Returns ParseError if a block comment is not closed or opened
+///
+pub fn lex(input_string: &str) -> Result<Box<[Word<'_>]>, ParserError> {
+    let mut vec: Vec<Word> = Vec::new();
+    for (line_index, line) in input_string.split('\n').enumerate() {
+        let line = line.trim_end_matches('\r');
+        let source_line: Rc<str> = Rc::from(line);
+        // byte offset and column of the word currently being read
+        let mut start: Option<(usize, usize)> = None;
+        for (column, (offset, c)) in line.char_indices().enumerate() {
+            match (c.is_whitespace(), start) {
+                (true, Some((begin, begin_column))) => {
+                    vec.push(make_word(&line[begin..offset], line_index, begin_column, &source_line));
+                    start = None;
+                },
+                (false, None) => start = Some((offset, column)),
+                _ => {}
+            }
+        }
+        if let Some((begin, begin_column)) = start {
+            vec.push(make_word(&line[begin..], line_index, begin_column, &source_line));
+        }
+    }
+    Ok(strip_comments(vec)?.into_boxed_slice())
+}
+
+/// Removes comments from whitespace separated words
+///
+/// A word starting with `#` comments out the rest of its line, except for `#{`, which
+/// opens a map. Words between `(` and
+/// `)` are a block comment, which is typically used for stack effects such as
+/// `( x y -- z )`. Block comments can be nested. Neither kind of comment is recognised
+/// inside a string
+///
+/// # Arguments
+///
+/// * `words` - The lexed words, including comments
+///
+/// # Errors
+///
+/// Returns ParseError if a block comment is not closed or opened
+///
+fn strip_comments(words: Vec<Word>) -> Result<Vec<Word>, ParserError> {
+    let mut result: Vec<Word> = Vec::with_capacity(words.len());
+    let mut in_string = false;
+    // line that is commented out by a line comment
+    let mut comment_line = None;
+    // opening parentheses of the block comments we are currently inside
+    let mut open_comments: Vec<Span> = Vec::new();
+    for word in words {
+        if comment_line == Some(word.span.line) {
+            continue
+        }
+        match word.text {
+            "(" if !in_string => {
+                open_comments.push(word.span);
+                continue
+            },
+            ")" if !in_string => {
+                open_comments.pop().ok_or_else(|| ParserError::IncompleteComment(word.span.clone()))?;
+                continue
+            },
+            _ if !open_comments.is_empty() => continue,
+            "\"" => in_string = !in_string,
+            "#{" => {},
+            s if s.starts_with('#') && !in_string => {
+                comment_line = Some(word.span.line);
+                continue
+            },
+            _ => {}
+        }
+        result.push(word);
+    }
+    match open_comments.into_iter().next() {
+        Some(span) => Err(ParserError::IncompleteComment(span)),
+        None => Ok(result)
+    }
+}
+
+/// Creates a word with a span from zero-based line and column indices
+///
+/// # Arguments
+///
+/// * `text` - The text of the word
+/// * `line_index` - Zero-based line number
+/// * `column_index` - Zero-based character column
+/// * `source_line` - The full text of the line the word belongs to
+///
+fn make_word<'a>(text: &'a str, line_index: usize, column_index: usize, source_line: &Rc<str>) -> Word<'a> {
+    let span = Span {
+        line: line_index + 1,
+        column: column_index + 1,
+        len: text.chars().count(),
+        source_line: Some(source_line.clone()),
+    };
+    Word { text, span, literal: None }
+}
+
+/// Character lexer
+///
+/// Scans the input one character at a time and splits it into words like `lex` does,
+/// except for string literals. A `"` that is directly followed by another character
+/// starts a literal that runs until the next unescaped `"`, with its contents kept
+/// exactly as written. A `"` followed by whitespace starts a string in the old
+/// space-delimited style, which ends at the next standalone `"`. In that case only
+/// the whitespace next to the quotes is dropped.
+///
+/// Both kinds of string support the escapes `\n`, `\t`, `\r`, `\"`, `\\` and `\u{...}`.
+///
+/// Comments are removed the same way as in `lex`, except that a quote inside a
+/// comment does not start a string.
+///
+/// # Arguments
+///
+/// * `input_string` - The string to be lexed
+///
+/// # Errors
+///
+/// Returns ParseError if a string is not closed or contains an invalid escape, or if
+/// a block comment is not closed or opened
+///
+pub fn lex_characters(input_string: &str) -> Result<Box<[Word<'_>]>, ParserError> {
+    let lines: Vec<Rc<str>> = input_string.split('\n').map(|l| Rc::from(l.trim_end_matches('\r'))).collect();
+    let chars: Vec<(usize, char)> = input_string.char_indices().collect();
+    // zero-based line and column of every character
+    let mut positions: Vec<(usize, usize)> = Vec::with_capacity(chars.len());
+    let (mut line, mut column) = (0, 0);
+    for (_, c) in &chars {
+        positions.push((line, column));
+        if *c == '\n' {
+            line += 1;
+            column = 0;
+        } else {
+            column += 1;
+        }
+    }
+    let span_at = |index: usize, len: usize| {
+        let (line, column) = positions[index];
+        Span { line: line + 1, column: column + 1, len, source_line: Some(lines[line].clone()) }
+    };
+
+    let mut vec: Vec<Word> = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let (offset, c) = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+            continue
+        }
+        let start = index;
+        let mut literal = None;
+        if c == '#' && !is_map_opening(&chars, index) {
+            // line comment
+            while index < chars.len() && chars[index].1 != '\n' {
+                index += 1;
+            }
+            continue
+        } else if is_single_char_word(&chars, index, '(') {
+            index = skip_block_comment(&chars, start, &span_at)?;
+            continue
+        } else if is_single_char_word(&chars, index, ')') {
+            return Err(ParserError::IncompleteComment(span_at(start, 1)))
+        } else if c == '"' {
+            let legacy = chars.get(index + 1).is_none_or(|(_, next)| next.is_whitespace());
+            let (end, content) = read_string_literal(&chars, start, legacy, &span_at)?;
+            index = end;
+            literal = Some(content);
+        } else {
+            while index < chars.len() && !chars[index].1.is_whitespace() {
+                index += 1;
+            }
+        }
+        let end_offset = chars.get(index).map_or(input_string.len(), |(o, _)| *o);
+        // a literal may span several lines, but the caret only covers the first one
+        let len = chars[start..index].iter().take_while(|(_, c)| *c != '\n').count();
+        vec.push(Word { text: &input_string[offset..end_offset], span: span_at(start, len), literal });
+    }
+    Ok(vec.into_boxed_slice())
+}
+
+/// Checks whether the word starting at `index` consists of the single character `c`
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the input, with their byte offsets
+/// * `index` - Index of the first character of the word
+/// * `c` - The character to look for
+///
+fn is_single_char_word(chars: &[(usize, char)], index: usize, c: char) -> bool {
+    chars[index].1 == c && chars.get(index + 1).is_none_or(|(_, next)| next.is_whitespace())
+}
+
+/// Checks whether the word starting at `index` is `#{`, which opens a map rather than
+/// a comment
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the input, with their byte offsets
+/// * `index` - Index of the `#`
+///
+fn is_map_opening(chars: &[(usize, char)], index: usize) -> bool {
+    chars.get(index + 1).is_some_and(|(_, c)| *c == '{') && chars.get(index + 2).is_none_or(|(_, next)| next.is_whitespace())
+}
+
+/// Skips a block comment for the character lexer
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the input, with their byte offsets
+/// * `start` - Index of the opening parenthesis
+/// * `span_at` - Creates a span from a character index and a length
+///
+/// # Returns
+///
+/// The index just past the closing parenthesis
+///
+/// # Errors
+///
+/// Returns ParseError if the comment is not closed
+///
+fn skip_block_comment(chars: &[(usize, char)], start: usize,
+                      span_at: &dyn Fn(usize, usize) -> Span) -> Result<usize, ParserError> {
+    let mut level = 0;
+    let mut index = start;
+    while index < chars.len() {
+        if chars[index].1.is_whitespace() {
+            index += 1;
+            continue
+        }
+        if is_single_char_word(chars, index, '(') {
+            level += 1;
+        } else if is_single_char_word(chars, index, ')') {
+            level -= 1;
+            if level == 0 {
+                return Ok(index + 1)
+            }
+        }
+        while index < chars.len() && !chars[index].1.is_whitespace() {
+            index += 1;
+        }
+    }
+    Err(ParserError::IncompleteComment(span_at(start, 1)))
+}
+
+/// Reads a string literal for the character lexer
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the input, with their byte offsets
+/// * `start` - Index of the opening double quote
+/// * `legacy` - Whether the string uses the old space-delimited syntax
+/// * `span_at` - Creates a span from a character index and a length
+///
+/// # Returns
+///
+/// The index just past the closing quote, and the unescaped contents of the string
+///
+/// # Errors
+///
+/// Returns ParseError if the string is not closed or contains an invalid escape
+///
+fn read_string_literal(chars: &[(usize, char)], start: usize, legacy: bool,
+                       span_at: &dyn Fn(usize, usize) -> Span) -> Result<(usize, String), ParserError> {
+    let mut index = start + 1;
+    let mut content = String::new();
+    // length of the contents up to the last character that is not delimiting whitespace
+    let mut keep = 0;
+    if legacy {
+        while chars.get(index).is_some_and(|(_, c)| c.is_whitespace()) {
+            index += 1;
+        }
+    }
+    loop {
+        match chars.get(index) {
+            None => return Err(ParserError::IncompleteString(span_at(start, 1))),
+            Some((_, '"')) if !legacy || closes_legacy_string(chars, index) => break,
+            Some((_, '\\')) => {
+                let (next, c) = read_escape(chars, index, span_at)?;
+                content.push(c);
+                keep = content.len();
+                index = next;
+            },
+            Some((_, c)) => {
+                content.push(*c);
+                if !c.is_whitespace() {
+                    keep = content.len();
+                }
+                index += 1;
+            }
+        }
+    }
+    if legacy {
+        content.truncate(keep);
+    }
+    Ok((index + 1, content))
+}
+
+/// Checks whether a double quote is a standalone word, which closes an old-style string
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the input, with their byte offsets
+/// * `index` - Index of the double quote
+///
+fn closes_legacy_string(chars: &[(usize, char)], index: usize) -> bool {
+    let after = chars.get(index + 1).is_none_or(|(_, c)| c.is_whitespace());
+    let before = index > 0 && chars[index - 1].1.is_whitespace();
+    before && after
+}
+
+/// Reads an escape sequence starting with a backslash
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the input, with their byte offsets
+/// * `index` - Index of the backslash
+/// * `span_at` - Creates a span from a character index and a length
+///
+/// # Returns
+///
+/// The index just past the escape sequence, and the character it represents
+///
+/// # Errors
+///
+/// Returns ParseError if the escape sequence is unknown or malformed
+///
+fn read_escape(chars: &[(usize, char)], index: usize,
+               span_at: &dyn Fn(usize, usize) -> Span) -> Result<(usize, char), ParserError> {
+    let simple = |c: char| Ok((index + 2, c));
+    match chars.get(index + 1).map(|(_, c)| *c) {
+        Some('n') => simple('\n'),
+        Some('t') => simple('\t'),
+        Some('r') => simple('\r'),
+        Some('"') => simple('"'),
+        Some('\\') => simple('\\'),
+        Some('u') => {
+            // \u{XXXX} with one to six hexadecimal digits
+            let close = chars[index..].iter().take(10).position(|(_, c)| *c == '}').map(|p| index + p);
+            let code = match close {
+                Some(close) if chars.get(index + 2).is_some_and(|(_, c)| *c == '{') => {
+                    let digits: String = chars[index + 3..close].iter().map(|(_, c)| *c).collect();
+                    u32::from_str_radix(digits.as_str(), 16).ok().and_then(char::from_u32).map(|c| (close + 1, c))
+                },
+                _ => None
+            };
+            code.ok_or_else(|| ParserError::InvalidEscape(span_at(index, close.map_or(2, |c| c + 1 - index))))
+        },
+        _ => Err(ParserError::InvalidEscape(span_at(index, 2)))
+    }
 }
 
 /// Initiates the parsing phase
@@ -52,7 +510,7 @@ pub fn lex(input_string: &str) -> Box<[&str]> {
 ///
 /// Returns ParseError if the operation fails during the tokenization
 ///
-fn tokenize_and_parse(words: &[&str], state: &mut State) -> Result<(), ParserError> {
+fn tokenize_and_parse(words: &[Word], state: &mut State) -> Result<(), ParserError> {
     let mut index: usize = 0;
 
     while index < words.len() {
@@ -77,17 +535,23 @@ fn tokenize_and_parse(words: &[&str], state: &mut State) -> Result<(), ParserErr
 ///
 /// Returns ParseError if the operation fails during the tokenization
 ///
-fn get_token(index: &mut usize, words: &[&str]) -> Result<Token, ParserError> {
-    match words[index.clone()] {
+fn get_token(index: &mut usize, words: &[Word]) -> Result<Token, ParserError> {
+    let word = &words[*index];
+    if let Some(literal) = &word.literal {
+        return Ok(Token::String(literal.clone()))
+    }
+    match word.text {
         "[" => make_collection(index, words, Token::List(vec![])),
-        "{" => make_collection(index, words, Token::Block(vec![])),
+        "{" => make_collection(index, words, Token::Block(Rc::from([]))),
+        "#{" => make_collection(index, words, Token::Map(Map::new())),
         "\"" => make_string(index, words),
-        "]" => Err(ParserError::IncompleteList),
-        "}" => Err(ParserError::IncompleteQuotation),
+        "]" => Err(ParserError::IncompleteList(word.span.clone())),
+        "}" => Err(ParserError::IncompleteQuotation(word.span.clone())),
         s if is_bool(s) => Ok(Token::Bool(s.to_lowercase().parse::<bool>().unwrap())),
-        s if is_integer(s) => Ok(Token::Int(s.parse::<i128>().unwrap())),
+        s if is_integer(s) => Ok(s.parse::<i128>().map_or_else(|_| Token::from(s.parse::<BigInt>().unwrap()), Token::Int)),
         s if is_float(s) => Ok(Token::Float(s.parse::<f64>().unwrap())),
-        s => Ok(Token::Symbol(s.to_string()))
+        s if is_decimal(s) => Ok(Token::Decimal(s[..s.len() - 1].parse::<Decimal>().unwrap())),
+        s => Ok(Token::Symbol(s.to_string(), word.span.clone()))
     }
 }
 
@@ -98,9 +562,9 @@ fn get_token(index: &mut usize, words: &[&str]) -> Result<Token, ParserError> {
 /// * `s` - The lexed word to be evaluated
 ///
 fn is_integer(s: &str) -> bool {
-    let at_least_one_digit = s.chars().any(|c| c.is_digit(10));
-    let rest_are_legal = s.chars().enumerate().all(|(i, c)| c.is_digit(10) || (i == 0 && c == '-'));
-    return at_least_one_digit && rest_are_legal
+    let at_least_one_digit = s.chars().any(|c| c.is_ascii_digit());
+    let rest_are_legal = s.chars().enumerate().all(|(i, c)| c.is_ascii_digit() || (i == 0 && c == '-'));
+    at_least_one_digit && rest_are_legal
 }
 
 /// Checks whether the word is a float representation
@@ -110,12 +574,23 @@ fn is_integer(s: &str) -> bool {
 /// * `s` - The lexed word to be evaluated
 ///
 fn is_float(s: &str) -> bool {
-    let at_least_one_digit = s.chars().any(|c| c.is_digit(10));
+    let at_least_one_digit = s.chars().any(|c| c.is_ascii_digit());
     let exactly_one_dot = s.chars().filter(|c| *c == '.').count() == 1;
-    let rest_are_legal = s.chars().enumerate().all(|(i, c)| c.is_digit(10) || (i == 0 && c == '-') || c == '.');
+    let rest_are_legal = s.chars().enumerate().all(|(i, c)| c.is_ascii_digit() || (i == 0 && c == '-') || c == '.');
     at_least_one_digit && exactly_one_dot && rest_are_legal
 }
 
+/// Checks whether the word is a decimal representation, which is an integer or float
+/// followed by `d`, e.g. `12.50d`
+///
+/// # Arguments
+///
+/// * `s` - The lexed word to be evaluated
+///
+fn is_decimal(s: &str) -> bool {
+    s.strip_suffix('d').is_some_and(|number| is_integer(number) || is_float(number))
+}
+
 /// Checks whether the word is a bool representation
 ///
 /// # Arguments
@@ -126,34 +601,37 @@ fn is_bool(s: &str) -> bool {
     s == "true" || s == "True" || s == "false" || s == "False"
 }
 
-/// Creates a collection of type Token::List or Token::Block
+/// Creates a collection of type Token::List, Token::Block or Token::Map
 ///
 /// Reads ahead for closing brackets and calls the tokenizer on the slice
 /// in between, so that the individual tokens can be inserted into the list.
+/// The tokens of a map are taken as alternating keys and values.
 ///
 /// # Arguments
 ///
 /// * `index` - Mutable index so that when the collection is made the program
-///             can continue at the updated index
+///   can continue at the updated index
 /// * `words` - Slice of lexed input
-/// * `t` - Type of collection, list or block
+/// * `t` - Type of collection, list, block or map
 ///
 /// # Errors
 ///
-/// Returns ParseError if there are missing or misplaced brackets/braces
+/// Returns ParseError if there are missing or misplaced brackets/braces, or if a map
+/// has a key without a value or a key that is not a string, integer or boolean
 ///
-fn make_collection(index: &mut usize, words: &[&str], t: Token) -> Result<Token, ParserError> {
+fn make_collection(index: &mut usize, words: &[Word], t: Token) -> Result<Token, ParserError> {
     let mut collection_state = State::new();
     let mut level = 0;
     // keeps track of the opening and closing index
     let start_index = *index + 1;
+    let opening = words[*index].span.clone();
     while *index < words.len() {
         // counts up the number of opening and closing brackets/braces
-        level += match (&t, words[*index]) {
+        level += match (&t, words[*index].text) {
             (Token::List(_),  "[") =>  1,
             (Token::List(_),  "]") => -1,
-            (Token::Block(_), "{") =>  1,
-            (Token::Block(_), "}") => -1,
+            (Token::Block(_) | Token::Map(_), "{" | "#{") =>  1,
+            (Token::Block(_) | Token::Map(_), "}") => -1,
             _                      =>  0,
         };
 
@@ -173,11 +651,27 @@ fn make_collection(index: &mut usize, words: &[&str], t: Token) -> Result<Token,
         },
         (Token::Block(_), 0) => {
             tokenize_and_parse(&words[start_index..*index], &mut collection_state)?;
-            Ok(Token::Block(collection_state.get_instructions()))
+            Ok(Token::Block(collection_state.get_instructions().into()))
+        },
+        (Token::Map(_), 0) => {
+            tokenize_and_parse(&words[start_index..*index], &mut collection_state)?;
+            let tokens = collection_state.get_instructions();
+            if !tokens.len().is_multiple_of(2) {
+                return Err(ParserError::IncompleteMap(opening))
+            }
+            let mut entries = Map::new();
+            for pair in tokens.chunks(2) {
+                if !map::is_key(&pair[0]) {
+                    return Err(ParserError::InvalidMapKey(opening))
+                }
+                entries.insert(pair[0].clone(), pair[1].clone()).unwrap();
+            }
+            Ok(Token::Map(entries))
         },
         // the index reached the end of the string and the closing bracket/brace was not found
-        (Token::List(_), _)  => Err(ParserError::IncompleteList),
-        (Token::Block(_), _) => Err(ParserError::IncompleteQuotation),
+        (Token::List(_), _)  => Err(ParserError::IncompleteList(opening)),
+        (Token::Block(_), _) => Err(ParserError::IncompleteQuotation(opening)),
+        (Token::Map(_), _) => Err(ParserError::IncompleteMap(opening)),
         _ => panic!("Incorrect Token Type given to function")
     }
 }
@@ -189,27 +683,28 @@ fn make_collection(index: &mut usize, words: &[&str], t: Token) -> Result<Token,
 /// # Arguments
 ///
 /// * `index` - Mutable index so that when the collection is made the program
-///             can continue at the updated index
+///   can continue at the updated index
 /// * `words` - Slice of lexed input
 ///
 /// # Errors
 ///
 /// Returns ParseError if there are missing or misplaced brackets/braces
 ///
-fn make_string(index: &mut usize, words: &[&str]) -> Result<Token, ParserError> {
+fn make_string(index: &mut usize, words: &[Word]) -> Result<Token, ParserError> {
+    let opening = words[*index].span.clone();
     *index += 1;
     let mut result_string = String::new();
-    while *index < words.len() && words[*index] != "\"" {
+    while *index < words.len() && words[*index].text != "\"" {
         if result_string.is_empty() {
-            result_string = words[*index].to_string();
+            result_string = words[*index].text.to_string();
         } else {
-            result_string = vec![result_string, words[*index].to_string()].join(" ");
+            result_string = [result_string, words[*index].text.to_string()].join(" ");
         }
         *index += 1;
     }
     if *index < words.len() {
         Ok(Token::String(result_string))
     } else {
-        Err(ParserError::IncompleteString)
+        Err(ParserError::IncompleteString(opening))
     }
 }
\ No newline at end of file
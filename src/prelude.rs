@@ -0,0 +1,63 @@
+use std::fs;
+use std::mem;
+use crate::state::State;
+use crate::error::PreludeError;
+use crate::interpreter::start_runtime;
+use crate::parser::parse_string_to_instructions;
+
+/// The standard library that is compiled into the interpreter
+pub const DEFAULT_PRELUDE: &str = include_str!("../prelude.bprog");
+
+/// Describes which prelude, if any, is loaded before the user program
+///
+/// The prelude is a program written in bprog itself that defines library
+/// words such as `sum` or `nip`.
+///
+#[derive(Debug, Clone, Default)]
+pub enum Prelude {
+    /// The prelude bundled with the interpreter
+    #[default]
+    Bundled,
+    /// A prelude read from the given file path
+    File(String),
+    /// Start from an empty state without any library words
+    Disabled,
+}
+
+/// Loads a prelude into the given state
+///
+/// The prelude is parsed and executed like any other program, so that its
+/// function definitions and bindings end up in the state. Any values the prelude
+/// leaves behind on the stack are discarded, so that the user program starts
+/// with an empty stack. The prelude is neither traced nor counted against the
+/// resource limits of the user program.
+///
+/// # Arguments
+///
+/// * `prelude` - Which prelude to load
+/// * `state` - The stack, instruction list, list of functions and bindings
+///
+/// # Errors
+///
+/// Returns PreludeError if the prelude cannot be read, parsed or executed
+///
+pub fn load_prelude(prelude: &Prelude, state: &mut State) -> Result<(), PreludeError> {
+    let source = match prelude {
+        Prelude::Bundled => DEFAULT_PRELUDE.to_string(),
+        Prelude::File(path) => fs::read_to_string(path).map_err(|_| PreludeError::Unreadable(path.clone()))?,
+        Prelude::Disabled => return Ok(())
+    };
+    let saved = (state.trace, mem::take(&mut state.limits), state.deadline.take());
+    state.trace = false;
+    let result = parse_string_to_instructions(source.as_str(), state)
+        .map_err(PreludeError::Parser)
+        .and_then(|_| start_runtime(state).map_err(PreludeError::Program));
+    // the settings of the user program are restored whether or not the prelude loaded
+    (state.trace, state.limits, state.deadline) = saved;
+    if result.is_err() {
+        state.instruction_set.clear();
+    }
+    result?;
+    state.stack.clear();
+    Ok(())
+}
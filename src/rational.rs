@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Div, Neg};
+use crate::bigint::BigInt;
+
+/// What `/` gives when dividing two integers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Division {
+    /// The quotient is converted to a `Float`
+    #[default]
+    Float,
+    /// The quotient is an exact `Rational`, or an `Int` if the division has no remainder
+    Exact,
+}
+
+/// An exact fraction of two integers
+///
+/// The fraction is always in lowest terms, and the denominator is positive, so that
+/// equal values are always represented the same way. The numerator and denominator
+/// are arbitrary-precision integers, so arithmetic on rationals never overflows.
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: BigInt,
+    denominator: BigInt,
+}
+
+impl Rational {
+    /// Creates the fraction `numerator / denominator` in lowest terms.
+    ///
+    /// # Returns
+    ///
+    /// The fraction, or `None` if the denominator is zero.
+    ///
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Option<Self> {
+        if denominator.is_zero() {
+            return None
+        }
+        let divisor = gcd(numerator.clone(), denominator.clone());
+        let divisor = if denominator.is_negative() { -divisor } else { divisor };
+        Some(Self { numerator: numerator.div_rem(&divisor).0, denominator: denominator.div_rem(&divisor).0 })
+    }
+
+    /// Returns the numerator, which has the sign of the fraction.
+    pub fn numerator(&self) -> &BigInt {
+        &self.numerator
+    }
+
+    /// Returns the denominator, which is always positive.
+    pub fn denominator(&self) -> &BigInt {
+        &self.denominator
+    }
+
+    /// Checks whether the fraction is zero.
+    pub fn is_zero(&self) -> bool {
+        self.numerator.is_zero()
+    }
+
+    /// Checks whether the fraction is a whole number.
+    pub fn is_integer(&self) -> bool {
+        self.denominator == BigInt::from(1)
+    }
+
+    /// Returns `-1`, `0` or `1` depending on the sign of the fraction.
+    pub fn signum(&self) -> i128 {
+        self.numerator.signum()
+    }
+
+    /// Returns the absolute value.
+    pub fn abs(self) -> Self {
+        Self { numerator: self.numerator.abs(), denominator: self.denominator }
+    }
+
+    /// Returns the integer part of the fraction, rounding towards zero.
+    pub fn trunc(&self) -> BigInt {
+        self.numerator.div_rem(&self.denominator).0
+    }
+
+    /// Returns the part of the fraction after the integer part, which has the sign of
+    /// the fraction.
+    pub fn fract(&self) -> Rational {
+        Self { numerator: self.numerator.div_rem(&self.denominator).1, denominator: self.denominator.clone() }
+    }
+
+    /// Converts the fraction to the nearest `f64`.
+    pub fn to_f64(&self) -> f64 {
+        self.numerator.to_f64() / self.denominator.to_f64()
+    }
+
+    /// Raises the fraction to the power of `exponent`.
+    ///
+    /// # Returns
+    ///
+    /// The power, or `None` if its numerator or denominator is too large, as for
+    /// `BigInt::checked_pow`.
+    ///
+    pub fn pow(&self, exponent: u32) -> Option<Self> {
+        // powers of numbers without common factors have no common factors either
+        Some(Self { numerator: self.numerator.checked_pow(exponent)?, denominator: self.denominator.checked_pow(exponent)? })
+    }
+
+    /// Returns the reciprocal `1 / self`.
+    ///
+    /// # Returns
+    ///
+    /// The reciprocal, or `None` if the fraction is zero.
+    ///
+    pub fn recip(&self) -> Option<Self> {
+        Rational::new(self.denominator.clone(), self.numerator.clone())
+    }
+}
+
+impl From<BigInt> for Rational {
+    /// Converts an integer to a fraction with denominator 1.
+    fn from(value: BigInt) -> Self {
+        Self { numerator: value, denominator: BigInt::from(1) }
+    }
+}
+
+impl fmt::Display for Rational {
+    /// Formats the fraction as `numerator/denominator`, e.g. `-1/3`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl Ord for Rational {
+    /// Compares two fractions numerically, by cross-multiplying with the positive denominators.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.numerator * &other.denominator).cmp(&(&other.numerator * &self.denominator))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+
+    /// Returns the fraction with the opposite sign.
+    fn neg(self) -> Self::Output {
+        Self { numerator: -self.numerator, denominator: self.denominator }
+    }
+}
+
+impl Add for &Rational {
+    type Output = Rational;
+
+    /// Adds two fractions.
+    fn add(self, other: &Rational) -> Self::Output {
+        let numerator = &(&self.numerator * &other.denominator) + &(&other.numerator * &self.denominator);
+        Rational::new(numerator, &self.denominator * &other.denominator).unwrap()
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+
+    /// Subtracts `other` from `self`.
+    fn sub(self, other: &Rational) -> Self::Output {
+        self + &-other.clone()
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+
+    /// Multiplies two fractions.
+    fn mul(self, other: &Rational) -> Self::Output {
+        Rational::new(&self.numerator * &other.numerator, &self.denominator * &other.denominator).unwrap()
+    }
+}
+
+impl Div for &Rational {
+    type Output = Option<Rational>;
+
+    /// Divides `self` by `other`.
+    ///
+    /// # Returns
+    ///
+    /// The quotient, or `None` if `other` is zero.
+    ///
+    fn div(self, other: &Rational) -> Self::Output {
+        Rational::new(&self.numerator * &other.denominator, &self.denominator * &other.numerator)
+    }
+}
+
+/// Helper function that finds the greatest common divisor of two integers with
+/// Euclid's algorithm. The result is never negative.
+fn gcd(mut x: BigInt, mut y: BigInt) -> BigInt {
+    while !y.is_zero() {
+        let remainder = x.div_rem(&y).1;
+        x = y;
+        y = remainder;
+    }
+    x.abs()
+}
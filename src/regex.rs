@@ -0,0 +1,658 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::error::ProgramError;
+use crate::token::{rt, Token};
+
+/// Largest number of instructions a compiled pattern may have
+const MAX_PROGRAM: usize = 10_000;
+
+/// Largest count of a counted repetition such as `{n,m}`
+const MAX_REPEAT: u32 = 1_000;
+
+/// Largest number of patterns kept in a `RegexCache` before it is emptied
+const MAX_CACHED: usize = 256;
+
+/// A compiled regular expression
+///
+/// The supported syntax is a subset of the common Perl-style syntax:
+///
+/// * literal characters, and `\` to escape any of `.^$|?*+()[]{}\`
+/// * `.` for any character but a newline, and the classes `\d`, `\w` and `\s` with
+///   their negations `\D`, `\W` and `\S`
+/// * character classes such as `[a-z_]` and `[^,]`
+/// * the anchors `^` and `$`, and the word boundaries `\b` and `\B`
+/// * capturing groups `(...)`, non-capturing groups `(?:...)` and alternation `|`
+/// * the quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`, which are greedy unless
+///   followed by `?`
+///
+/// Patterns are matched by a backtracking machine that never visits the same
+/// instruction at the same position twice, so matching takes time proportional to
+/// the length of the pattern times the length of the string.
+///
+#[derive(Debug)]
+pub(crate) struct Regex {
+    program: Vec<Inst>,
+    groups: usize,
+}
+
+/// A set of characters in a pattern
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+/// A member of a character class
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Class(Class),
+}
+
+/// A node of a parsed pattern
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+/// An instruction of a compiled pattern
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    /// Continues at the first address, and backtracks to the second
+    Split(usize, usize),
+    Jump(usize),
+    Save(usize),
+    Start,
+    End,
+    WordBoundary(bool),
+    Match,
+}
+
+/// Work left for the backtracking machine
+enum Job {
+    Explore(usize, usize),
+    Restore(usize, Option<usize>),
+}
+
+impl Class {
+    /// Creates the class of the escape `\d`, `\w` or `\s`, or their negations.
+    fn perl(c: char) -> Option<Class> {
+        let items = match c.to_ascii_lowercase() {
+            'd' => vec![ClassItem::Range('0', '9')],
+            'w' => vec![ClassItem::Range('a', 'z'), ClassItem::Range('A', 'Z'), ClassItem::Range('0', '9'), ClassItem::Range('_', '_')],
+            's' => vec![ClassItem::Range(' ', ' '), ClassItem::Range('\t', '\r')],
+            _ => return None
+        };
+        Some(Class { negated: c.is_ascii_uppercase(), items })
+    }
+
+    /// Checks whether a character belongs to the class.
+    fn contains(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match item {
+            ClassItem::Range(low, high) => (*low..=*high).contains(&c),
+            ClassItem::Class(class) => class.contains(c),
+        });
+        found != self.negated
+    }
+}
+
+impl Regex {
+    /// Compiles a pattern.
+    ///
+    /// # Errors
+    ///
+    /// Returns `InvalidRegex` if the pattern is malformed or too large
+    ///
+    pub fn new(pattern: &str) -> Result<Self, ProgramError> {
+        let mut parser = Parser { chars: pattern.chars().collect(), position: 0, groups: 0 };
+        let node = parser.alternation()?;
+        if parser.position < parser.chars.len() {
+            // only an unbalanced `)` stops the parser early
+            return Err(ProgramError::InvalidRegex)
+        }
+        // the whole match is group 0
+        let mut program = Vec::new();
+        compile(&Node::Group(Box::new(node), Some(0)), &mut program)?;
+        program.push(Inst::Match);
+        Ok(Self { program, groups: parser.groups + 1 })
+    }
+
+    /// Finds the first match that starts at or after `start`.
+    ///
+    /// # Arguments
+    ///
+    /// * `chars` - The characters of the string to search
+    /// * `start` - The index of the character to start searching at
+    ///
+    /// # Returns
+    ///
+    /// The start and end character indices of the whole match followed by those of
+    /// every group, where groups that did not take part in the match are `None`, or
+    /// `None` if there is no match.
+    ///
+    fn find_at(&self, chars: &[char], start: usize) -> Option<Vec<Option<(usize, usize)>>> {
+        let width = chars.len() + 1;
+        let mut visited = vec![0u64; (self.program.len() * width).div_ceil(64)];
+        let mut slots = vec![None; self.groups * 2];
+        for first in start..=chars.len() {
+            let mut jobs = vec![Job::Explore(0, first)];
+            while let Some(job) = jobs.pop() {
+                let (mut pc, mut position) = match job {
+                    Job::Explore(pc, position) => (pc, position),
+                    Job::Restore(slot, value) => {
+                        slots[slot] = value;
+                        continue
+                    }
+                };
+                loop {
+                    let bit = pc * width + position;
+                    if visited[bit / 64] & (1 << (bit % 64)) != 0 {
+                        break
+                    }
+                    visited[bit / 64] |= 1 << (bit % 64);
+                    let current = chars.get(position);
+                    match &self.program[pc] {
+                        Inst::Char(c) if current == Some(c) => (pc, position) = (pc + 1, position + 1),
+                        Inst::Any if current.is_some_and(|c| *c != '\n') => (pc, position) = (pc + 1, position + 1),
+                        Inst::Class(class) if current.is_some_and(|c| class.contains(*c)) => (pc, position) = (pc + 1, position + 1),
+                        Inst::Split(x, y) => {
+                            jobs.push(Job::Explore(*y, position));
+                            pc = *x;
+                        },
+                        Inst::Jump(x) => pc = *x,
+                        Inst::Save(slot) => {
+                            jobs.push(Job::Restore(*slot, slots[*slot]));
+                            slots[*slot] = Some(position);
+                            pc += 1;
+                        },
+                        Inst::Start if position == 0 => pc += 1,
+                        Inst::End if position == chars.len() => pc += 1,
+                        Inst::WordBoundary(negated) if is_boundary(chars, position) != *negated => pc += 1,
+                        Inst::Match => {
+                            return Some(slots.chunks(2).map(|slot| slot[0].zip(slot[1])).collect())
+                        },
+                        _ => break
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds every non-overlapping match from left to right.
+    ///
+    /// After an empty match the search continues one character further, so that
+    /// every position is considered once.
+    ///
+    fn find_all(&self, chars: &[char]) -> Vec<Vec<Option<(usize, usize)>>> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+        while start <= chars.len() {
+            let Some(groups) = self.find_at(chars, start) else { break };
+            let (from, to) = groups[0].unwrap();
+            start = if to == from { to + 1 } else { to };
+            matches.push(groups);
+        }
+        matches
+    }
+}
+
+/// Compiled patterns of a state, shared with its clones
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RegexCache(Rc<RefCell<HashMap<String, Rc<Regex>>>>);
+
+impl RegexCache {
+    /// Returns the compiled pattern, compiling it if it is not in the cache yet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedString` if the pattern is not a string, and `InvalidRegex` if it
+    /// cannot be compiled
+    ///
+    fn get(&self, pattern: Token) -> Result<Rc<Regex>, ProgramError> {
+        let pattern = String::try_from(pattern)?;
+        if let Some(regex) = self.0.borrow().get(&pattern) {
+            return Ok(regex.clone())
+        }
+        let regex = Rc::new(Regex::new(&pattern)?);
+        let mut cache = self.0.borrow_mut();
+        if cache.len() >= MAX_CACHED {
+            cache.clear();
+        }
+        cache.insert(pattern, regex.clone());
+        Ok(regex)
+    }
+}
+
+/// Checks whether a pattern matches anywhere in a string
+///
+/// # Arguments
+///
+/// * `token` - The string to search
+/// * `pattern` - The regular expression
+/// * `cache` - The compiled patterns of the state
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string, and `InvalidRegex` if
+/// the pattern is malformed
+///
+pub(crate) fn is_match(token: Token, pattern: Token, cache: &RegexCache) -> Result<Option<Token>, ProgramError> {
+    let regex = cache.get(pattern)?;
+    let chars = characters(token)?;
+    rt(Token::Bool(regex.find_at(&chars, 0).is_some()))
+}
+
+/// Finds the first match of a pattern in a string
+///
+/// # Arguments
+///
+/// * `token` - The string to search
+/// * `pattern` - The regular expression
+/// * `cache` - The compiled patterns of the state
+///
+/// # Returns
+///
+/// A list of the whole match followed by every capture group, where groups that did not
+/// take part in the match are empty strings, or an empty list if there is no match.
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string, and `InvalidRegex` if
+/// the pattern is malformed
+///
+pub(crate) fn first_match(token: Token, pattern: Token, cache: &RegexCache) -> Result<Option<Token>, ProgramError> {
+    let regex = cache.get(pattern)?;
+    let chars = characters(token)?;
+    rt(regex.find_at(&chars, 0).map_or(Token::List(Vec::new()), |groups| captures(&chars, &groups)))
+}
+
+/// Finds every non-overlapping match of a pattern in a string
+///
+/// # Returns
+///
+/// A list with a list of captures, as returned by `first_match`, for every match.
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string, and `InvalidRegex` if
+/// the pattern is malformed
+///
+pub(crate) fn all_matches(token: Token, pattern: Token, cache: &RegexCache) -> Result<Option<Token>, ProgramError> {
+    let regex = cache.get(pattern)?;
+    let chars = characters(token)?;
+    rt(Token::List(regex.find_all(&chars).iter().map(|groups| captures(&chars, groups)).collect()))
+}
+
+/// Replaces every match of a pattern in a string
+///
+/// In the replacement, `$0` stands for the whole match, `$1` to `$9` for the capture
+/// groups, and `$$` for a literal `$`.
+///
+/// # Arguments
+///
+/// * `token` - The string
+/// * `pattern` - The regular expression
+/// * `replacement` - The string to replace every match with
+/// * `cache` - The compiled patterns of the state
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if any of the operands is not a string, and `InvalidRegex`
+/// if the pattern is malformed or the replacement refers to a group that does not exist
+///
+pub(crate) fn replace(token: Token, pattern: Token, replacement: Token, cache: &RegexCache) -> Result<Option<Token>, ProgramError> {
+    let regex = cache.get(pattern)?;
+    let chars = characters(token)?;
+    let replacement = characters(replacement)?;
+    let mut result = String::new();
+    let mut last = 0;
+    for groups in regex.find_all(&chars) {
+        let (from, to) = groups[0].unwrap();
+        result.extend(&chars[last..from]);
+        let mut iter = replacement.iter().peekable();
+        while let Some(c) = iter.next() {
+            match (c, iter.peek().and_then(|d| d.to_digit(10))) {
+                ('$', Some(group)) => {
+                    iter.next();
+                    let group = groups.get(group as usize).ok_or(ProgramError::InvalidRegex)?;
+                    if let Some((from, to)) = group {
+                        result.extend(&chars[*from..*to]);
+                    }
+                },
+                ('$', None) if iter.next_if_eq(&&'$').is_some() => result.push('$'),
+                (c, _) => result.push(*c),
+            }
+        }
+        last = to;
+    }
+    result.extend(&chars[last..]);
+    rt(Token::String(result))
+}
+
+/// Splits a string on every match of a pattern
+///
+/// Empty matches at the start and end of the string do not produce empty parts.
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string, and `InvalidRegex` if
+/// the pattern is malformed
+///
+pub(crate) fn split(token: Token, pattern: Token, cache: &RegexCache) -> Result<Option<Token>, ProgramError> {
+    let regex = cache.get(pattern)?;
+    let chars = characters(token)?;
+    let mut parts = Vec::new();
+    let mut last = 0;
+    for groups in regex.find_all(&chars) {
+        let (from, to) = groups[0].unwrap();
+        if from == to && (from == 0 || from == chars.len()) {
+            continue
+        }
+        parts.push(Token::String(chars[last..from].iter().collect()));
+        last = to;
+    }
+    parts.push(Token::String(chars[last..].iter().collect()));
+    rt(Token::List(parts))
+}
+
+/// Helper function that extracts the characters of a string token
+fn characters(token: Token) -> Result<Vec<char>, ProgramError> {
+    Ok(String::try_from(token)?.chars().collect())
+}
+
+/// Helper function that turns the positions of the groups of a match into a list of strings
+fn captures(chars: &[char], groups: &[Option<(usize, usize)>]) -> Token {
+    Token::List(groups.iter()
+        .map(|group| Token::String(group.map_or(String::new(), |(from, to)| chars[from..to].iter().collect())))
+        .collect())
+}
+
+/// Helper function that checks whether a position lies between a word character and a
+/// character that is not part of a word
+fn is_boundary(chars: &[char], position: usize) -> bool {
+    let word = |c: Option<&char>| c.is_some_and(|c| c.is_alphanumeric() || *c == '_');
+    let before = position.checked_sub(1).and_then(|p| chars.get(p));
+    word(before) != word(chars.get(position))
+}
+
+/// Helper function that appends the instructions for a node to a program
+///
+/// # Errors
+///
+/// Returns `InvalidRegex` if the program gets longer than `MAX_PROGRAM`
+///
+fn compile(node: &Node, program: &mut Vec<Inst>) -> Result<(), ProgramError> {
+    if program.len() > MAX_PROGRAM {
+        return Err(ProgramError::InvalidRegex)
+    }
+    match node {
+        Node::Empty => {},
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::WordBoundary(negated) => program.push(Inst::WordBoundary(*negated)),
+        Node::Group(node, None) => compile(node, program)?,
+        Node::Group(node, Some(index)) => {
+            program.push(Inst::Save(index * 2));
+            compile(node, program)?;
+            program.push(Inst::Save(index * 2 + 1));
+        },
+        Node::Concat(nodes) => for node in nodes {
+            compile(node, program)?;
+        },
+        Node::Alternate(nodes) => {
+            // every alternative but the last tries itself first and backtracks to the next
+            let mut jumps = Vec::new();
+            for (i, node) in nodes.iter().enumerate() {
+                if i + 1 < nodes.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program)?;
+                    jumps.push(program.len());
+                    program.push(Inst::Jump(0));
+                    program[split] = Inst::Split(split + 1, program.len());
+                } else {
+                    compile(node, program)?;
+                }
+            }
+            for jump in jumps {
+                program[jump] = Inst::Jump(program.len());
+            }
+        },
+        Node::Repeat { node, min, max, greedy } => {
+            for _ in 0..*min {
+                compile(node, program)?;
+            }
+            let split = |program: &mut Vec<Inst>, at: usize, skip: usize| {
+                program[at] = if *greedy { Inst::Split(at + 1, skip) } else { Inst::Split(skip, at + 1) };
+            };
+            match max {
+                None => {
+                    let start = program.len();
+                    program.push(Inst::Jump(0));
+                    compile(node, program)?;
+                    program.push(Inst::Jump(start));
+                    split(program, start, program.len());
+                },
+                Some(max) => {
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Jump(0));
+                        compile(node, program)?;
+                    }
+                    for at in splits {
+                        split(program, at, program.len());
+                    }
+                },
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Recursive descent parser for patterns
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    groups: usize,
+}
+
+impl Parser {
+    /// Returns the next character without consuming it.
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Consumes the next character.
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    /// Consumes the next character if it is `c`.
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses alternatives separated by `|`.
+    fn alternation(&mut self) -> Result<Node, ProgramError> {
+        let mut nodes = vec![self.concatenation()?];
+        while self.eat('|') {
+            nodes.push(self.concatenation()?);
+        }
+        Ok(if nodes.len() == 1 { nodes.pop().unwrap() } else { Node::Alternate(nodes) })
+    }
+
+    /// Parses a sequence of quantified atoms, up to a `|` or `)`.
+    fn concatenation(&mut self) -> Result<Node, ProgramError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break
+            }
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(if nodes.is_empty() { Node::Empty } else { Node::Concat(nodes) })
+    }
+
+    /// Parses a single character, class, anchor or group.
+    fn atom(&mut self) -> Result<Node, ProgramError> {
+        match self.next() {
+            Some('.') => Ok(Node::Any),
+            Some('^') => Ok(Node::Start),
+            Some('$') => Ok(Node::End),
+            Some('[') => Ok(Node::Class(self.class()?)),
+            Some('(') => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(ProgramError::InvalidRegex)
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return Err(ProgramError::InvalidRegex)
+                }
+                Ok(Node::Group(Box::new(node), index))
+            },
+            Some('\\') => match self.next() {
+                Some('b') => Ok(Node::WordBoundary(false)),
+                Some('B') => Ok(Node::WordBoundary(true)),
+                Some(c) => Ok(Self::escape(c).map_or(Node::Char(c), Node::Class)),
+                None => Err(ProgramError::InvalidRegex)
+            },
+            Some('*' | '+' | '?' | '{') => Err(ProgramError::InvalidRegex),
+            Some(c) => Ok(Node::Char(c)),
+            None => Err(ProgramError::InvalidRegex)
+        }
+    }
+
+    /// Parses the escape after a `\`, other than a word boundary.
+    ///
+    /// # Returns
+    ///
+    /// The class of a class escape, or `None` for an escaped character.
+    ///
+    fn escape(c: char) -> Option<Class> {
+        match c {
+            'n' => Some(Class { negated: false, items: vec![ClassItem::Range('\n', '\n')] }),
+            't' => Some(Class { negated: false, items: vec![ClassItem::Range('\t', '\t')] }),
+            c => Class::perl(c)
+        }
+    }
+
+    /// Parses a character class after its `[`.
+    fn class(&mut self) -> Result<Class, ProgramError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        // a `]` right at the start is a literal
+        let mut first = true;
+        loop {
+            let low = match self.next() {
+                Some(']') if !first => break,
+                Some('\\') => match self.next() {
+                    Some(c) => match Self::escape(c) {
+                        Some(class) => {
+                            items.push(ClassItem::Class(class));
+                            first = false;
+                            continue
+                        },
+                        None => c,
+                    },
+                    None => return Err(ProgramError::InvalidRegex)
+                },
+                Some(c) => c,
+                None => return Err(ProgramError::InvalidRegex)
+            };
+            first = false;
+            if self.peek() == Some('-') && self.chars.get(self.position + 1).is_some_and(|c| *c != ']') {
+                self.position += 1;
+                let high = match self.next() {
+                    Some('\\') => self.next().ok_or(ProgramError::InvalidRegex)?,
+                    Some(c) => c,
+                    None => return Err(ProgramError::InvalidRegex)
+                };
+                if high < low {
+                    return Err(ProgramError::InvalidRegex)
+                }
+                items.push(ClassItem::Range(low, high));
+            } else {
+                items.push(ClassItem::Range(low, low));
+            }
+        }
+        Ok(Class { negated, items })
+    }
+
+    /// Parses the quantifiers after an atom, if any.
+    fn quantifier(&mut self, atom: Node) -> Result<Node, ProgramError> {
+        let (min, max) = if self.eat('*') {
+            (0, None)
+        } else if self.eat('+') {
+            (1, None)
+        } else if self.eat('?') {
+            (0, Some(1))
+        } else if self.eat('{') {
+            let min = self.number()?;
+            let max = match self.eat(',') {
+                true if self.peek() == Some('}') => None,
+                true => Some(self.number()?),
+                false => Some(min),
+            };
+            if !self.eat('}') || max.is_some_and(|max| max < min) {
+                return Err(ProgramError::InvalidRegex)
+            }
+            (min, max)
+        } else {
+            return Ok(atom)
+        };
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_)) {
+            return Err(ProgramError::InvalidRegex)
+        }
+        let greedy = !self.eat('?');
+        if matches!(self.peek(), Some('*' | '+' | '?' | '{')) {
+            return Err(ProgramError::InvalidRegex)
+        }
+        Ok(Node::Repeat { node: Box::new(atom), min, max, greedy })
+    }
+
+    /// Parses the number of a counted repetition.
+    fn number(&mut self) -> Result<u32, ProgramError> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        let digits: String = self.chars[start..self.position].iter().collect();
+        match digits.parse() {
+            Ok(count) if count <= MAX_REPEAT => Ok(count),
+            _ => Err(ProgramError::InvalidRegex)
+        }
+    }
+}
@@ -0,0 +1,425 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+use crate::error::ProgramError;
+use crate::frame;
+use crate::state::State;
+use crate::token::{rt, Token};
+
+/// The symbol that separates the parameters of a block from its body
+pub(crate) const ARROW: &str = "->";
+
+/// The variables of one scope, shared by every state that can see them
+type Frame = Rc<RefCell<HashMap<String, Token>>>;
+
+/// A chain of local frames, innermost first
+#[derive(Debug)]
+struct Env {
+    frame: Frame,
+    parent: Option<Rc<Env>>,
+}
+
+impl Env {
+    /// Returns the frames of the chain, innermost first.
+    fn frames(env: &Option<Rc<Env>>) -> impl Iterator<Item = &Frame> {
+        let mut next = env.as_deref();
+        std::iter::from_fn(move || {
+            let env = next?;
+            next = env.parent.as_deref();
+            Some(&env.frame)
+        })
+    }
+}
+
+/// The variables visible to a program
+///
+/// The global variables are at the bottom, with a local scope on top for every call
+/// of a block that is still running. A scope is a chain of local frames, with a frame
+/// for the parameters and `let` variables of the call, if it has any, and then the
+/// frames the block was created in. The frames are shared with the closures that
+/// captured them rather than copied, so assignments made by one are seen by the others.
+///
+#[derive(Debug, Default)]
+pub(crate) struct Scopes {
+    globals: Frame,
+    /// The local scopes, innermost last, each entered by a block that is still running
+    locals: Vec<Option<Rc<Env>>>,
+}
+
+impl Clone for Scopes {
+    /// Copies the global variables, so that assignments made by the copy are not seen
+    /// by the original and the other way around.
+    fn clone(&self) -> Self {
+        let globals = Rc::new(RefCell::new(self.globals.borrow().clone()));
+        Self { globals, locals: self.locals.clone() }
+    }
+}
+
+impl Scopes {
+    /// Helper function that returns the local frames of the innermost scope
+    fn env(&self) -> &Option<Rc<Env>> {
+        self.locals.last().unwrap_or(&None)
+    }
+
+    /// Checks whether any local scope is running.
+    pub(crate) fn is_local(&self) -> bool {
+        self.env().is_some()
+    }
+
+    /// Looks up a local variable, innermost frame first.
+    pub(crate) fn local(&self, name: &str) -> Option<Token> {
+        lookup(self.env(), name)
+    }
+
+    /// Looks up a global variable.
+    pub(crate) fn global(&self, name: &str) -> Option<Token> {
+        self.globals.borrow().get(name).cloned()
+    }
+
+    /// Checks whether a variable is visible in any scope.
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.local(name).is_some() || self.globals.borrow().contains_key(name)
+    }
+
+    /// Sets a global variable.
+    pub(crate) fn set_global(&self, name: &str, value: Token) {
+        self.globals.borrow_mut().insert(name.to_string(), value);
+    }
+
+    /// Assigns a value to a variable, like `:=` does.
+    ///
+    /// The value goes to the innermost frame in which the variable is defined, or
+    /// to the global scope if it is not defined yet.
+    ///
+    pub(crate) fn assign(&self, name: String, value: Token) {
+        let frame = Env::frames(self.env())
+            .find(|frame| frame.borrow().contains_key(&name))
+            .unwrap_or(&self.globals);
+        frame.borrow_mut().insert(name, value);
+    }
+
+    /// Defines a variable in the innermost frame, like `let` does, shadowing any
+    /// variable of the same name in the frames around it.
+    pub(crate) fn define(&self, name: String, value: Token) {
+        let frame = self.env().as_ref().map_or(&self.globals, |env| &env.frame);
+        frame.borrow_mut().insert(name, value);
+    }
+
+    /// Enters a new local scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `variables` - The variables of the new frame
+    /// * `parent` - The frames around the new one
+    ///
+    fn enter(&mut self, variables: HashMap<String, Token>, parent: Option<Rc<Env>>) {
+        let env = Env { frame: Rc::new(RefCell::new(variables)), parent };
+        self.locals.push(Some(Rc::new(env)));
+    }
+
+    /// Enters a local scope that has no frame of its own, only the frames around it.
+    fn reuse(&mut self, parent: Option<Rc<Env>>) {
+        self.locals.push(parent);
+    }
+
+    /// Leaves the innermost local scope, once the block that entered it is done.
+    pub(crate) fn leave(&mut self) {
+        self.locals.pop();
+    }
+
+    /// Returns the visible variables, with the innermost definition of every name.
+    pub(crate) fn visible(&self) -> HashMap<String, Token> {
+        let mut variables = self.globals.borrow().clone();
+        let frames: Vec<&Frame> = Env::frames(self.env()).collect();
+        for frame in frames.into_iter().rev() {
+            variables.extend(frame.borrow().iter().map(|(k, v)| (k.clone(), v.clone())));
+        }
+        variables
+    }
+}
+
+/// A block together with the local variables that were visible where it was created
+///
+/// Calling a closure runs its body with those variables rather than the ones visible
+/// where it is called, so a closure returned from a function still sees the arguments
+/// of that function. The variables are shared, not copied, so assignments to them are
+/// seen by every closure that captured them.
+///
+#[derive(Clone)]
+pub struct Closure {
+    body: Rc<[Token]>,
+    env: Option<Rc<Env>>,
+}
+
+impl Closure {
+    /// Returns the instructions of the closure.
+    pub fn body(&self) -> &[Token] {
+        &self.body
+    }
+
+    /// Checks whether the closure behaves like a block, which is the case if it has
+    /// the same body and captured none of the variables that body refers to.
+    pub(crate) fn eq_block(&self, block: &[Token]) -> bool {
+        *self.body == *block && names(&self.body).into_iter().all(|name| lookup(&self.env, name).is_none())
+    }
+}
+
+impl PartialEq for Closure {
+    /// Closures are equal if they have the same body and the variables it refers to
+    /// have the same values in the variables each of them captured.
+    fn eq(&self, other: &Self) -> bool {
+        if self.body != other.body {
+            return false
+        }
+        if same_env(&self.env, &other.env) {
+            return true
+        }
+        names(&self.body).into_iter().all(|name| {
+            match (lookup(&self.env, name), lookup(&other.env, name)) {
+                // a closure that refers to itself, such as a recursive local function
+                (Some(Token::Closure(x)), Some(Token::Closure(y))) if same_env(&x.env, &self.env) && same_env(&y.env, &other.env) => x.body == y.body,
+                (x, y) => x == y
+            }
+        })
+    }
+}
+
+/// Helper function that checks whether two chains of frames are the same
+fn same_env(x: &Option<Rc<Env>>, y: &Option<Rc<Env>>) -> bool {
+    match (x, y) {
+        (Some(x), Some(y)) => Rc::ptr_eq(x, y),
+        (x, y) => x.is_none() && y.is_none(),
+    }
+}
+
+/// Helper function that looks up a variable in a chain of frames, innermost first
+fn lookup(env: &Option<Rc<Env>>, name: &str) -> Option<Token> {
+    Env::frames(env).find_map(|frame| frame.borrow().get(name).cloned())
+}
+
+/// Helper function that returns the names a block refers to, including those in the
+/// blocks, lists and maps inside it
+fn names(body: &[Token]) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut pending: Vec<&Token> = body.iter().collect();
+    while let Some(token) = pending.pop() {
+        match token {
+            Token::Symbol(name, _) => names.push(name.as_str()),
+            Token::Block(tokens) => pending.extend(tokens.iter()),
+            Token::List(items) => pending.extend(items.iter()),
+            Token::Map(entries) => pending.extend(entries.iter().map(|(_, value)| value)),
+            _ => ()
+        }
+    }
+    names
+}
+
+impl PartialOrd for Closure {
+    /// Closures are not ordered, they are only equal or not.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        (self == other).then_some(Ordering::Equal)
+    }
+}
+
+impl fmt::Debug for Closure {
+    /// Formats the body only, as the captured variables may contain the closure itself.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Closure").field("body", &self.body).finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for Closure {
+    /// Formats the closure like the block it was created from, e.g. `{ n + }`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Token::Block(self.body.clone()))
+    }
+}
+
+/// Returns the names of the parameters of a block, such as `a` and `b` for
+/// `{ a b -> a b + }`
+///
+/// # Returns
+///
+/// The names, or `None` if the block has no `->`. The body of the block starts right
+/// after them and the `->`.
+///
+/// # Errors
+///
+/// Returns `ExpectedVariable` if anything but a symbol comes before the `->`
+///
+pub(crate) fn parameters(block: &[Token]) -> Result<Option<Vec<String>>, ProgramError> {
+    let arrow = block.iter().position(|token| matches!(token, Token::Symbol(s, _) if s == ARROW));
+    let Some(arrow) = arrow else {
+        return Ok(None)
+    };
+    let names = block[..arrow].iter().map(|token| match token {
+        Token::Symbol(name, _) => Ok(name.clone()),
+        _ => Err(ProgramError::ExpectedVariable)
+    }).collect::<Result<Vec<String>, ProgramError>>()?;
+    Ok(Some(names))
+}
+
+/// Calls a block
+///
+/// The body runs in a new local scope that lasts until it is done, so that variables
+/// defined with `let` do not escape it. The arguments of any named parameters are
+/// taken from the stack, the deepest one for the first parameter, and bound in it.
+///
+/// # Arguments
+///
+/// * `body` - The block
+/// * `state` - The state to run the body in
+///
+/// # Errors
+///
+/// Returns `StackEmpty` if there are fewer values on the stack than parameters, and
+/// `ExpectedVariable` if the parameters are malformed
+///
+pub(crate) fn call(body: Rc<[Token]>, state: &mut State) -> Result<Option<Token>, ProgramError> {
+    let (names, start) = split(&body)?;
+    // a call at the end of a block leaves it first, so its frames are not captured
+    state.leave_finished();
+    let parent = state.scopes.env().clone();
+    enter(names, body, start, parent, state)
+}
+
+/// Calls a function by its name
+///
+/// The body gets a scope of its own that does not see the local variables of the
+/// caller, only the global ones, as the function was not defined in that scope. A
+/// closure sees the variables it captured instead.
+///
+/// # Errors
+///
+/// Returns `StackEmpty` if there are fewer values on the stack than parameters, and
+/// `ExpectedVariable` if the parameters are malformed
+///
+pub(crate) fn call_function(function: Token, state: &mut State) -> Result<Option<Token>, ProgramError> {
+    match function {
+        Token::Block(body) => {
+            let (names, start) = split(&body)?;
+            enter(names, body, start, None, state)
+        },
+        function => function.exec(state)
+    }
+}
+
+/// Captures the local variables visible in a state in a closure
+///
+/// # Arguments
+///
+/// * `body` - The instructions of the block
+/// * `state` - The state whose variables are captured
+///
+pub(crate) fn capture(body: Rc<[Token]>, state: &State) -> Token {
+    Token::Closure(Closure { body, env: state.scopes.env().clone() })
+}
+
+/// Turns a block into a closure if it refers to any local variable visible in a state,
+/// and leaves it a block otherwise.
+///
+/// # Arguments
+///
+/// * `body` - The instructions of the block
+/// * `state` - The state whose variables are captured
+///
+pub(crate) fn enclose(body: Rc<[Token]>, state: &State) -> Token {
+    let env = state.scopes.env();
+    if env.is_some() && names(&body).into_iter().any(|name| lookup(env, name).is_some()) {
+        capture(body, state)
+    } else {
+        Token::Block(body)
+    }
+}
+
+/// Creates a closure from the block on top of the stack, `{ ... } closure`
+///
+/// # Errors
+///
+/// Returns `ExpectedQuotation` if the operand is neither a block nor a closure
+///
+pub(crate) fn closure(token: Token, state: &mut State) -> Result<Option<Token>, ProgramError> {
+    match token {
+        Token::Block(body) => rt(capture(body, state)),
+        Token::Closure(_) => rt(token),
+        _ => Err(ProgramError::ExpectedQuotation)
+    }
+}
+
+/// Calls a closure
+///
+/// The body runs in a new local scope around the variables the closure captured,
+/// with its named parameters, if any, taken from the stack.
+///
+/// # Errors
+///
+/// Returns `StackEmpty` if there are fewer values on the stack than parameters, and
+/// `ExpectedVariable` if the parameters are malformed
+///
+pub(crate) fn call_closure(closure: Closure, state: &mut State) -> Result<Option<Token>, ProgramError> {
+    let (names, start) = split(&closure.body)?;
+    enter(names, closure.body, start, closure.env, state)
+}
+
+/// Helper function that returns the names of the parameters of a block, if any, and
+/// the index of the first instruction of its body
+fn split(block: &[Token]) -> Result<(Vec<String>, usize), ProgramError> {
+    Ok(match parameters(block)? {
+        Some(names) => {
+            let start = names.len() + 1;
+            (names, start)
+        },
+        None => (Vec::new(), 0)
+    })
+}
+
+/// Helper function that binds the arguments of a call in a new scope and runs the
+/// block from the instruction at `start`, right after its parameters
+///
+/// A block without parameters that defines no variables with `let` gets no frame of
+/// its own, only the frames around it, so that calling it does not make lookups slower.
+///
+fn enter(names: Vec<String>, body: Rc<[Token]>, start: usize, parent: Option<Rc<Env>>, state: &mut State) -> Result<Option<Token>, ProgramError> {
+    if state.stack.len() < names.len() {
+        return Err(ProgramError::StackEmpty)
+    }
+    let arguments = state.stack.split_off(state.stack.len() - names.len());
+    // a call at the end of a block leaves it first, so the scope is entered after that
+    state.leave_finished();
+    if names.is_empty() && !defines(&body[start..]) {
+        state.scopes.reuse(parent);
+    } else {
+        state.scopes.enter(names.into_iter().zip(arguments).collect(), parent);
+    }
+    state.frames.push(frame::Frame::Code { body, ip: start, scoped: true, boundary: false });
+    Ok(None)
+}
+
+/// Helper function that checks whether a block defines local variables with `let`
+fn defines(body: &[Token]) -> bool {
+    body.iter().any(|token| matches!(token, Token::Symbol(s, _) if s == "let"))
+}
+
+/// Defines a local variable, `value let name`
+///
+/// The name is the instruction after the word, and is not resolved, so that it may
+/// shadow a variable of an enclosing scope.
+///
+/// # Errors
+///
+/// Returns `InstructionListEmpty` if there is no name, and `ExpectedVariable` if it is
+/// not a symbol
+///
+pub(crate) fn define(state: &mut State) -> Result<Option<Token>, ProgramError> {
+    let value = state.stack_pop()?;
+    match state.next_instruction() {
+        Some(Token::Symbol(name, _)) => {
+            state.scopes.define(name, value);
+            Ok(None)
+        },
+        Some(_) => Err(ProgramError::ExpectedVariable),
+        None => Err(ProgramError::InstructionListEmpty)
+    }
+}
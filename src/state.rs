@@ -1,21 +1,60 @@
 use std::fmt;
+use std::cell::Cell;
 use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::time::Instant;
+use crate::bigint::Overflow;
+use crate::rational::Division;
+use crate::decimal::DecimalContext;
+use crate::builtins::Registry;
 use crate::token::Token;
 use crate::error::ProgramError;
-use crate::read_input;
+use crate::capabilities::Capabilities;
+use crate::limits::Limits;
+use crate::parser::{LexerMode, Span};
+use crate::streams::Streams;
+use crate::regex::RegexCache;
+use crate::scope::{self, Scopes};
+use crate::frame::{Frame, Task};
 
 /// State holds the current state of the parsed/executed program
 ///
-/// In REPL mode the state is considered global. However it may make
-/// secondary temporary states for calculating mapped values, etc.
-///
+/// In REPL mode the state is considered global. Running a block pushes a frame onto
+/// its call stack rather than creating another state, and words that call quotations,
+/// such as `map`, wait for their results in frames of their own, so a program and
+/// everything it calls run in this one state.
 ///
 #[derive(Debug, Clone)]
 pub struct State {
     pub(crate) stack: Vec<Token>,
+    /// Instructions of the next program to run, moved to a frame when it starts
     pub(crate) instruction_set: VecDeque<Token>,
-    pub(crate) bindings: HashMap<String, Token>,
-    pub(crate) functions: HashMap<String, Token>
+    /// Blocks being run and words waiting for their quotations, innermost last
+    pub(crate) frames: Vec<Frame>,
+    /// Global variables, and the local variables of the blocks being run
+    pub(crate) scopes: Scopes,
+    pub(crate) functions: HashMap<String, Token>,
+    pub(crate) lexer_mode: LexerMode,
+    pub(crate) trace: bool,
+    pub(crate) limits: Limits,
+    /// Whether integer arithmetic that overflows an `Int` fails or gives a `BigInt`
+    pub(crate) overflow: Overflow,
+    /// Whether `/` on two integers gives a `Float` or an exact `Rational`
+    pub(crate) division: Division,
+    /// Scale and rounding mode of the quotient when dividing decimals
+    pub(crate) decimals: DecimalContext,
+    /// Side effects the program may perform
+    pub(crate) capabilities: Capabilities,
+    /// Input and output of `print`, `read` and tracing
+    pub(crate) streams: Streams,
+    /// Number of instructions executed by the current program so far
+    pub(crate) steps: Cell<u64>,
+    /// Point in time at which the program exceeds `limits.timeout`
+    pub(crate) deadline: Option<Instant>,
+    /// Words implemented in Rust, shared with clones of the state until modified
+    pub(crate) builtins: Rc<Registry>,
+    /// Compiled regular expressions
+    pub(crate) regexes: RegexCache,
 }
 
 // Implement the Display trait for the State struct.
@@ -39,6 +78,12 @@ impl fmt::Display for State {
     }
 }
 
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl State {
     /// Creates a new `State` instance with empty stack, instruction set, bindings, and functions.
     ///
@@ -49,28 +94,106 @@ impl State {
     pub fn new() -> Self {
         let stack: Vec<Token> = Vec::new();
         let instruction_set: VecDeque<Token> = VecDeque::new();
-        let bindings: HashMap<String, Token> = HashMap::new();
+        let scopes = Scopes::default();
         let functions: HashMap<String, Token> = HashMap::new();
-        Self { stack, instruction_set, bindings, functions }
+        let lexer_mode = LexerMode::default();
+        let steps = Cell::new(0);
+        let builtins = Rc::new(Registry::standard());
+        let limits = Limits::default();
+        let capabilities = Capabilities::default();
+        let streams = Streams::stdio();
+        Self { stack, instruction_set, frames: Vec::new(), scopes, functions, lexer_mode, trace: false, limits, overflow: Overflow::default(), division: Division::default(), decimals: DecimalContext::default(), capabilities, streams, steps, deadline: None, builtins, regexes: RegexCache::default() }
+    }
+
+    /// Starts measuring the resources used by a new program.
+    ///
+    /// Resets the step counter, and starts the clock for the timeout.
+    ///
+    pub fn start_clock(&mut self) {
+        self.steps.set(0);
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    /// Counts an executed instruction against the step limit, and checks whether the
+    /// program has run out of time or was cancelled.
+    ///
+    /// Instructions run by the quotations of words such as `map` count as well, as
+    /// they run in frames of the same state.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the program may continue, or a `StepLimitExceeded`, `TimeLimitExceeded`
+    /// or `Cancelled` error.
+    ///
+    pub fn count_step(&self) -> Result<(), ProgramError> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if self.limits.max_steps.is_some_and(|max| steps > max) {
+            Err(ProgramError::StepLimitExceeded)
+        } else if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            Err(ProgramError::TimeLimitExceeded)
+        } else if self.limits.cancel.as_ref().is_some_and(|flag| flag.is_cancelled()) {
+            Err(ProgramError::Cancelled)
+        } else {
+            Ok(())
+        }
     }
 
-    /// Creates a new `State` instance based on an existing `State`,
-    /// copying its bindings and functions.
+    /// Applies the overflow policy of the state to the result of integer arithmetic.
     ///
     /// # Arguments
     ///
-    /// * `other` - The existing `State` to copy bindings and functions from.
+    /// * `result` - The result of an arithmetic operation
     ///
     /// # Returns
     ///
-    /// A new `State` instance with the same bindings and functions as `other`.
+    /// The result unchanged, or an `IntegerOverflow` error if it is a `BigInt` and the
+    /// state does not promote overflowing integers.
     ///
-    pub fn from(other: &State) -> Self {
-        let stack: Vec<Token> = Vec::new();
-        let instruction_set: VecDeque<Token> = VecDeque::new();
-        let bindings = other.bindings.clone();
-        let functions = other.functions.clone();
-        Self { stack, instruction_set, bindings, functions }
+    pub fn check_overflow(&self, result: Result<Option<Token>, ProgramError>) -> Result<Option<Token>, ProgramError> {
+        match result {
+            Ok(Some(Token::BigInt(_))) if self.overflow == Overflow::Error => Err(ProgramError::IntegerOverflow),
+            result => result
+        }
+    }
+
+    /// Checks the size of the stack, and of the value on top of it, against the limits.
+    ///
+    /// Only the top of the stack is inspected, as that is where new values end up.
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` if the sizes are within the limits, or a `StackLimitExceeded` or
+    /// `CollectionLimitExceeded` error.
+    ///
+    pub fn check_size(&self) -> Result<(), ProgramError> {
+        if self.limits.max_stack_depth.is_some_and(|max| self.stack.len() > max) {
+            return Err(ProgramError::StackLimitExceeded)
+        }
+        let size = match self.stack.last() {
+            Some(Token::List(x)) => x.len(),
+            Some(Token::Block(x)) => x.len(),
+            Some(Token::String(x)) => x.len(),
+            Some(Token::Map(x)) => x.len(),
+            _ => 0
+        };
+        match self.limits.max_collection_size {
+            Some(max) if size > max => Err(ProgramError::CollectionLimitExceeded),
+            _ => Ok(())
+        }
+    }
+
+    /// Returns the words implemented in Rust.
+    pub fn builtins(&self) -> &Registry {
+        &self.builtins
+    }
+
+    /// Returns the words implemented in Rust for modification.
+    ///
+    /// The registry is copied first if it is shared with another state.
+    ///
+    pub fn builtins_mut(&mut self) -> &mut Registry {
+        Rc::make_mut(&mut self.builtins)
     }
 
     /// Returns the current length of the stack.
@@ -79,10 +202,15 @@ impl State {
     ///
     /// The number of elements in the stack.
     ///
-    pub fn len(&mut self) -> usize {
+    pub fn len(&self) -> usize {
         self.stack.len()
     }
 
+    /// Checks whether the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
     /// Pushes a `Token` onto the stack.
     ///
     /// # Arguments
@@ -128,6 +256,34 @@ impl State {
         Ok(None)
     }
 
+    /// Copies the second element of the stack to the top, `x y -- x y x`.
+    ///
+    /// # Returns
+    ///
+    /// The copy, to be pushed onto the stack, or a `ProgramError::StackEmpty` error if
+    /// the stack has fewer than two elements.
+    ///
+    pub fn stack_over(&mut self) -> Result<Option<Token>, ProgramError> {
+        match self.stack.len() {
+            n if n >= 2 => Ok(Some(self.stack[n - 2].clone())),
+            _ => Err(ProgramError::StackEmpty)
+        }
+    }
+
+    /// Moves the third element of the stack to the top, `x y z -- y z x`.
+    ///
+    /// # Returns
+    ///
+    /// The removed element, to be pushed onto the stack, or a `ProgramError::StackEmpty`
+    /// error if the stack has fewer than three elements.
+    ///
+    pub fn stack_rot(&mut self) -> Result<Option<Token>, ProgramError> {
+        match self.stack.len() {
+            n if n >= 3 => Ok(Some(self.stack.remove(n - 3))),
+            _ => Err(ProgramError::StackEmpty)
+        }
+    }
+
     /// Returns the top element of the stack without removing it.
     ///
     /// # Returns
@@ -144,6 +300,21 @@ impl State {
         }
     }
 
+    /// Returns the type names of the topmost elements of the stack.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - How many elements to look at, starting from the top.
+    ///
+    /// # Returns
+    ///
+    /// The type names of at most `count` elements, deepest first.
+    ///
+    pub fn stack_types(&self, count: usize) -> Vec<&'static str> {
+        let start = self.stack.len().saturating_sub(count);
+        self.stack[start..].iter().map(|t| t.type_name()).collect()
+    }
+
     /// Converts the instruction set stored in a `State` instance into a `Vec<Token>`.
     ///
     /// # Returns
@@ -155,34 +326,118 @@ impl State {
 
     }
 
-    /// Pops and returns the next instruction from the front of the instruction set.
+    /// Takes the next instruction of the innermost block being run.
+    ///
+    /// Blocks that are done are left on the way, so a word at the end of a block takes
+    /// the instruction after the call of the block. Words waiting for a quotation and
+    /// the start of a program are never passed.
+    ///
+    /// # Returns
+    ///
+    /// The instruction, or `None` if there is none.
+    ///
+    pub(crate) fn next_instruction(&mut self) -> Option<Token> {
+        loop {
+            match self.frames.last_mut() {
+                Some(Frame::Code { body, ip, .. }) if *ip < body.len() => {
+                    *ip += 1;
+                    return Some(body[*ip - 1].clone())
+                },
+                Some(Frame::Code { boundary: false, .. }) => self.pop_frame(),
+                _ => return None
+            }
+        }
+    }
+
+    /// Runs a block by pushing a frame for it.
+    ///
+    /// Blocks that are done are left first, so that a call at the end of a block does
+    /// not make the call stack grow.
+    ///
+    pub(crate) fn push_frame(&mut self, frame: Frame) {
+        self.leave_finished();
+        self.frames.push(frame);
+    }
+
+    /// Leaves the blocks on top of the call stack that have no instructions left,
+    /// along with their local scopes. The start of a program is never left.
+    pub(crate) fn leave_finished(&mut self) {
+        while let Some(Frame::Code { body, ip, boundary: false, .. }) = self.frames.last() {
+            if *ip < body.len() {
+                break
+            }
+            self.pop_frame();
+        }
+    }
+
+    /// Removes the innermost frame, leaving the local scope it entered, if any.
+    pub(crate) fn pop_frame(&mut self) {
+        if let Some(Frame::Code { scoped: true, .. }) = self.frames.pop() {
+            self.scopes.leave();
+        }
+    }
+
+    /// Calls a quotation.
+    ///
+    /// Blocks and closures are executed like `exec` does, and any other token is run
+    /// as a single instruction, e.g. `+` or the name of a function.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `exec`
+    ///
+    pub(crate) fn call(&mut self, quotation: Token) -> Result<(), ProgramError> {
+        match quotation {
+            Token::Block(_) | Token::Closure(_) => quotation.exec(self).map(|_| ()),
+            token => {
+                self.push_frame(Frame::Code { body: Rc::from(vec![token]), ip: 0, scoped: false, boundary: false });
+                Ok(())
+            }
+        }
+    }
+
+    /// Starts a word that calls quotations, which the interpreter resumes after the
+    /// word returns.
+    pub(crate) fn start_task(&mut self, task: Task) {
+        self.frames.push(Frame::Task { task: Box::new(task), saved: None, origin: None });
+    }
+
+    /// Pops and returns the next instruction of the innermost block being run.
     ///
     /// If the instruction is a symbol, it resolves the symbol to its corresponding
     /// binding or function.
     ///
     /// # Returns
     ///
-    /// The next `Token` instruction if the instruction set is not empty,
-    /// or a `ProgramError::StackEmpty` error if the instruction set is empty.
+    /// The next `Token` instruction if there is one, or a
+    /// `ProgramError::InstructionListEmpty` error if there is none.
     ///
     pub fn instruction_pop(&mut self, exec: bool) -> Result<Token, ProgramError> {
-        match self.instruction_set.pop_front() {
+        match self.next_instruction() {
             // Some(Token::Symbol(op)) => Ok(self.resolve_symbol(op.as_str())?.unwrap()),
-            Some(Token::Symbol(op)) => {
+            Some(Token::Symbol(op, span)) => {
                 match self.resolve_symbol(op.as_str(), exec)? {
                     Some(binding) => Ok(binding),
-                    None => Ok(Token::Symbol(op))
+                    None => Ok(Token::Symbol(op, span))
                 }
                 // Ok(self.resolve_symbol(op.as_str())?.unwrap())
             },
+            // the block may run after the scope it was written in is left
+            Some(Token::Block(body)) if self.scopes.is_local() => Ok(scope::enclose(body, self)),
             Some(x) => Ok(x),
             None => Err(ProgramError::InstructionListEmpty)
         }
     }
 
+    /// Checks whether a symbol names a function or a binding.
+    pub fn is_bound(&self, name: &str) -> bool {
+        self.functions.contains_key(name) || self.scopes.contains(name)
+    }
+
     /// Resolves a given symbol to its corresponding binding or function.
     ///
-    /// Functions take precedence over bindings.
+    /// Local variables take precedence over functions, which take precedence over
+    /// global bindings.
     ///
     /// # Arguments
     ///
@@ -194,20 +449,22 @@ impl State {
     /// or functions, or the original symbol as a `Token::Symbol` if not found.
     ///
     pub fn resolve_symbol(&mut self, op: &str, exec: bool) -> Result<Option<Token>, ProgramError> {
-        // checking if there is a binding or a function. Function will take precedence
+        // checking if there is a binding or a function. Locals shadow functions, which
+        // shadow global bindings
+        if let Some(t) = self.scopes.local(op) {
+            return Ok(Some(t))
+        }
         if let Some(t) = self.functions.get(op) {
             return if exec {
-                self.instruction_set.push_front(Token::Symbol("exec".to_string()));
-                self.instruction_set.push_front(t.clone());
-                Ok(None)
+                scope::call_function(t.clone(), self)
             } else {
                 Ok(Some(t.clone()))
             }
         }
 
-        match self.bindings.get(op) {
-            Some(t) => Ok(Some(t.clone())),
-            None => Ok(Some(Token::Symbol(op.to_string())))
+        match self.scopes.global(op) {
+            Some(t) => Ok(Some(t)),
+            None => Ok(Some(Token::Symbol(op.to_string(), Span::default())))
         }
     }
 
@@ -219,7 +476,7 @@ impl State {
     /// or a `ProgramError::InstructionListEmpty` error if the instruction set is empty.
     ///
     pub fn stack_add_unbound(&mut self) -> Result<Option<Token>, ProgramError> {
-        match self.instruction_set.pop_front() {
+        match self.next_instruction() {
             Some(t) => Ok(Some(t)),
             None => Err(ProgramError::InstructionListEmpty)
         }
@@ -227,6 +484,8 @@ impl State {
 
     /// Displays either the bindings or functions depending on the provided operator.
     ///
+    /// The names are written to the output stream in alphabetical order.
+    ///
     /// # Arguments
     ///
     /// * `op` - The operator to determine the type of display.
@@ -235,30 +494,36 @@ impl State {
     ///
     /// # Returns
     ///
-    /// `Ok(None)` if the display was successful, or a `ProgramError::ExpectedSymbol`
-    /// error if an unrecognized operator was provided.
+    /// `Ok(None)` if the display was successful, a `ProgramError::ExpectedSymbol`
+    /// error if an unrecognized operator was provided, or a `ProgramError::IoError`
+    /// if the output cannot be written.
     ///
     pub fn display(&self, op: &str) -> Result<Option<Token>, ProgramError> {
+        let variables = self.scopes.visible();
         let (header, items) = match op {
-            ":b" => ("bindings", &self.bindings),
+            ":b" => ("bindings", &variables),
             ":f" => ("functions", &self.functions),
             _ => Err(ProgramError::ExpectedSymbol)?
         };
-        print!("{} : ", header);
-        for (key, value) in items {
-            print!("[ {} = {} ] ", key, value);
+        let mut names: Vec<&String> = items.keys().collect();
+        names.sort();
+        let mut line = format!("{} : ", header);
+        for name in names {
+            line.push_str(format!("[ {} = {} ] ", name, items[name]).as_str());
         }
-        println!();
+        self.streams.write_line(line.as_str()).map_err(|_| ProgramError::IoError)?;
         Ok(None)
     }
 
-    /// Reads input from the user and returns it as a `Token::String`.
+    /// Reads a line from the input stream and returns it as a `Token::String`.
     ///
     /// # Returns
     ///
-    /// `Ok(Some(Token))` containing the user input as a `Token::String`.
+    /// `Ok(Some(Token))` containing the line as a `Token::String`, which is empty at
+    /// the end of the input, or a `ProgramError::IoError` if the input cannot be read.
     ///
     pub fn read(&self) -> Result<Option<Token>, ProgramError> {
-        Ok(Some(Token::String(read_input("input"))))
+        let line = self.streams.read_line().map_err(|_| ProgramError::IoError)?;
+        Ok(Some(Token::String(line)))
     }
 }
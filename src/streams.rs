@@ -0,0 +1,146 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+/// A readable stream shared between a state and its clones
+pub type Input = Rc<RefCell<dyn Read>>;
+
+/// A writable stream shared between a state and its clones
+pub type Output = Rc<RefCell<dyn Write>>;
+
+/// The streams that programs use to communicate with the outside world
+///
+/// `print`, `:b` and `:f` write to `output`, `read` reads lines from `input`, and
+/// tracing writes to `error`. By default these are the standard streams of the
+/// process, but tests and host applications can provide their own.
+///
+#[derive(Clone)]
+pub struct Streams {
+    pub input: Input,
+    pub output: Output,
+    pub error: Output,
+    /// Written to `output` before `read` waits for a line, if set
+    pub prompt: Option<String>,
+}
+
+impl fmt::Debug for Streams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Streams").field("prompt", &self.prompt).finish_non_exhaustive()
+    }
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self::stdio()
+    }
+}
+
+impl Streams {
+    /// Creates streams connected to the standard input, output and error of the process.
+    pub fn stdio() -> Self {
+        Self::new(io::stdin(), io::stdout(), io::stderr())
+    }
+
+    /// Creates streams from the given reader and writers, without a prompt.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Where `read` reads lines from
+    /// * `output` - Where `print`, `:b` and `:f` write to
+    /// * `error` - Where tracing writes to
+    ///
+    pub fn new<R, W, E>(input: R, output: W, error: E) -> Self
+        where R: Read + 'static, W: Write + 'static, E: Write + 'static {
+        Self {
+            input: Rc::new(RefCell::new(input)),
+            output: Rc::new(RefCell::new(output)),
+            error: Rc::new(RefCell::new(error)),
+            prompt: None,
+        }
+    }
+
+    /// Reads a line from the input, after writing the prompt if there is one.
+    ///
+    /// The input is read byte by byte, so that nothing after the line is consumed.
+    ///
+    /// # Returns
+    ///
+    /// The line without its line ending, which is empty at the end of the input
+    ///
+    /// # Errors
+    ///
+    /// Returns the underlying error if a stream cannot be read or written
+    ///
+    pub fn read_line(&self) -> io::Result<String> {
+        if let Some(prompt) = &self.prompt {
+            let mut output = self.output.borrow_mut();
+            write!(output, "{} > ", prompt)?;
+            output.flush()?;
+        }
+        let mut input = self.input.borrow_mut();
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        while input.read(&mut byte)? == 1 && byte[0] != b'\n' {
+            line.push(byte[0]);
+        }
+        let line = String::from_utf8_lossy(&line);
+        Ok(line.trim_end_matches('\r').to_string())
+    }
+
+    /// Writes a line to the output.
+    ///
+    /// # Errors
+    ///
+    /// Returns the underlying error if the output cannot be written
+    ///
+    pub fn write_line(&self, line: &str) -> io::Result<()> {
+        writeln!(self.output.borrow_mut(), "{}", line)
+    }
+
+    /// Writes a line to the error stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns the underlying error if the error stream cannot be written
+    ///
+    pub fn write_error_line(&self, line: &str) -> io::Result<()> {
+        writeln!(self.error.borrow_mut(), "{}", line)
+    }
+}
+
+/// An in-memory output stream whose contents can be read after the program ran
+///
+/// Clones share the same buffer, so one clone can be handed to `Streams::new`
+/// while another is kept to inspect what the program wrote.
+///
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns everything written so far, with invalid UTF-8 replaced.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).to_string()
+    }
+
+    /// Removes everything written so far.
+    pub fn clear(&self) {
+        self.0.borrow_mut().clear();
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
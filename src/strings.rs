@@ -0,0 +1,188 @@
+use crate::error::ProgramError;
+use crate::token::{rt, Token};
+
+/// Applies a transformation to a string, such as `str::trim`
+///
+/// # Arguments
+///
+/// * `token` - The string to transform
+/// * `function` - The transformation
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if the operand is not a string
+///
+pub(crate) fn transform(token: Token, function: fn(&str) -> String) -> Result<Option<Token>, ProgramError> {
+    rt(Token::String(function(string(token)?.as_str())))
+}
+
+/// Tests a string against a pattern with a predicate, such as `str::starts_with`
+///
+/// # Arguments
+///
+/// * `token` - The string to test
+/// * `pattern` - The string to look for
+/// * `function` - The predicate
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string
+///
+pub(crate) fn test(token: Token, pattern: Token, function: fn(&str, &str) -> bool) -> Result<Option<Token>, ProgramError> {
+    rt(Token::Bool(function(string(token)?.as_str(), string(pattern)?.as_str())))
+}
+
+/// Returns the characters of a string from `start` up to, but not including, `end`
+///
+/// # Arguments
+///
+/// * `token` - The string
+/// * `start` - Index of the first character
+/// * `end` - Index after the last character
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if the operand is not a string, and `IndexOutOfRange` unless
+/// `0 <= start <= end <= length`
+///
+pub(crate) fn substring(token: Token, start: Token, end: Token) -> Result<Option<Token>, ProgramError> {
+    let s = string(token)?;
+    let (start, end) = (index(start)?, index(end)?);
+    if start > end || end > s.chars().count() {
+        return Err(ProgramError::IndexOutOfRange)
+    }
+    rt(Token::String(s.chars().skip(start).take(end - start).collect()))
+}
+
+/// Finds the first occurrence of `pattern` in a string
+///
+/// # Arguments
+///
+/// * `token` - The string to search
+/// * `pattern` - The string to look for
+///
+/// # Returns
+///
+/// The index of the first character of the occurrence, or `-1` if there is none
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string
+///
+pub(crate) fn index_of(token: Token, pattern: Token) -> Result<Option<Token>, ProgramError> {
+    let (s, pattern) = (string(token)?, string(pattern)?);
+    match s.find(pattern.as_str()) {
+        Some(byte) => rt(Token::Int(s[..byte].chars().count() as i128)),
+        None => rt(Token::Int(-1))
+    }
+}
+
+/// Splits a string on every occurrence of a separator
+///
+/// An empty separator splits the string into its characters.
+///
+/// # Arguments
+///
+/// * `token` - The string to split
+/// * `separator` - The string between the parts
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if either operand is not a string
+///
+pub(crate) fn split(token: Token, separator: Token) -> Result<Option<Token>, ProgramError> {
+    let (s, separator) = (string(token)?, string(separator)?);
+    if separator.is_empty() {
+        return chars(Token::String(s))
+    }
+    rt(Token::List(s.split(separator.as_str()).map(|part| Token::String(part.to_string())).collect()))
+}
+
+/// Joins a list of strings with a separator between them
+///
+/// # Arguments
+///
+/// * `list` - The strings to join
+/// * `separator` - The string to put between them
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the first operand is not a list, and `ExpectedString` if the
+/// separator or any of the elements is not a string
+///
+pub(crate) fn join(list: Token, separator: Token) -> Result<Option<Token>, ProgramError> {
+    let separator = string(separator)?;
+    let parts = strings(list)?;
+    rt(Token::String(parts.join(separator.as_str())))
+}
+
+/// Replaces every occurrence of `from` in a string with `to`
+///
+/// # Arguments
+///
+/// * `token` - The string
+/// * `from` - The string to replace
+/// * `to` - The replacement
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if any of the operands is not a string
+///
+pub(crate) fn replace(token: Token, from: Token, to: Token) -> Result<Option<Token>, ProgramError> {
+    let (s, from, to) = (string(token)?, string(from)?, string(to)?);
+    rt(Token::String(s.replace(from.as_str(), to.as_str())))
+}
+
+/// Splits a string into a list of strings of one character each
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if the operand is not a string
+///
+pub(crate) fn chars(token: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::List(string(token)?.chars().map(|c| Token::String(c.to_string())).collect()))
+}
+
+/// Concatenates a list of strings, the reverse of `chars`
+///
+/// # Errors
+///
+/// Returns `ExpectedList` if the operand is not a list, and `ExpectedString` if any of
+/// its elements is not a string
+///
+pub(crate) fn from_chars(list: Token) -> Result<Option<Token>, ProgramError> {
+    rt(Token::String(strings(list)?.concat()))
+}
+
+/// Converts any token to a string
+///
+/// Strings are returned as they are, while any other token is rendered the way the
+/// interpreter prints it, e.g. `[1,2]` or `12.50d`.
+///
+pub(crate) fn to_string(token: Token) -> Result<Option<Token>, ProgramError> {
+    match token {
+        Token::String(_) => rt(token),
+        _ => rt(Token::String(token.to_string()))
+    }
+}
+
+/// Helper function that extracts the value of a string token
+fn string(token: Token) -> Result<String, ProgramError> {
+    String::try_from(token)
+}
+
+/// Helper function that extracts the values of a list of strings
+fn strings(list: Token) -> Result<Vec<String>, ProgramError> {
+    Vec::<Token>::try_from(list)?.into_iter().map(String::try_from).collect()
+}
+
+/// Helper function that extracts a character index from an integer token
+///
+/// # Errors
+///
+/// Returns `IndexOutOfRange` for negative integers, and the errors of
+/// `i128::try_from` for anything but an integer
+///
+fn index(token: Token) -> Result<usize, ProgramError> {
+    usize::try_from(i128::try_from(token)?).map_err(|_| ProgramError::IndexOutOfRange)
+}
@@ -0,0 +1,198 @@
+use crate::bigint::BigInt;
+use crate::decimal::{Decimal, DecimalContext};
+use crate::error::ProgramError;
+use crate::state::State;
+use crate::token::{rt, Token};
+
+/// Largest width or precision of a placeholder
+const MAX_SPEC: usize = 10_000;
+
+/// How a value is placed within the width of its placeholder
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// The specifier of a placeholder, e.g. `{:*^10.2}`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Spec {
+    /// Character used for padding
+    fill: char,
+    /// Alignment, or `None` for the default of the value's type
+    align: Option<Align>,
+    /// Minimum number of characters
+    width: usize,
+    /// Number of decimals for numbers, or maximum number of characters for anything else
+    precision: Option<usize>,
+}
+
+/// A part of a parsed template
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Placeholder(Spec),
+}
+
+/// Fills the placeholders of a template with values from the stack
+///
+/// The template is taken from the top of the stack. If the template has placeholders
+/// and the value below it is a list, they are filled with its elements, otherwise with
+/// as many values from the stack as there are placeholders, deepest first.
+///
+/// A placeholder is written `{}` or `{:spec}`, where the specifier consists of an
+/// optional fill character and alignment (`<`, `^` or `>`), a minimum width, and a
+/// precision after a `.`, neither of which may be above 10000. Literal braces are
+/// written `{{` and `}}`.
+///
+/// # Arguments
+///
+/// * `state` - The state whose stack holds the template and the values
+///
+/// # Errors
+///
+/// Returns `ExpectedString` if the template is not a string, `InvalidFormat` if it is
+/// malformed, `StackEmpty` if there are too few values on the stack, and
+/// `IndexOutOfRange` if the list has too few elements
+///
+pub(crate) fn format(state: &mut State) -> Result<Option<Token>, ProgramError> {
+    let pieces = parse(&String::try_from(state.stack_pop()?)?)?;
+    let count = pieces.iter().filter(|piece| matches!(piece, Piece::Placeholder(_))).count();
+    let values = match state.stack.last() {
+        Some(Token::List(_)) if count > 0 => {
+            let list = Vec::<Token>::try_from(state.stack_pop()?)?;
+            if list.len() < count {
+                return Err(ProgramError::IndexOutOfRange)
+            }
+            list
+        },
+        _ if state.stack.len() < count => return Err(ProgramError::StackEmpty),
+        _ => state.stack.split_off(state.stack.len() - count),
+    };
+    let mut values = values.into_iter();
+    let mut result = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => result.push_str(&text),
+            Piece::Placeholder(spec) => result.push_str(&render(values.next().unwrap(), spec)),
+        }
+    }
+    rt(Token::String(result))
+}
+
+/// Helper function that splits a template into text and placeholders
+///
+/// # Errors
+///
+/// Returns `InvalidFormat` for unbalanced braces and malformed specifiers
+///
+fn parse(template: &str) -> Result<Vec<Piece>, ProgramError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+            '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+            '{' => {
+                let mut inner = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => inner.push(c),
+                        None => return Err(ProgramError::InvalidFormat)
+                    }
+                }
+                let spec = match inner.strip_prefix(':') {
+                    Some(spec) => parse_spec(spec)?,
+                    None if inner.is_empty() => parse_spec("")?,
+                    None => return Err(ProgramError::InvalidFormat)
+                };
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+                pieces.push(Piece::Placeholder(spec));
+            },
+            '}' => return Err(ProgramError::InvalidFormat),
+            c => text.push(c),
+        }
+    }
+    pieces.push(Piece::Text(text));
+    Ok(pieces)
+}
+
+/// Helper function that parses the specifier of a placeholder, the part after the `:`
+///
+/// # Errors
+///
+/// Returns `InvalidFormat` if the specifier is malformed
+///
+fn parse_spec(spec: &str) -> Result<Spec, ProgramError> {
+    let align = |c: char| match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None
+    };
+    let chars: Vec<char> = spec.chars().collect();
+    let (fill, alignment, rest) = match chars.as_slice() {
+        [fill, c, rest @ ..] if align(*c).is_some() => (*fill, align(*c), rest),
+        [c, rest @ ..] if align(*c).is_some() => (' ', align(*c), rest),
+        rest => (' ', None, rest),
+    };
+    let rest: String = rest.iter().collect();
+    let (width, precision) = match rest.split_once('.') {
+        Some((width, precision)) => (width, Some(number(precision)?)),
+        None => (rest.as_str(), None),
+    };
+    let width = if width.is_empty() { 0 } else { number(width)? };
+    Ok(Spec { fill, align: alignment, width, precision })
+}
+
+/// Helper function that parses the digits of a width or precision, which may be at
+/// most `MAX_SPEC`
+fn number(digits: &str) -> Result<usize, ProgramError> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ProgramError::InvalidFormat)
+    }
+    match digits.parse() {
+        Ok(number) if number <= MAX_SPEC => Ok(number),
+        _ => Err(ProgramError::InvalidFormat)
+    }
+}
+
+/// Helper function that renders a value according to the specifier of its placeholder
+///
+/// Strings are inserted without quotes and any other value as it is displayed. With a
+/// precision, numbers are written with that many decimals, rounding half to even for
+/// exact numbers, while anything else is cut off after that many characters. Numbers
+/// are aligned to the right by default, and anything else to the left.
+///
+fn render(token: Token, spec: Spec) -> String {
+    let number = matches!(token, Token::Int(_) | Token::BigInt(_) | Token::Rational(_) | Token::Decimal(_) | Token::Float(_));
+    let text = match (token, spec.precision) {
+        (Token::String(s), None) => s,
+        (token, None) => token.to_string(),
+        (Token::Float(x), Some(precision)) => format!("{:.*}", precision, x),
+        (Token::Int(x), Some(precision)) => fixed(&Decimal::from(BigInt::from(x)), &Decimal::from(BigInt::from(1)), precision),
+        (Token::BigInt(x), Some(precision)) => fixed(&Decimal::from(x), &Decimal::from(BigInt::from(1)), precision),
+        (Token::Decimal(x), Some(precision)) => fixed(&x, &Decimal::from(BigInt::from(1)), precision),
+        (Token::Rational(x), Some(precision)) => fixed(&Decimal::from(x.numerator().clone()), &Decimal::from(x.denominator().clone()), precision),
+        (Token::String(s), Some(precision)) => s.chars().take(precision).collect(),
+        (token, Some(precision)) => token.to_string().chars().take(precision).collect(),
+    };
+    let padding = spec.width.saturating_sub(text.chars().count());
+    let (left, right) = match spec.align.unwrap_or(if number { Align::Right } else { Align::Left }) {
+        Align::Left => (0, padding),
+        Align::Center => (padding / 2, padding - padding / 2),
+        Align::Right => (padding, 0),
+    };
+    let fill = |count: usize| spec.fill.to_string().repeat(count);
+    format!("{}{}{}", fill(left), text, fill(right))
+}
+
+/// Helper function that writes the quotient of two decimals with a number of decimals
+fn fixed(numerator: &Decimal, denominator: &Decimal, precision: usize) -> String {
+    // the precision is at most `MAX_SPEC`, so it fits
+    let context = DecimalContext { scale: precision as u32, ..DecimalContext::default() };
+    numerator.div(denominator, context).unwrap().to_string()
+}
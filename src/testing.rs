@@ -0,0 +1,107 @@
+use crate::interpreter::execute_program;
+use crate::parser::parse_string_to_instructions;
+use crate::state::State;
+use std::fs;
+use std::path::PathBuf;
+
+/// Marks the end of a test case, followed by the value the case should produce
+const EXPECT_MARKER: &str = "# expect:";
+
+/// A single case of a test file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Case {
+    /// Line of the `# expect:` comment that ends the case
+    pub line: usize,
+    /// The expected value, or the name of the expected error
+    pub expected: String,
+    /// Source code of the case
+    pub program: String,
+}
+
+/// Finds the test files to run.
+///
+/// # Arguments
+///
+/// * `paths` - Test files, or directories that are searched for files ending in `.bprog`
+///
+/// # Returns
+///
+/// The test files in the order they were given, where the files of a directory are sorted by name
+///
+/// # Errors
+///
+/// Returns a description of the problem if a path cannot be read
+///
+pub fn collect_test_files(paths: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for path in paths.iter().map(PathBuf::from) {
+        if path.is_dir() {
+            let entries = fs::read_dir(&path)
+                .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+            let mut found: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|entry| entry.is_file() && entry.extension().is_some_and(|ext| ext == "bprog"))
+                .collect();
+            found.sort();
+            files.extend(found);
+        } else if path.is_file() {
+            files.push(path);
+        } else {
+            return Err(format!("could not read {}: no such file or directory", path.display()));
+        }
+    }
+    Ok(files)
+}
+
+/// Splits a test file into cases.
+///
+/// Every line of the form `# expect: <value>` ends a case, which consists of all lines
+/// since the previous case. Lines after the last `# expect:` comment are ignored.
+///
+/// # Arguments
+///
+/// * `input` - The contents of the test file
+///
+/// # Returns
+///
+/// The cases in the order they appear in the file
+///
+pub fn parse_test_cases(input: &str) -> Vec<Case> {
+    let mut cases = Vec::new();
+    let mut program = String::new();
+    for (i, line) in input.lines().enumerate() {
+        match line.trim().strip_prefix(EXPECT_MARKER) {
+            Some(expected) => {
+                let expected = expected.trim().to_string();
+                cases.push(Case { line: i + 1, expected, program: std::mem::take(&mut program) });
+            },
+            None => {
+                program.push_str(line);
+                program.push('\n');
+            }
+        }
+    }
+    cases
+}
+
+/// Runs a single test case.
+///
+/// # Arguments
+///
+/// * `case` - The case to be run
+/// * `state` - The state to run the case in, with the prelude already loaded
+///
+/// # Returns
+///
+/// The value the case produced, or the name of the error it failed with
+///
+pub fn run_test_case(case: &Case, state: &mut State) -> String {
+    state.start_clock();
+    match parse_string_to_instructions(case.program.as_str(), state) {
+        Ok(_) => match execute_program(state) {
+            Ok(token) => token.to_string(),
+            Err(e) => format!("{:?}", e.kind())
+        },
+        Err(e) => e.name().to_string()
+    }
+}
@@ -1,28 +1,49 @@
 use std::cmp::Ordering;
-use std::collections::VecDeque;
+use std::env;
 use std::fmt;
+use std::fs;
 use std::iter::once;
 use std::mem::discriminant;
-use std::ops::{Add, Sub, Mul, Div};
-use crate::interpreter::execute_program;
-use crate::parser::{lex};
+use std::rc::Rc;
+use std::ops::{Add, Sub, Mul, Div, Rem, Neg, Not};
+use crate::bigint::BigInt;
+use crate::decimal::{Decimal, DecimalContext};
+use crate::frame::Task;
+use crate::lists::{Apply, Collect};
+use crate::map::{self, Map};
+use crate::parser::Span;
+use crate::rational::Rational;
+use crate::scope::{self, Closure};
 use crate::state::State;
 use crate::error::ProgramError;
 
 /// Represents a single token in the language.
 ///
 /// Each variant of the enum corresponds to a different type of token,
-/// such as string, integer, float, boolean, list, block, or symbol.
+/// such as string, integer, float, boolean, list, map, block, or symbol.
+/// Symbols remember where they were written, so that errors can point
+/// back to the source code.
+///
+/// Integers that do not fit in an `i128` are held in a `BigInt` instead.
+/// A `BigInt` never holds a value that fits in an `Int`, and a `Rational`
+/// never holds a whole number, so every integer and fraction has exactly
+/// one representation. A `Decimal` keeps the number of decimals it was
+/// written with.
 ///
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Token {
     String(String),
     Int(i128),
+    BigInt(BigInt),
+    Rational(Rational),
+    Decimal(Decimal),
     Float(f64),
     Bool(bool),
     List(Vec<Token>),
-    Block(Vec<Token>),
-    Symbol(String),
+    Map(Map),
+    Block(Rc<[Token]>),
+    Closure(Closure),
+    Symbol(String, Span),
 }
 
 /// Implements the `Display` trait for the `Token` enum.
@@ -42,11 +63,16 @@ impl fmt::Display for Token {
         match self {
             Token::String(x) => write!(f, "\"{}\"", x),
             Token::Int(x) => write!(f, "{}", x),
+            Token::BigInt(x) => write!(f, "{}", x),
+            Token::Rational(x) => write!(f, "{}", x),
+            Token::Decimal(x) => write!(f, "{}d", x),
             Token::Float(x) => write!(f, "{:?}", x),
             Token::Bool(x) => write!(f, "{}", if *x {"True"} else {"False"}),
             Token::List(x) => write!(f, "[{}]", x.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(",")),
+            Token::Map(x) => write!(f, "{}", x),
             Token::Block(x) => write!(f, "{{ {} }}", x.iter().map(|c| c.to_string()).collect::<Vec<String>>().join(" ")),
-            Token::Symbol(x) => write!(f, "{}", x),
+            Token::Closure(x) => write!(f, "{}", x),
+            Token::Symbol(x, _) => write!(f, "{}", x),
         }
     }
 }
@@ -68,7 +94,10 @@ impl Add for Token {
     /// A `Result` containing an optional token as the result of the addition or an error.
     fn add(self, other: Token) -> Self::Output {
         match self.type_coercion(other)? {
-            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x+y)),
+            (Token::Int(x), Token::Int(y)) => rt(x.checked_add(y).map_or_else(|| (BigInt::from(x) + BigInt::from(y)).into(), Token::Int)),
+            (Token::BigInt(x), Token::BigInt(y)) => rt((x + y).into()),
+            (Token::Rational(x), Token::Rational(y)) => rt((&x + &y).into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(&x + &y)),
             (Token::Float(x), Token::Float(y)) => rt(Token::Float(x+y)),
             _ => Err(ProgramError::ExpectedNumber)
         }
@@ -93,7 +122,10 @@ impl Sub for Token {
     ///
     fn sub(self, other: Token) -> Self::Output {
         match self.type_coercion(other)? {
-            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x-y)),
+            (Token::Int(x), Token::Int(y)) => rt(x.checked_sub(y).map_or_else(|| (BigInt::from(x) - BigInt::from(y)).into(), Token::Int)),
+            (Token::BigInt(x), Token::BigInt(y)) => rt((x - y).into()),
+            (Token::Rational(x), Token::Rational(y)) => rt((&x - &y).into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(&x - &y)),
             (Token::Float(x), Token::Float(y)) => rt(Token::Float(x-y)),
             _ => Err(ProgramError::NumberConversionError)
         }
@@ -118,7 +150,10 @@ impl Mul for Token {
     ///
     fn mul(self, other: Token) -> Self::Output {
         match self.type_coercion(other)? {
-            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x*y)),
+            (Token::Int(x), Token::Int(y)) => rt(x.checked_mul(y).map_or_else(|| (BigInt::from(x) * BigInt::from(y)).into(), Token::Int)),
+            (Token::BigInt(x), Token::BigInt(y)) => rt((x * y).into()),
+            (Token::Rational(x), Token::Rational(y)) => rt((&x * &y).into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(&x * &y)),
             (Token::Float(x), Token::Float(y)) => rt(Token::Float(x*y)),
             _ => Err(ProgramError::NumberConversionError)
         }
@@ -133,6 +168,9 @@ impl Div for Token {
 
     /// Divides `self` by `other` and returns the result.
     ///
+    /// Decimal quotients are rounded with the default `DecimalContext`. The `/` word
+    /// uses `decimal_div` with the context of the state instead.
+    ///
     /// # Arguments
     ///
     /// * `other` - The token to divide `self` by.
@@ -142,18 +180,414 @@ impl Div for Token {
     /// A `Result` containing an optional token as the result of the division or an error.
     ///
     fn div(self, other: Token) -> Self::Output {
+        self.decimal_div(other, DecimalContext::default())
+    }
+}
+
+/// Implements the `Rem` trait for the `Token` enum.
+///
+/// This enables remainder operations between two tokens. The remainder has the sign
+/// of `self`, matching `div`, which rounds towards zero.
+impl Rem for Token {
+    type Output = Result<Option<Token>, ProgramError>;
+
+    /// Returns the remainder of dividing `self` by `other`.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The token to divide `self` by.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the remainder or an error.
+    ///
+    fn rem(self, other: Token) -> Self::Output {
         match self.type_coercion(other)? {
-            (_, Token::Int(y)) if y == 0 => Err(ProgramError::DivisionByZero),
-            (_, Token::Float(y)) if y == 0.0 => Err(ProgramError::DivisionByZero),
-            (Token::Int(x), Token::Int(y)) => rt(Token::Float(x as f64 /y as f64)),
-            (Token::Float(x), Token::Float(y)) => rt(Token::Float(x/y)),
+            (_, Token::Int(0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::Float(0.0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::BigInt(y)) if y.is_zero() => Err(ProgramError::DivisionByZero),
+            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x.wrapping_rem(y))),
+            (Token::BigInt(x), Token::BigInt(y)) => rt(x.div_rem(&y).1.into()),
+            (Token::Rational(x), Token::Rational(y)) => rt((&(&x / &y).ok_or(ProgramError::DivisionByZero)?.fract() * &y).into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(x.div_rem(&y).ok_or(ProgramError::DivisionByZero)?.1)),
+            (Token::Float(x), Token::Float(y)) => rt(Token::Float(x % y)),
             _ => Err(ProgramError::NumberConversionError)
         }
     }
 }
 
+/// Implements the `Neg` trait for the `Token` enum.
+///
+/// This enables arithmetic negation of a token.
+impl Neg for Token {
+    type Output = Result<Option<Token>, ProgramError>;
+
+    /// Returns the negation of the numeric value of `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token representing the negation or an error.
+    ///
+    fn neg(self) -> Self::Output {
+        match self {
+            Token::Int(x) => rt(x.checked_neg().map_or_else(|| (-BigInt::from(x)).into(), Token::Int)),
+            Token::BigInt(x) => rt((-x).into()),
+            Token::Rational(x) => rt(Token::Rational(-x)),
+            Token::Decimal(x) => rt(Token::Decimal(-x)),
+            Token::Float(x) => rt(Token::Float(-x)),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+}
+
+/// Implements the `Not` trait for the `Token` enum.
+///
+/// This enables boolean negation of a token.
+impl Not for Token {
+    type Output = Result<Option<Token>, ProgramError>;
+
+    /// Returns the negation of the boolean value of `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token representing the negation or an error.
+    ///
+    fn not(self) -> Self::Output {
+        match self {
+            Token::Bool(x) => rt(Token::Bool(!x)),
+            _ => Err(ProgramError::ExpectedBool)
+        }
+    }
+}
+
+impl From<i128> for Token {
+    /// Wraps an integer in a `Token::Int`.
+    fn from(value: i128) -> Self {
+        Token::Int(value)
+    }
+}
+
+impl From<i64> for Token {
+    /// Wraps an integer in a `Token::Int`.
+    fn from(value: i64) -> Self {
+        Token::Int(value as i128)
+    }
+}
+
+impl From<i32> for Token {
+    /// Wraps an integer in a `Token::Int`.
+    fn from(value: i32) -> Self {
+        Token::Int(value as i128)
+    }
+}
+
+impl From<BigInt> for Token {
+    /// Wraps an integer in a `Token::Int` if it fits, or a `Token::BigInt` otherwise.
+    fn from(value: BigInt) -> Self {
+        match value.to_i128() {
+            Some(x) => Token::Int(x),
+            None => Token::BigInt(value)
+        }
+    }
+}
+
+impl From<Rational> for Token {
+    /// Wraps a fraction in a `Token::Rational`, or in an integer token if it is a whole number.
+    fn from(value: Rational) -> Self {
+        if value.is_integer() {
+            Token::from(value.numerator().clone())
+        } else {
+            Token::Rational(value)
+        }
+    }
+}
+
+impl From<Decimal> for Token {
+    /// Wraps a decimal in a `Token::Decimal`.
+    fn from(value: Decimal) -> Self {
+        Token::Decimal(value)
+    }
+}
+
+impl From<Map> for Token {
+    /// Wraps a map in a `Token::Map`.
+    fn from(value: Map) -> Self {
+        Token::Map(value)
+    }
+}
+
+impl From<f64> for Token {
+    /// Wraps a float in a `Token::Float`.
+    fn from(value: f64) -> Self {
+        Token::Float(value)
+    }
+}
+
+impl From<bool> for Token {
+    /// Wraps a boolean in a `Token::Bool`.
+    fn from(value: bool) -> Self {
+        Token::Bool(value)
+    }
+}
+
+impl From<&str> for Token {
+    /// Wraps a string in a `Token::String`.
+    fn from(value: &str) -> Self {
+        Token::String(value.to_string())
+    }
+}
+
+impl From<String> for Token {
+    /// Wraps a string in a `Token::String`.
+    fn from(value: String) -> Self {
+        Token::String(value)
+    }
+}
+
+impl<T: Into<Token>> From<Vec<T>> for Token {
+    /// Converts every element and wraps them in a `Token::List`.
+    fn from(values: Vec<T>) -> Self {
+        Token::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl TryFrom<Token> for i128 {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Int`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NumberConversionError` for floats and `ExpectedNumber` for any other token
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Int(x) => Ok(x),
+            Token::BigInt(_) | Token::Rational(_) | Token::Decimal(_) | Token::Float(_) => Err(ProgramError::NumberConversionError),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+}
+
+impl TryFrom<Token> for i64 {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Int`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NumberConversionError` for floats and integers that do not fit in an `i64`,
+    /// and `ExpectedNumber` for any other token
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        i64::try_from(i128::try_from(token)?).map_err(|_| ProgramError::NumberConversionError)
+    }
+}
+
+impl TryFrom<Token> for f64 {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Float`. Integers are converted to floats.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedNumber` if the token is not a number
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Float(x) => Ok(x),
+            Token::Int(x) => Ok(x as f64),
+            Token::BigInt(x) => Ok(x.to_f64()),
+            Token::Rational(x) => Ok(x.to_f64()),
+            Token::Decimal(x) => Ok(x.to_f64()),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+}
+
+impl TryFrom<Token> for BigInt {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Int` or `Token::BigInt`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NumberConversionError` for floats and `ExpectedNumber` for any other token
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Int(x) => Ok(BigInt::from(x)),
+            Token::BigInt(x) => Ok(x),
+            Token::Rational(_) | Token::Decimal(_) | Token::Float(_) => Err(ProgramError::NumberConversionError),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+}
+
+impl TryFrom<Token> for Rational {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Rational`. Integers and decimals are converted to fractions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NumberConversionError` for floats and `ExpectedNumber` for any other token
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Rational(x) => Ok(x),
+            Token::Int(_) | Token::BigInt(_) => Ok(Rational::from(BigInt::try_from(token)?)),
+            Token::Decimal(x) => Ok(x.to_rational()),
+            Token::Float(_) => Err(ProgramError::NumberConversionError),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+}
+
+impl TryFrom<Token> for Decimal {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Decimal`. Integers are converted to decimals.
+    ///
+    /// # Errors
+    ///
+    /// Returns `NumberConversionError` for floats and rationals, and `ExpectedNumber` for
+    /// any other token
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Decimal(x) => Ok(x),
+            Token::Int(_) | Token::BigInt(_) => Ok(Decimal::from(BigInt::try_from(token)?)),
+            Token::Rational(_) | Token::Float(_) => Err(ProgramError::NumberConversionError),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+}
+
+impl TryFrom<Token> for bool {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::Bool`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedBool` if the token is not a boolean
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Bool(x) => Ok(x),
+            _ => Err(ProgramError::ExpectedBool)
+        }
+    }
+}
+
+impl TryFrom<Token> for String {
+    type Error = ProgramError;
+
+    /// Extracts the value of a `Token::String`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedString` if the token is not a string
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::String(x) => Ok(x),
+            _ => Err(ProgramError::ExpectedString)
+        }
+    }
+}
+
+impl TryFrom<Token> for Map {
+    type Error = ProgramError;
+
+    /// Extracts the entries of a `Token::Map`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedMap` if the token is not a map
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::Map(x) => Ok(x),
+            _ => Err(ProgramError::ExpectedMap)
+        }
+    }
+}
+
+impl TryFrom<Token> for Vec<Token> {
+    type Error = ProgramError;
+
+    /// Extracts the elements of a `Token::List`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExpectedList` if the token is not a list
+    ///
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        match token {
+            Token::List(x) => Ok(x),
+            _ => Err(ProgramError::ExpectedList)
+        }
+    }
+}
+
 impl Token {
 
+    /// Returns the name of the type of `self`, as used in error messages.
+    ///
+    /// # Returns
+    ///
+    /// A static string naming the variant.
+    ///
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Token::String(_) => "String",
+            Token::Int(_) | Token::BigInt(_) => "Int",
+            Token::Rational(_) => "Rational",
+            Token::Decimal(_) => "Decimal",
+            Token::Float(_) => "Float",
+            Token::Bool(_) => "Bool",
+            Token::List(_) => "List",
+            Token::Map(_) => "Map",
+            Token::Block(_) => "Block",
+            Token::Closure(_) => "Closure",
+            Token::Symbol(_, _) => "Symbol",
+        }
+    }
+
+    /// Renders `self` as JSON.
+    ///
+    /// Lists become arrays, and maps become objects whose keys are the strings or the
+    /// printed form of their keys. Blocks and symbols become strings of their source code.
+    /// Floats that cannot be represented in JSON, such as NaN, become `null`.
+    ///
+    /// # Returns
+    ///
+    /// The JSON text.
+    ///
+    pub fn to_json(&self) -> String {
+        match self {
+            Token::String(x) => json_string(x),
+            Token::Int(x) => x.to_string(),
+            Token::BigInt(x) => x.to_string(),
+            Token::Rational(x) => json_string(x.to_string().as_str()),
+            Token::Decimal(x) => x.to_string(),
+            Token::Float(x) if x.is_finite() => format!("{:?}", x),
+            Token::Float(_) => "null".to_string(),
+            Token::Bool(x) => x.to_string(),
+            Token::List(x) => format!("[{}]", x.iter().map(|c| c.to_json()).collect::<Vec<String>>().join(",")),
+            Token::Map(x) => {
+                let entries = x.iter().map(|(k, v)| match k {
+                    Token::String(k) => format!("{}:{}", json_string(k), v.to_json()),
+                    k => format!("{}:{}", json_string(k.to_string().as_str()), v.to_json()),
+                });
+                format!("{{{}}}", entries.collect::<Vec<String>>().join(","))
+            },
+            Token::Block(_) | Token::Closure(_) | Token::Symbol(_, _) => json_string(self.to_string().as_str()),
+        }
+    }
+
     /// Performs integer division between `self` and `other` and returns the result.
     ///
     /// # Arguments
@@ -166,50 +600,309 @@ impl Token {
     ///
     pub fn int_div(self, other: Token) -> Result<Option<Token>, ProgramError> {
         match self.type_coercion(other)? {
-            (_, Token::Int(y)) if y == 0 => Err(ProgramError::DivisionByZero),
-            (_, Token::Float(y)) if y == 0.0 => Err(ProgramError::DivisionByZero),
-            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x / y)),
+            (_, Token::Int(0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::Float(0.0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::BigInt(y)) if y.is_zero() => Err(ProgramError::DivisionByZero),
+            (Token::Int(x), Token::Int(y)) => rt(x.checked_div(y).map_or_else(|| (-BigInt::from(x)).into(), Token::Int)),
+            (Token::BigInt(x), Token::BigInt(y)) => rt(x.div_rem(&y).0.into()),
+            (Token::Rational(x), Token::Rational(y)) => rt((&x / &y).ok_or(ProgramError::DivisionByZero)?.trunc().into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(x.div_rem(&y).ok_or(ProgramError::DivisionByZero)?.0.into()),
             (Token::Float(x), Token::Float(y)) => rt(Token::Int((x / y) as i128)),
             _ => Err(ProgramError::NumberConversionError)
         }
     }
 
+    /// Returns `self` modulo `other`.
+    ///
+    /// Unlike the remainder, the result has the sign of `other`, so that e.g.
+    /// `-7 3 mod` is `2` while `-7 3 rem` is `-1`.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The modulus.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the operation or an error.
+    ///
+    pub fn modulo(self, other: Token) -> Result<Option<Token>, ProgramError> {
+        match self.type_coercion(other)? {
+            (_, Token::Int(0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::Float(0.0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::BigInt(y)) if y.is_zero() => Err(ProgramError::DivisionByZero),
+            (Token::Int(x), Token::Int(y)) => {
+                let r = x.wrapping_rem(y);
+                rt(Token::Int(if r != 0 && (r < 0) != (y < 0) { r + y } else { r }))
+            },
+            (Token::BigInt(x), Token::BigInt(y)) => {
+                let r = x.div_rem(&y).1;
+                rt(if !r.is_zero() && r.is_negative() != y.is_negative() { &r + &y } else { r }.into())
+            },
+            (Token::Rational(x), Token::Rational(y)) => {
+                let r = &(&x / &y).ok_or(ProgramError::DivisionByZero)?.fract() * &y;
+                rt(if !r.is_zero() && r.signum() != y.signum() { &r + &y } else { r }.into())
+            },
+            (Token::Decimal(x), Token::Decimal(y)) => {
+                let r = x.div_rem(&y).ok_or(ProgramError::DivisionByZero)?.1;
+                rt(Token::Decimal(if !r.is_zero() && r.signum() != y.signum() { &r + &y } else { r }))
+            },
+            (Token::Float(x), Token::Float(y)) => {
+                let r = x % y;
+                rt(Token::Float(if r != 0.0 && (r < 0.0) != (y < 0.0) { r + y } else { r }))
+            },
+            _ => Err(ProgramError::NumberConversionError)
+        }
+    }
+
+    /// Raises `self` to the power of `other`.
+    ///
+    /// Two integers give an integer, unless the exponent is negative, in which case
+    /// the result is a float like for `/`. Exact powers with more than a million bits
+    /// fail with `IntegerOverflow`, and negative powers of zero with `DivisionByZero`.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The exponent.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the operation or an error.
+    ///
+    pub fn pow(self, other: Token) -> Result<Option<Token>, ProgramError> {
+        match self.type_coercion(other)? {
+            // like `1 0 /`, a negative power of zero divides by zero
+            (Token::Int(0), Token::Int(y)) if y < 0 => Err(ProgramError::DivisionByZero),
+            (Token::Int(x), Token::Int(y)) if y < 0 => rt(Token::Float((x as f64).powf(y as f64))),
+            (Token::Int(x), Token::Int(y)) => match u32::try_from(y) {
+                Ok(y) => match x.checked_pow(y) {
+                    Some(power) => rt(Token::Int(power)),
+                    None => rt(BigInt::from(x).checked_pow(y).ok_or(ProgramError::IntegerOverflow)?.into())
+                },
+                // only -1, 0 and 1 have powers this large that fit in memory
+                Err(_) if x.abs() <= 1 => rt(Token::Int(if x == -1 && y % 2 == 0 { 1 } else { x })),
+                Err(_) => Err(ProgramError::IntegerOverflow)
+            },
+            (Token::BigInt(x), Token::BigInt(y)) => match y.to_i128().and_then(|y| u32::try_from(y).ok()) {
+                Some(y) => rt(x.checked_pow(y).ok_or(ProgramError::IntegerOverflow)?.into()),
+                None if y.is_negative() => rt(Token::Float(x.to_f64().powf(y.to_f64()))),
+                None => Err(ProgramError::IntegerOverflow)
+            },
+            (Token::Rational(x), Token::Rational(y)) if y.is_integer() => {
+                let exponent = y.numerator().to_i128().ok_or(ProgramError::IntegerOverflow)?;
+                let power = u32::try_from(exponent.unsigned_abs()).ok().and_then(|y| x.pow(y)).ok_or(ProgramError::IntegerOverflow)?;
+                rt(if exponent < 0 { power.recip().ok_or(ProgramError::DivisionByZero)? } else { power }.into())
+            },
+            (Token::Rational(x), Token::Rational(y)) if x.is_zero() && y.signum() < 0 => Err(ProgramError::DivisionByZero),
+            (Token::Rational(x), Token::Rational(y)) => rt(Token::Float(x.to_f64().powf(y.to_f64()))),
+            (Token::Decimal(x), Token::Decimal(y)) => {
+                let exponent = y.to_rational();
+                let power = exponent.is_integer().then(|| exponent.numerator().to_i128().and_then(|y| u32::try_from(y).ok())).flatten();
+                match power {
+                    _ if x.is_zero() && y.signum() < 0 => Err(ProgramError::DivisionByZero),
+                    Some(power) => rt(Token::Decimal(x.pow(power).ok_or(ProgramError::IntegerOverflow)?)),
+                    None => rt(Token::Float(x.to_f64().powf(y.to_f64())))
+                }
+            },
+            (Token::Float(x), Token::Float(y)) if x == 0.0 && y < 0.0 => Err(ProgramError::DivisionByZero),
+            (Token::Float(x), Token::Float(y)) => rt(Token::Float(x.powf(y))),
+            _ => Err(ProgramError::NumberConversionError)
+        }
+    }
+
+    /// Divides `self` by `other` exactly.
+    ///
+    /// Unlike `/`, the quotient of two integers or decimals is a `Rational`, or an integer
+    /// if the division has no remainder. Floats are divided like with `/`.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The token to divide `self` by.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the division or an error.
+    ///
+    pub fn exact_div(self, other: Token) -> Result<Option<Token>, ProgramError> {
+        match self.type_coercion(other)? {
+            (x @ (Token::Int(_) | Token::BigInt(_) | Token::Decimal(_)), y) => {
+                let (x, y) = (Rational::try_from(x)?, Rational::try_from(y)?);
+                rt((&x / &y).ok_or(ProgramError::DivisionByZero)?.into())
+            },
+            (x, y) => x / y
+        }
+    }
+
+    /// Divides `self` by `other`, where decimal quotients are rounded according to `context`.
+    ///
+    /// The quotient of two integers is a float.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The token to divide `self` by.
+    /// * `context` - The scale and rounding mode of decimal quotients.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the division or an error.
+    ///
+    pub fn decimal_div(self, other: Token, context: DecimalContext) -> Result<Option<Token>, ProgramError> {
+        match self.type_coercion(other)? {
+            (_, Token::Int(0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::Float(0.0)) => Err(ProgramError::DivisionByZero),
+            (_, Token::BigInt(y)) if y.is_zero() => Err(ProgramError::DivisionByZero),
+            (Token::Int(x), Token::Int(y)) => rt(Token::Float(x as f64 /y as f64)),
+            (Token::BigInt(x), Token::BigInt(y)) => rt(Token::Float(x.to_f64() / y.to_f64())),
+            (Token::Rational(x), Token::Rational(y)) => rt((&x / &y).ok_or(ProgramError::DivisionByZero)?.into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(x.div(&y, context).ok_or(ProgramError::DivisionByZero)?)),
+            (Token::Float(x), Token::Float(y)) => rt(Token::Float(x/y)),
+            _ => Err(ProgramError::NumberConversionError)
+        }
+    }
+
+    /// Creates the fraction `self / other` out of two exact numbers.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The denominator.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the fraction or an error.
+    ///
+    pub fn rational(self, other: Token) -> Result<Option<Token>, ProgramError> {
+        match (&self, &other) {
+            (Token::Int(_) | Token::BigInt(_) | Token::Rational(_) | Token::Decimal(_),
+             Token::Int(_) | Token::BigInt(_) | Token::Rational(_) | Token::Decimal(_)) => self.exact_div(other),
+            _ => Err(ProgramError::NumberConversionError)
+        }
+    }
+
+    /// Converts the numeric value of `self` to a float.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the float or an error.
+    ///
+    pub fn to_float(self) -> Result<Option<Token>, ProgramError> {
+        rt(Token::Float(f64::try_from(self)?))
+    }
+
+    /// Returns the absolute value of `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the operation or an error.
+    ///
+    pub fn abs(self) -> Result<Option<Token>, ProgramError> {
+        match self {
+            Token::Int(x) => rt(x.checked_abs().map_or_else(|| BigInt::from(x).abs().into(), Token::Int)),
+            Token::BigInt(x) => rt(x.abs().into()),
+            Token::Rational(x) => rt(Token::Rational(x.abs())),
+            Token::Decimal(x) => rt(Token::Decimal(x.abs())),
+            Token::Float(x) => rt(Token::Float(x.abs())),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+
+    /// Returns the sign of `self`.
+    ///
+    /// Integers, rationals and decimals give the integer `-1`, `0` or `1`. Floats give
+    /// `-1.0` or `1.0`, and zero or NaN unchanged, so that `-0.0` stays `-0.0`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the operation or an error.
+    ///
+    pub fn sign(self) -> Result<Option<Token>, ProgramError> {
+        match self {
+            Token::Int(x) => rt(Token::Int(x.signum())),
+            Token::BigInt(x) => rt(Token::Int(x.signum())),
+            Token::Rational(x) => rt(Token::Int(x.signum())),
+            Token::Decimal(x) => rt(Token::Int(x.signum())),
+            Token::Float(x) if x == 0.0 || x.is_nan() => rt(Token::Float(x)),
+            Token::Float(x) => rt(Token::Float(x.signum())),
+            _ => Err(ProgramError::ExpectedNumber)
+        }
+    }
+
+    /// Returns the smaller of `self` and `other`, after coercing them to a common type.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The token to compare with `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the operation or an error.
+    ///
+    pub fn min(self, other: Token) -> Result<Option<Token>, ProgramError> {
+        match self.type_coercion(other)? {
+            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x.min(y))),
+            (Token::BigInt(x), Token::BigInt(y)) => rt(x.min(y).into()),
+            (Token::Rational(x), Token::Rational(y)) => rt(x.min(y).into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(x.min(y))),
+            (Token::Float(x), Token::Float(y)) => rt(Token::Float(x.min(y))),
+            _ => Err(ProgramError::NumberConversionError)
+        }
+    }
+
+    /// Returns the larger of `self` and `other`, after coercing them to a common type.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The token to compare with `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token as the result of the operation or an error.
+    ///
+    pub fn max(self, other: Token) -> Result<Option<Token>, ProgramError> {
+        match self.type_coercion(other)? {
+            (Token::Int(x), Token::Int(y)) => rt(Token::Int(x.max(y))),
+            (Token::BigInt(x), Token::BigInt(y)) => rt(x.max(y).into()),
+            (Token::Rational(x), Token::Rational(y)) => rt(x.max(y).into()),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Decimal(x.max(y))),
+            (Token::Float(x), Token::Float(y)) => rt(Token::Float(x.max(y))),
+            _ => Err(ProgramError::NumberConversionError)
+        }
+    }
+
     /// Compares `self` and `other` based on the specified comparison ordering.
     ///
     /// # Arguments
     ///
     /// * `other` - The token to compare with `self`.
     /// * `comparison` - The desired comparison ordering (`Ordering::Less`,
-    ///                  `Ordering::Greater`, or `Ordering::Equal`).
+    ///   `Ordering::Greater`, or `Ordering::Equal`).
     ///
     /// # Returns
     ///
     /// A `Result` containing an optional token representing the result of the comparison or an error.
     ///
     pub fn compare(self, other: Token, comparison: Ordering) -> Result<Option<Token>, ProgramError> {
+        // a closure is equal to a block if it captured none of the variables it refers to
+        if let (Token::Closure(x), Token::Block(y)) | (Token::Block(y), Token::Closure(x)) = (&self, &other) {
+            if comparison == Ordering::Equal {
+                return rt(Token::Bool(x.eq_block(y)))
+            }
+        }
         match self.type_coercion(other)? {
             (Token::Int(x), Token::Int(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
+            (Token::BigInt(x), Token::BigInt(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
+            (Token::Rational(x), Token::Rational(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
+            (Token::Decimal(x), Token::Decimal(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
             (Token::Float(x), Token::Float(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
             (Token::Bool(x), Token::Bool(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
             (Token::String(x), Token::String(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
             (Token::List(x), Token::List(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
+            (Token::Map(x), Token::Map(y)) => rt(Token::Bool(compare_values(&x, &y, comparison))),
+            // quotations are only equal or not
+            (Token::Block(x), Token::Block(y)) if comparison == Ordering::Equal => rt(Token::Bool(x == y)),
+            (Token::Closure(x), Token::Closure(y)) if comparison == Ordering::Equal => rt(Token::Bool(x == y)),
             _ => Err(ProgramError::ExpectedBoolOrNumber),
         }
     }
 
-    /// Returns the negation of the boolean value of `self`.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing an optional token representing the negation or an error.
-    ///
-    pub fn not(self: Token) -> Result<Option<Token>, ProgramError> {
-        match self {
-            Token::Bool(x) => rt(Token::Bool(!x)),
-            _ => Err(ProgramError::ExpectedBool)
-        }
-    }
-
     /// Performs a logical AND operation between `self` and `other`.
     ///
     /// # Arguments
@@ -248,6 +941,8 @@ impl Token {
 
     /// Returns the length of `self` if it is a list, block, or string.
     ///
+    /// The length of a string is the number of Unicode characters, not bytes.
+    ///
     /// # Returns
     ///
     /// A `Result` containing an optional token representing the length or an error.
@@ -256,7 +951,8 @@ impl Token {
         match self {
             Token::List(x) => rt(Token::Int(x.len() as i128)),
             Token::Block(x) => rt(Token::Int(x.len() as i128)),
-            Token::String(x) => rt(Token::Int(x.len() as i128)),
+            Token::Closure(x) => rt(Token::Int(x.body().len() as i128)),
+            Token::String(x) => rt(Token::Int(x.chars().count() as i128)),
             _ => Err(ProgramError::ExpectedEnumerable)
         }
     }
@@ -268,12 +964,12 @@ impl Token {
     /// A `Result` containing an optional token representing the parsed integer or an error.
     ///
     pub fn parse_int(self: Token) -> Result<Option<Token>, ProgramError> {
-        println!("{:?}", self);
         match self {
             Token::String(x) => {
-                match x.parse::<i128>() {
-                    Ok(i) => rt(Token::Int(i)),
-                    Err(_) => Err(ProgramError::NumberConversionError)
+                match (x.parse::<i128>(), x.parse::<BigInt>()) {
+                    (Ok(i), _) => rt(Token::Int(i)),
+                    (_, Ok(i)) => rt(i.into()),
+                    _ => Err(ProgramError::NumberConversionError)
                 }
             },
             _ => Err(ProgramError::ExpectedString)
@@ -298,6 +994,24 @@ impl Token {
         }
     }
 
+    /// Parses the string value of `self` as a decimal number, keeping its scale.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing an optional token representing the parsed decimal or an error.
+    ///
+    pub fn parse_decimal(self: Token) -> Result<Option<Token>, ProgramError> {
+        match self {
+            Token::String(x) => {
+                match x.parse::<Decimal>() {
+                    Ok(d) => rt(Token::Decimal(d)),
+                    Err(_) => Err(ProgramError::NumberConversionError)
+                }
+            },
+            _ => Err(ProgramError::ExpectedString)
+        }
+    }
+
     /// Splits the string value of `self` into words and returns a list of tokens.
     ///
     /// # Returns
@@ -307,7 +1021,7 @@ impl Token {
     pub fn words(self: Token) -> Result<Option<Token>, ProgramError> {
         match self {
             Token::String(x) => {
-                rt(Token::List(lex(x.as_str()).iter().map(|s| Token::String(s.to_string())).collect::<Vec<Token>>()))
+                rt(Token::List(x.split_whitespace().map(|s| Token::String(s.to_string())).collect::<Vec<Token>>()))
             },
             _ => Err(ProgramError::ExpectedString)
         }
@@ -354,11 +1068,12 @@ impl Token {
         }
     }
 
-    /// Adds a new token to the beginning of the list value of `self`.
+    /// Adds a new token to the beginning of the list value of `self`, or a string to the
+    /// beginning of the string value of `self`.
     ///
     /// # Arguments
     ///
-    /// * `other` - The token to be added to the beginning of the list.
+    /// * `other` - The token to be added to the beginning of the list or string.
     ///
     /// # Returns
     ///
@@ -367,16 +1082,18 @@ impl Token {
     pub fn cons(self, other: Token) -> Result<Option<Token>, ProgramError> {
         match (self, other) {
             (Token::List(x), a) => rt(Token::List(once(a).chain(x).collect())),
+            (Token::String(x), Token::String(a)) => rt(Token::String(a + x.as_str())),
             _ => Err(ProgramError::ExpectedList)
         }
     }
 
 
-    /// Appends the elements of the second list to the first list value of `self`.
+    /// Appends the elements of the second list to the first list value of `self`, or
+    /// concatenates two strings.
     ///
     /// # Arguments
     ///
-    /// * `other` - The token representing the list to be appended.
+    /// * `other` - The token representing the list or string to be appended.
     ///
     /// # Returns
     ///
@@ -384,12 +1101,16 @@ impl Token {
     ///
     pub fn append(self, other: Token) -> Result<Option<Token>, ProgramError> {
         match (self, other) {
-            (Token::List(x), Token::List(y)) => rt(Token::List(x.into_iter().chain(y.into_iter()).collect())),
+            (Token::List(x), Token::List(y)) => rt(Token::List(x.into_iter().chain(y).collect())),
+            (Token::String(x), Token::String(y)) => rt(Token::String(x + y.as_str())),
             _ => Err(ProgramError::ExpectedList)
         }
     }
 
-    /// Executes a block of tokens by pushing them into the instruction set of the given state.
+    /// Executes a block of tokens by pushing a frame for it onto the call stack of the given state.
+    ///
+    /// The block runs in a new local scope. A block with named parameters, such as
+    /// `{ a b -> a b + }`, first takes its arguments from the stack and binds them in it.
     ///
     /// # Arguments
     ///
@@ -401,10 +1122,8 @@ impl Token {
     ///
     pub fn exec(self: Token, state: &mut State) -> Result<Option<Token>, ProgramError> {
         match self {
-            Token::Block(x) => {
-                x.iter().rev().for_each(|t| state.instruction_set.push_front(t.clone()));
-                Ok(None)
-            },
+            Token::Block(x) => scope::call(x, state),
+            Token::Closure(x) => scope::call_closure(x, state),
             _ => Err(ProgramError::ExpectedQuotation)
         }
     }
@@ -424,20 +1143,8 @@ impl Token {
         let right = state.instruction_pop(false)?;
 
         match (self, middle, right) {
-            (Token::Bool(true), Token::Block(y), _) => {
-                y.iter().rev().for_each(|t| state.instruction_set.push_front(t.clone()));
-                Ok(None)
-            },
-            (Token::Bool(false), _, Token::Block(z)) => {
-                z.iter().rev().for_each(|t| state.instruction_set.push_front(t.clone()));
-                Ok(None)
-            },
-            (Token::Bool(true), y, _) => {
-                state.instruction_set.push_front(y);
-                Ok(None)
-            },
-            (Token::Bool(false), _, z) => {
-                state.instruction_set.push_front(z);
+            (Token::Bool(true), y, _) | (Token::Bool(false), _, y) => {
+                state.call(y)?;
                 Ok(None)
             },
             _ => Err(ProgramError::ExpectedBool)
@@ -446,6 +1153,8 @@ impl Token {
 
     /// Applies a function represented by a block of tokens to each element of the list value of `self`.
     ///
+    /// A map is treated as the list of its entries, each a `[key value]` list.
+    ///
     /// # Arguments
     ///
     /// * `state` - The mutable reference to the state where the block will be executed.
@@ -456,23 +1165,21 @@ impl Token {
     ///
     pub fn map(self, state: &mut State) -> Result<Option<Token>, ProgramError> {
         let right = state.instruction_pop(false)?;
-        match (self, &right) {
-            (Token::List(x), Token::Block(_)) => {
-                let mut list: Vec<Token> = Vec::new();
-                for item in x {
-                    let mut temp_state = State::from(state);
-                    temp_state.instruction_set = VecDeque::from(vec![item, right.clone(), Token::Symbol("exec".to_string())]);
-                    list.push(execute_program(&mut temp_state)?)
-                }
-                rt(Token::List(list))
+        match (map::entry_list(self), &right) {
+            (Token::List(x), Token::Block(_) | Token::Closure(_)) => {
+                state.start_task(Task::Apply(Apply::new(Collect::Map, right, x)));
+                Ok(None)
             },
             _ => Err(ProgramError::ExpectedList)
-
         }
     }
 
     /// Applies a function or token to each element of the list value of `self`.
     ///
+    /// The function runs on the stack of `state`, so it may leave any number of
+    /// values behind, e.g. none for `each { print }`. A map is treated as the list of its
+    /// entries, each a `[key value]` list.
+    ///
     /// # Arguments
     ///
     /// * `state` - The mutable reference to the state where the function will be executed.
@@ -483,19 +1190,12 @@ impl Token {
     ///
     pub fn each(self, state: &mut State) -> Result<Option<Token>, ProgramError> {
         let right = state.instruction_pop(false)?;
-        if let (Token::List(x), _) = (self, &right) {
-            for item in x {
-                let mut instructions = vec![item, right.clone()];
-                if let Token::Block(_) = right {
-                    instructions.push(Token::Symbol("exec".to_string()));
-                }
-                let mut temp_state = State::from(state);
-                temp_state.instruction_set = VecDeque::from(instructions);
-                state.stack_push(execute_program(&mut temp_state)?)
-            }
-            Ok(None)
-        } else {
-            Err(ProgramError::ExpectedList)
+        match map::entry_list(self) {
+            Token::List(x) => {
+                state.start_task(Task::Each { quotation: right, items: x.into() });
+                Ok(None)
+            },
+            _ => Err(ProgramError::ExpectedList)
         }
     }
 
@@ -505,7 +1205,7 @@ impl Token {
     /// # Arguments
     ///
     /// * `state` - The mutable reference to the state where the block will be executed
-    ///             or the token will be pushed.
+    ///   or the token will be pushed.
     ///
     /// # Returns
     ///
@@ -513,56 +1213,20 @@ impl Token {
     ///
     pub fn times(self, state: &mut State) -> Result<Option<Token>, ProgramError> {
         let right = state.instruction_pop(false)?;
-        match (self, &right) {
-            (Token::Int(x), Token::Block(_)) => {
-                for _ in 0..x {
-                    right.clone().exec(state)?;
-                }
+        match self {
+            Token::Int(count) => {
+                state.start_task(Task::Times { quotation: right, count });
                 Ok(None)
             },
-            (Token::Int(x), _) => {
-                for _ in 0..x {
-                    state.instruction_set.push_front(right.clone())
-                }
-                Ok(None)
-            }
             _ => Err(ProgramError::ExpectedBoolOrNumber)
         }
     }
 
-    /// Applies a left-fold operation on a list of tokens with an initial accumulator value.
-    ///
-    /// # Arguments
-    ///
-    /// * `middle` - The initial accumulator value.
-    /// * `state` - The mutable reference to the state where the function will be executed.
-    ///
-    /// # Returns
-    ///
-    /// A `Result` containing an optional token representing the final accumulator value or an error.
-    ///
-    pub fn foldl(self, middle: Token, state: &mut State) -> Result<Option<Token>, ProgramError> {
-        let right = state.instruction_pop(false)?;
-        match (self, &middle, &right) {
-            (Token::List(x), Token::Int(_), right @ Token::Block(_)) |
-            (Token::List(x), Token::Int(_), right @ Token::Symbol(_)) => {
-                let mut sum = middle;
-                for item in x {
-                    let mut temp_state = State::from(state);
-                    temp_state.instruction_set = VecDeque::from(vec![sum.clone(), item, right.clone()]);
-                    if let Token::Block(_) = right {
-                        temp_state.instruction_set.push_back(Token::Symbol("exec".to_string()));
-                    }
-                    sum = execute_program(&mut temp_state)?;
-                }
-                rt(sum)
-            },
-            _ => Err(ProgramError::ExpectedList)
-        }
-    }
-
     /// Binds a token to a symbol in the current state.
     ///
+    /// The binding goes to the innermost scope that already defines the symbol, or to
+    /// the global scope if none does.
+    ///
     /// # Arguments
     ///
     /// * `other` - The token to be bound.
@@ -574,8 +1238,8 @@ impl Token {
     ///
     pub fn set_bind(self, other: Token, stack: &mut State) -> Result<Option<Token>, ProgramError> {
         match (self, other) {
-            (Token::Symbol(x), other @ _) => {
-                stack.bindings.insert(x, other);
+            (Token::Symbol(x, _), other) => {
+                stack.scopes.assign(x, other);
                 Ok(None)
             },
             _ => Err(ProgramError::ExpectedVariable)
@@ -595,7 +1259,7 @@ impl Token {
     ///
     pub fn set_fun(self, other: Token, stack: &mut State) -> Result<Option<Token>, ProgramError> {
         match (self, other) {
-            (Token::Symbol(x), other @ Token::Block(_)) => {
+            (Token::Symbol(x, _), other @ (Token::Block(_) | Token::Closure(_))) => {
                 stack.functions.insert(x, other);
                 Ok(None)
             },
@@ -604,17 +1268,68 @@ impl Token {
     }
 
 
-    /// Prints a token to stdout
+    /// Prints a token to the output stream of the state
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state whose output stream is written to.
     ///
     /// # Returns
     ///
-    /// None
+    /// None, or an `IoError` if the output cannot be written
     ///
-    pub fn print(self) -> Result<Option<Token>, ProgramError> {
-        println!("{}", self);
+    pub fn print(self, state: &mut State) -> Result<Option<Token>, ProgramError> {
+        state.streams.write_line(self.to_string().as_str()).map_err(|_| ProgramError::IoError)?;
         Ok(None)
     }
 
+    /// Reads the file at the path given by `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the contents of the file as a string, or an `IoError`
+    /// if the file cannot be read.
+    ///
+    pub fn read_file(self) -> Result<Option<Token>, ProgramError> {
+        match self {
+            Token::String(path) => rt(Token::String(fs::read_to_string(path).map_err(|_| ProgramError::IoError)?)),
+            _ => Err(ProgramError::ExpectedString)
+        }
+    }
+
+    /// Writes the string value of `self` to a file, replacing its contents.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing None, or an `IoError` if the file cannot be written.
+    ///
+    pub fn write_file(self, path: Token) -> Result<Option<Token>, ProgramError> {
+        match (self, path) {
+            (Token::String(contents), Token::String(path)) => {
+                fs::write(path, contents).map_err(|_| ProgramError::IoError)?;
+                Ok(None)
+            },
+            _ => Err(ProgramError::ExpectedString)
+        }
+    }
+
+    /// Looks up the environment variable named by `self`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the value of the variable as a string, or `False` if it is not set.
+    ///
+    pub fn get_env(self) -> Result<Option<Token>, ProgramError> {
+        match self {
+            Token::String(name) => rt(env::var(name).map_or(Token::Bool(false), Token::String)),
+            _ => Err(ProgramError::ExpectedString)
+        }
+    }
+
 
     /// Coerces the types of the two input tokens to a common type.
     ///
@@ -627,13 +1342,28 @@ impl Token {
     ///
     /// A `Result` containing a tuple of the coerced tokens or an error.
     ///
-    fn type_coercion(self, right: Token) -> Result<(Token, Token), ProgramError> {
+    pub(crate) fn type_coercion(self, right: Token) -> Result<(Token, Token), ProgramError> {
         if discriminant(&self) == discriminant(&right) {
             return Ok((self, right))
         }
         match (self, right) {
             (Token::Int(x), Token::Float(y)) => Ok((Token::Float(x as f64), Token::Float(y))),
             (Token::Float(x), Token::Int(y)) => Ok((Token::Float(x), Token::Float(y as f64))),
+            (Token::BigInt(x), Token::Float(y)) => Ok((Token::Float(x.to_f64()), Token::Float(y))),
+            (Token::Float(x), Token::BigInt(y)) => Ok((Token::Float(x), Token::Float(y.to_f64()))),
+            // the `Int` is widened, and the result is narrowed again by `Token::from`
+            (Token::Int(x), Token::BigInt(y)) => Ok((Token::BigInt(BigInt::from(x)), Token::BigInt(y))),
+            (Token::BigInt(x), Token::Int(y)) => Ok((Token::BigInt(x), Token::BigInt(BigInt::from(y)))),
+            (Token::Rational(x), Token::Float(y)) => Ok((Token::Float(x.to_f64()), Token::Float(y))),
+            (Token::Float(x), Token::Rational(y)) => Ok((Token::Float(x), Token::Float(y.to_f64()))),
+            (x @ (Token::Int(_) | Token::BigInt(_)), Token::Rational(y)) => Ok((Token::Rational(Rational::try_from(x)?), Token::Rational(y))),
+            (Token::Rational(x), y @ (Token::Int(_) | Token::BigInt(_))) => Ok((Token::Rational(x), Token::Rational(Rational::try_from(y)?))),
+            (Token::Decimal(x), Token::Float(y)) => Ok((Token::Float(x.to_f64()), Token::Float(y))),
+            (Token::Float(x), Token::Decimal(y)) => Ok((Token::Float(x), Token::Float(y.to_f64()))),
+            (Token::Decimal(x), Token::Rational(y)) => Ok((Token::Rational(x.to_rational()), Token::Rational(y))),
+            (Token::Rational(x), Token::Decimal(y)) => Ok((Token::Rational(x), Token::Rational(y.to_rational()))),
+            (x @ (Token::Int(_) | Token::BigInt(_)), Token::Decimal(y)) => Ok((Token::Decimal(Decimal::try_from(x)?), Token::Decimal(y))),
+            (Token::Decimal(x), y @ (Token::Int(_) | Token::BigInt(_))) => Ok((Token::Decimal(x), Token::Decimal(Decimal::try_from(y)?))),
             _ => Err(ProgramError::NumberConversionError)
         }
     }
@@ -650,10 +1380,33 @@ impl Token {
 ///
 /// A `Result` containing an `Option` with the token
 ///
-fn rt<T>(token: T) -> Result<Option<T>, ProgramError> {
+pub(crate) fn rt<T>(token: T) -> Result<Option<T>, ProgramError> {
     Ok(Some(token))
 }
 
+/// Helper function that quotes and escapes a string for JSON output
+///
+/// # Arguments
+///
+/// * `s` - The string to be quoted
+///
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_control() => result.push_str(format!("\\u{:04x}", c as u32).as_str()),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
 /// Helper function that compares two values using a specified comparison operation. The
 /// function is type templated such that it can be used in the compare method
 ///
@@ -662,7 +1415,7 @@ fn rt<T>(token: T) -> Result<Option<T>, ProgramError> {
 /// * `x` - The first value.
 /// * `y` - The second value.
 /// * `comparison` - The comparison operation to be used (`Ordering::Less`,
-///                  `Ordering::Greater`, or `Ordering::Equal`).
+///   `Ordering::Greater`, or `Ordering::Equal`).
 ///
 /// # Returns
 ///
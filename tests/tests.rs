@@ -362,6 +362,15 @@ mod test_list_quotations {
         assert_eq!(t("[ 2 5 ] 20 foldl { div }"), "2");
     }
 
+    #[test]
+    fn test_each_uses_the_stack() {
+        // quotations that leave one value give the same results as on a stack of their own
+        assert_eq!(t("[ 1 2 3 ] each { 10 * } [ ] cons cons cons"), "[10,20,30]");
+        // quotations that leave no value, or take values from below the list, used to fail
+        assert_eq!(t("0 [ 1 2 3 4 ] each { + }"), "10");
+        assert_eq!(t("[ 1 2 ] each { pop } 5"), "5");
+    }
+
     #[test]
     fn test_each_parse_integer() {
         assert_eq!(t("[ \" 1 \" \" 2 \" \" 3 \" ] each { parseInteger } [ ] cons cons cons"), "[1,2,3]");
@@ -569,9 +578,10 @@ mod test_functions {
                   4 toList"), "[1,2,3,4]");
     }
 
+    // `max` is a builtin, so it has to be quoted with `'` to be used as a variable name
     #[test]
     fn test_gen1to_num_function() {
-        assert_eq!(t("gen1toNum { max swap := 1 loop { dup max > } { dup 1 + } } fun \
+        assert_eq!(t("gen1toNum { ' max swap := 1 loop { dup max > } { dup 1 + } } fun \
                   3 gen1toNum + + +"), "10");
     }
 
@@ -579,7 +589,7 @@ mod test_functions {
     fn test_odd_to_list_gen1to_num_functions_combined() {
         assert_eq!(t("odd { dup 2 div swap 2 / == if False True } fun \
                   toList { [ ] swap times cons } fun \
-                  gen1toNum { max swap := 1 loop { dup max > } { dup 1 + } } fun \
+                  gen1toNum { ' max swap := 1 loop { dup max > } { dup 1 + } } fun \
                   4 gen1toNum 5 toList map odd"), "[True,False,True,False,True]");
     }
 
@@ -592,4 +602,2055 @@ mod test_functions {
     fn test_mul10_and_inc_functions() {
         assert_eq!(t("mul10 { 10 * } fun inc { 1 + } fun 10 inc mul10"), "110");
     }
-}
\ No newline at end of file
+}
+mod test_prelude {
+    use bprog::t;
+
+    #[test]
+    fn test_prelude_nip() {
+        assert_eq!(t("1 2 nip"), "2");
+    }
+
+    #[test]
+    fn test_prelude_over() {
+        assert_eq!(t("1 2 over [ ] cons cons cons"), "[1,2,1]");
+        assert_eq!(t("1 2 over pop pop pop __over"), "__over");
+        assert_eq!(t("1 over"), "StackEmpty");
+    }
+
+    #[test]
+    fn test_prelude_rot() {
+        assert_eq!(t("1 2 3 rot [ ] cons cons cons"), "[2,3,1]");
+        assert_eq!(t("1 2 3 rot pop pop pop __rot"), "__rot");
+        assert_eq!(t("1 2 rot"), "StackEmpty");
+    }
+
+    #[test]
+    fn test_prelude_reverse() {
+        assert_eq!(t("[ 1 2 3 ] reverse"), "[3,2,1]");
+    }
+
+    #[test]
+    fn test_prelude_reverse_empty() {
+        assert_eq!(t("[ ] reverse"), "[]");
+    }
+
+    #[test]
+    fn test_prelude_sum() {
+        assert_eq!(t("[ 1 2 3 4 ] sum"), "10");
+    }
+
+    #[test]
+    fn test_prelude_range() {
+        assert_eq!(t("1 5 range"), "[1,2,3,4]");
+        assert_eq!(t("0 100000000000 range"), "CollectionLimitExceeded");
+    }
+
+    #[test]
+    fn test_prelude_filter() {
+        assert_eq!(t("[ 1 2 3 4 5 ] filter { 2 > }"), "[3,4,5]");
+    }
+
+    #[test]
+    fn test_prelude_combined() {
+        assert_eq!(t("0 10 range filter { 3 < } sum"), "3");
+    }
+}
+
+mod test_error_reporting {
+    use bprog::{t, t_err};
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(t("1 head"), "ExpectedList");
+    }
+
+    #[test]
+    fn test_error_operation_and_location() {
+        assert!(t_err("1 2 +\n  3 head").starts_with("ExpectedList in `head` at line 2, column 5 (found Int)"));
+    }
+
+    #[test]
+    fn test_error_found_types() {
+        assert!(t_err("1 \" a \" +").contains("(found Int, String)"));
+    }
+
+    #[test]
+    fn test_error_snippet() {
+        assert!(t_err("[ 1 ] \" x \" head").ends_with("1 | [ 1 ] \" x \" head\n  |             ^^^^"));
+    }
+
+    #[test]
+    fn test_error_inside_function_body() {
+        assert!(t_err("f { 1 head } fun\nf").contains("at line 1, column 7"));
+    }
+
+    #[test]
+    fn test_error_inside_map() {
+        assert!(t_err("[ 1 ] map { \" a \" + }").starts_with("NumberConversionError in `+` at line 1, column 19"));
+    }
+
+    #[test]
+    fn test_parser_error_incomplete_list() {
+        assert!(t_err("1\n[ 1 2").starts_with("IncompleteList at line 2, column 1"));
+    }
+
+    #[test]
+    fn test_parser_error_incomplete_string() {
+        assert_eq!(t_err("  \" abc"), "IncompleteString at line 1, column 3\n  |\n1 |   \" abc\n  |   ^");
+    }
+
+    #[test]
+    fn test_parser_error_unmatched_brace() {
+        assert!(t_err("1 }").starts_with("IncompleteQuotation at line 1, column 3"));
+    }
+}
+
+mod test_character_lexer {
+    use bprog::{t, t_chars};
+
+    #[test]
+    fn test_words_lexer_collapses_whitespace() {
+        assert_eq!(t("\" a   b \""), "\"a b\"");
+    }
+
+    #[test]
+    fn test_literal_without_spaces() {
+        assert_eq!(t_chars("\"foo\""), "\"foo\"");
+    }
+
+    #[test]
+    fn test_literal_preserves_whitespace() {
+        assert_eq!(t_chars("\"a   b\" length"), "5");
+    }
+
+    #[test]
+    fn test_literal_keeps_tabs_and_newlines() {
+        assert_eq!(t_chars("\"a\tb\nc\" length"), "5");
+    }
+
+    #[test]
+    fn test_legacy_string_preserves_inner_whitespace() {
+        assert_eq!(t_chars("\" a   b \""), "\"a   b\"");
+    }
+
+    #[test]
+    fn test_legacy_string_in_list() {
+        assert_eq!(t_chars("[ \" 1 \" \" 2 \" ] each { parseInteger } +"), "3");
+    }
+
+    #[test]
+    fn test_empty_literal() {
+        assert_eq!(t_chars("\"\" length"), "0");
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(t_chars("\"say \\\"hi\\\"\""), "\"say \"hi\"\"");
+        assert_eq!(t_chars("\"back\\\\slash\" length"), "10");
+    }
+
+    #[test]
+    fn test_unicode_escape() {
+        assert_eq!(t_chars("\"\\u{48}\\u{49}\""), "\"HI\"");
+    }
+
+    #[test]
+    fn test_brackets_inside_literal() {
+        assert_eq!(t_chars("[ \"]\" \"{\" ]"), "[\"]\",\"{\"]");
+    }
+
+    #[test]
+    fn test_old_programs_still_work() {
+        assert_eq!(t_chars("odd { dup 2 div swap 2 / == if False True } fun 3 odd"), "True");
+        assert_eq!(t_chars("\" [ so { not if ] and } \""), "\"[ so { not if ] and }\"");
+    }
+
+    #[test]
+    #[should_panic(expected = "InvalidEscape")]
+    fn test_invalid_escape() {
+        t_chars("\"\\q\"");
+    }
+
+    #[test]
+    #[should_panic(expected = "IncompleteString")]
+    fn test_unterminated_literal() {
+        t_chars("\"abc");
+    }
+}
+
+mod test_comments {
+    use bprog::{t, t_chars, t_err};
+
+    #[test]
+    fn test_line_comment() {
+        assert_eq!(t("1 # the first number\n2 +"), "3");
+    }
+
+    #[test]
+    fn test_line_comment_without_space() {
+        assert_eq!(t("#!/usr/bin/env bprog\n1"), "1");
+    }
+
+    #[test]
+    fn test_block_comment() {
+        assert_eq!(t("inc ( n -- n ) { 1 + } fun 1 inc"), "2");
+    }
+
+    #[test]
+    fn test_nested_block_comment() {
+        assert_eq!(t("1 ( outer ( inner ) still outer ) 2 +"), "3");
+    }
+
+    #[test]
+    fn test_multiline_block_comment() {
+        assert_eq!(t("1 (\n  spans\n  lines\n) 2 +"), "3");
+    }
+
+    #[test]
+    fn test_comments_inside_list() {
+        assert_eq!(t("[ 1 ( two ) 3 # four ]\n 5 ]"), "[1,3,5]");
+    }
+
+    #[test]
+    fn test_comments_inside_block() {
+        assert_eq!(t("{ ( x -- x ) 10 * # times ten\n } 2 swap exec"), "20");
+    }
+
+    #[test]
+    fn test_comment_markers_inside_string() {
+        assert_eq!(t("\" # ( not a comment ) \""), "\"# ( not a comment )\"");
+    }
+
+    #[test]
+    fn test_comment_markers_inside_literal() {
+        assert_eq!(t_chars("\"#1 (a)\""), "\"#1 (a)\"");
+    }
+
+    #[test]
+    fn test_quote_inside_comment() {
+        assert_eq!(t_chars("( an \" unbalanced quote ) 5 # and \" another"), "5");
+    }
+
+    #[test]
+    fn test_unclosed_block_comment() {
+        assert!(t_err("1 ( never closed").starts_with("IncompleteComment at line 1, column 3"));
+    }
+
+    #[test]
+    fn test_unopened_block_comment() {
+        assert!(t_err("1 )").starts_with("IncompleteComment at line 1, column 3"));
+    }
+}
+
+mod test_run_mode {
+    use std::env;
+    use std::fs;
+    use std::process::{Command, Output};
+
+    /// Writes the program to a temporary file and runs it with `bprog run`
+    fn run(name: &str, program: &str, args: &[&str]) -> Output {
+        let path = env::temp_dir().join(format!("bprog_test_{}.bprog", name));
+        fs::write(&path, program).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_bprog"))
+            .arg("run")
+            .arg(&path)
+            .args(args)
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_run_success() {
+        let output = run("success", "1 2 +", &[]);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    }
+
+    #[test]
+    fn test_run_args() {
+        let output = run("args", "args", &["first", "second value"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "[\"first\",\"second value\"]\n");
+    }
+
+    #[test]
+    fn test_run_args_are_not_interpreter_options() {
+        let output = run("options", "args length", &["--no-prelude", "repl"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    }
+
+    #[test]
+    fn test_run_parse_error() {
+        let output = run("parse_error", "[ 1 2", &[]);
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("IncompleteList"));
+    }
+
+    #[test]
+    fn test_run_runtime_error() {
+        let output = run("runtime_error", "1 0 div", &[]);
+        assert_eq!(output.status.code(), Some(3));
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("DivisionByZero"));
+    }
+
+    #[test]
+    fn test_run_multiple_values() {
+        assert_eq!(run("multiple_values", "1 2", &[]).status.code(), Some(4));
+    }
+
+    #[test]
+    fn test_run_missing_file() {
+        let output = Command::new(env!("CARGO_BIN_EXE_bprog")).args(["run", "does_not_exist.bprog"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(1));
+    }
+}
+
+mod test_cli {
+    use std::env;
+    use std::fs;
+    use std::io::Write;
+    use std::process::{Command, Output, Stdio};
+
+    /// Runs the interpreter with the given arguments and standard input
+    fn bprog(args: &[&str], stdin: &str) -> Output {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_bprog"))
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    fn stderr(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stderr).to_string()
+    }
+
+    /// Writes a file to the temporary directory and returns its path
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("bprog_cli_{}", name));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_stdin() {
+        let output = bprog(&[], "1 2\n+");
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), "3\n");
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(stdout(&bprog(&["eval", "-e", "[ 1 2 3 ] map { 10 * }"], "")), "[10,20,30]\n");
+        assert_eq!(stdout(&bprog(&["eval", "5 dup *"], "")), "25\n");
+        assert_eq!(bprog(&["eval", "1 0 div"], "").status.code(), Some(3));
+    }
+
+    #[test]
+    fn test_eval_negative_number() {
+        assert_eq!(stdout(&bprog(&["eval", "-1"], "")), "-1\n");
+        assert_eq!(stdout(&bprog(&["eval", "-5 abs"], "")), "5\n");
+        assert_eq!(stdout(&bprog(&["eval", "--no-prelude", "-1 2 +"], "")), "1\n");
+        assert_eq!(bprog(&["eval", "-1", "--fast"], "").status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_eval_after_separator() {
+        assert_eq!(stdout(&bprog(&["eval", "--", "-1 abs"], "")), "1\n");
+        assert_eq!(stdout(&bprog(&["eval", "--no-prelude", "--", "--"], "")), "--\n");
+        assert_eq!(bprog(&["eval", "--", "1", "2"], "").status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_eval_without_program() {
+        let output = bprog(&["eval"], "");
+        assert_eq!(output.status.code(), Some(1));
+        assert!(stderr(&output).contains("Usage: bprog"));
+    }
+
+    #[test]
+    fn test_options_after_command() {
+        assert_eq!(stdout(&bprog(&["eval", "--no-prelude", "1 2 +"], "")), "3\n");
+        // without the prelude `nip` is an unknown symbol, which is left on the stack
+        assert_eq!(bprog(&["eval", "--no-prelude", "1 2 nip"], "").status.code(), Some(4));
+    }
+
+    #[test]
+    fn test_unknown_arguments() {
+        assert_eq!(bprog(&["compile"], "").status.code(), Some(1));
+        assert_eq!(bprog(&["--fast", "eval", "1"], "").status.code(), Some(1));
+        assert_eq!(bprog(&["--lexer", "bytes", "eval", "1"], "").status.code(), Some(1));
+        assert_eq!(bprog(&["--max-steps", "many", "eval", "1"], "").status.code(), Some(1));
+    }
+
+    #[test]
+    fn test_help_and_version() {
+        let output = bprog(&["--help"], "");
+        assert_eq!(output.status.code(), Some(0));
+        assert!(stdout(&output).starts_with("Usage: bprog"));
+        assert_eq!(stdout(&bprog(&["-V"], "")), format!("bprog {}\n", env!("CARGO_PKG_VERSION")));
+    }
+
+    #[test]
+    fn test_output_json() {
+        let output = bprog(&["--output", "json", "eval", "[ 1 \" a b \" 2.5 False [ ] ]"], "");
+        assert_eq!(stdout(&output), "[1,\"a b\",2.5,false,[]]\n");
+        let output = bprog(&["--output", "json", "--lexer", "chars", "eval", r#""say \"hi\"\n""#], "");
+        assert_eq!(stdout(&output), "\"say \\\"hi\\\"\\n\"\n");
+        assert_eq!(stdout(&bprog(&["--output", "json", "eval", "{ 1 + }"], "")), "\"{ 1 + }\"\n");
+    }
+
+    #[test]
+    fn test_max_steps() {
+        let program = "0 10 times { 1 + }";
+        assert_eq!(stdout(&bprog(&["--max-steps", "100", "eval", program], "")), "10\n");
+        let output = bprog(&["--max-steps", "10", "eval", program], "");
+        assert_eq!(output.status.code(), Some(3));
+        assert!(stderr(&output).starts_with("StepLimitExceeded"));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let program = "2 127 pow";
+        assert_eq!(stdout(&bprog(&["eval", program], "")), "170141183460469231731687303715884105728\n");
+        let output = bprog(&["--overflow", "error", "eval", program], "");
+        assert_eq!(output.status.code(), Some(3));
+        assert!(stderr(&output).starts_with("IntegerOverflow"));
+        assert!(!bprog(&["--overflow", "wrap", "eval", program], "").status.success());
+    }
+
+    #[test]
+    fn test_division() {
+        assert_eq!(stdout(&bprog(&["eval", "1 4 /"], "")), "0.25\n");
+        assert_eq!(stdout(&bprog(&["--division", "exact", "eval", "1 4 /"], "")), "1/4\n");
+        assert!(!bprog(&["--division", "decimal", "eval", "1 4 /"], "").status.success());
+    }
+
+    #[test]
+    fn test_decimal_context() {
+        assert_eq!(stdout(&bprog(&["eval", "2.00d 3 /"], "")), "0.6666666667d\n");
+        let args = ["--decimal-scale", "2", "--decimal-rounding", "down", "eval", "2.00d 3 /"];
+        assert_eq!(stdout(&bprog(&args, "")), "0.66d\n");
+        assert!(!bprog(&["--decimal-rounding", "nearest", "eval", "1d"], "").status.success());
+    }
+
+    #[test]
+    fn test_max_steps_ignores_prelude() {
+        assert_eq!(stdout(&bprog(&["--max-steps", "3", "eval", "1 2 +"], "")), "3\n");
+    }
+
+    #[test]
+    fn test_trace() {
+        let output = bprog(&["--trace", "eval", "1 2 +"], "");
+        assert_eq!(stdout(&output), "3\n");
+        let trace = stderr(&output);
+        assert_eq!(trace.lines().count(), 3);
+        assert!(trace.lines().last().unwrap().starts_with("trace : +"));
+        assert!(trace.lines().last().unwrap().ends_with("stack : 1 2"));
+    }
+
+    #[test]
+    fn test_check() {
+        let valid = temp_file("check_valid.bprog", "[ 1 2 ] # not closed: [\n");
+        let invalid = temp_file("check_invalid.bprog", "{ 1 2");
+        assert_eq!(bprog(&["check", &valid], "").status.code(), Some(0));
+        let output = bprog(&["check", &valid, &invalid], "");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(stderr(&output).contains("IncompleteQuotation at line 1, column 1"));
+        assert_eq!(bprog(&["check"], "[ 1").status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_fmt() {
+        let input = "  double { 2 *\n}   fun\n\n\n[ 1\n[ 2 3 ]\n  ]\n";
+        let output = bprog(&["fmt"], input);
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(stdout(&output), "double { 2 *\n}   fun\n\n[ 1\n    [ 2 3 ]\n]\n");
+    }
+
+    #[test]
+    fn test_fmt_keeps_strings_and_comments() {
+        let input = "\" a   [ b \"\n{ ( [ )\n  1 } # {\n";
+        assert_eq!(stdout(&bprog(&["fmt"], input)), "\" a   [ b \"\n{ ( [ )\n    1 } # {\n");
+        let input = "[ \"first\n    second\" ]\n";
+        assert_eq!(stdout(&bprog(&["--lexer", "chars", "fmt"], input)), input);
+    }
+
+    #[test]
+    fn test_fmt_parse_error() {
+        assert_eq!(bprog(&["fmt"], "[ 1").status.code(), Some(2));
+    }
+
+    #[test]
+    fn test_test_files() {
+        let output = bprog(&["test", "tests/prelude.bprog"], "");
+        assert_eq!(output.status.code(), Some(0), "{}", stdout(&output));
+        assert!(stdout(&output).contains("test result: ok."));
+    }
+
+    #[test]
+    fn test_test_failures() {
+        let file = temp_file("failing.bprog", "1 2 +\n# expect: 3\n2\n# expect: 3\n1 0 div\n# expect: DivisionByZero\n[ 1\n# expect: IncompleteList\n");
+        let output = bprog(&["test", &file], "");
+        assert_eq!(output.status.code(), Some(5));
+        assert!(stdout(&output).contains(":4 ... FAILED\n    expected: 3\n    found   : 2"));
+        assert!(stdout(&output).contains("test result: FAILED. 3 passed; 1 failed"));
+    }
+
+    #[test]
+    fn test_test_missing_path() {
+        assert_eq!(bprog(&["test", "does_not_exist"], "").status.code(), Some(1));
+    }
+}
+
+mod test_interpreter_api {
+    use bprog::{Error, Interpreter, LexerMode, Limits, Options, ParserError, Prelude, ProgramError, Token};
+
+    #[test]
+    fn test_eval() {
+        let mut bprog = Interpreter::new();
+        assert_eq!(bprog.eval("1 2 +").unwrap(), Token::Int(3));
+        assert_eq!(bprog.eval("[ 1 2 3 ] sum").unwrap(), Token::Int(6));
+        assert!(bprog.stack().is_empty());
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let mut bprog = Interpreter::new();
+        assert!(matches!(bprog.eval("[ 1 2"), Err(Error::Parser(ParserError::IncompleteList(_)))));
+        let e = bprog.eval("1 0 div").unwrap_err();
+        assert!(matches!(&e, Error::Program(e) if matches!(e.kind(), ProgramError::DivisionByZero)));
+        assert!(e.to_string().starts_with("DivisionByZero in `div` at line 1, column 5"));
+        assert!(matches!(bprog.eval("1 2"), Err(Error::Program(ProgramError::ProgramFinishedWithMultipleValues))));
+        // the interpreter can be used again after an error
+        bprog.clear_stack();
+        assert_eq!(bprog.eval("5").unwrap(), Token::Int(5));
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut bprog = Interpreter::new();
+        bprog.push(20);
+        bprog.push(22);
+        bprog.run("+").unwrap();
+        assert_eq!(bprog.stack(), &[Token::Int(42)]);
+        assert_eq!(bprog.pop::<i64>().unwrap(), 42);
+        assert!(matches!(bprog.pop::<i64>(), Err(Error::Program(ProgramError::StackEmpty))));
+
+        bprog.push(vec!["a b", "c"]);
+        bprog.push(true);
+        bprog.push(2.5);
+        assert_eq!(bprog.peek(), Some(&Token::Float(2.5)));
+        assert_eq!(bprog.pop::<f64>().unwrap(), 2.5);
+        assert!(bprog.pop::<bool>().unwrap());
+        assert_eq!(bprog.pop::<Vec<Token>>().unwrap(), vec![Token::from("a b"), Token::from("c")]);
+    }
+
+    #[test]
+    fn test_pop_wrong_type_keeps_value() {
+        let mut bprog = Interpreter::new();
+        bprog.push("text");
+        assert!(matches!(bprog.pop::<i64>(), Err(Error::Program(ProgramError::ExpectedNumber))));
+        assert!(matches!(bprog.pop::<bool>(), Err(Error::Program(ProgramError::ExpectedBool))));
+        assert_eq!(bprog.pop::<String>().unwrap(), "text");
+    }
+
+    #[test]
+    fn test_eval_on_pushed_values() {
+        let mut bprog = Interpreter::new();
+        bprog.push(vec![1, 2, 3]);
+        assert_eq!(bprog.eval("map { 10 * }").unwrap(), Token::from(vec![10, 20, 30]));
+    }
+
+    #[test]
+    fn test_bindings_and_functions() {
+        let mut bprog = Interpreter::new();
+        bprog.set_binding("limit", 10);
+        bprog.define_function("square", "dup *").unwrap();
+        assert_eq!(bprog.eval("limit square").unwrap(), Token::Int(100));
+        bprog.run("name \" bprog \" :=").unwrap();
+        assert_eq!(bprog.binding("name"), Some(Token::from("bprog")));
+        assert_eq!(bprog.function("square").map(|f| f.to_string()), Some("{ dup * }".to_string()));
+        assert!(matches!(bprog.define_function("broken", "{ 1"), Err(Error::Parser(_))));
+    }
+
+    #[test]
+    fn test_state_is_kept_between_calls() {
+        let mut bprog = Interpreter::new();
+        bprog.run("inc { 1 + } fun 1").unwrap();
+        bprog.run("inc inc").unwrap();
+        assert_eq!(bprog.pop::<i64>().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_clones_are_independent() {
+        let mut bprog = Interpreter::new();
+        bprog.run("x 1 :=").unwrap();
+        let mut copy = bprog.clone();
+        copy.run("' x 2 := y 3 :=").unwrap();
+        assert_eq!(bprog.binding("x"), Some(Token::Int(1)));
+        assert_eq!(bprog.binding("y"), None);
+        assert_eq!(copy.binding("x"), Some(Token::Int(2)));
+        bprog.set_binding("x", 4);
+        assert_eq!(copy.eval("x").unwrap(), Token::Int(2));
+    }
+
+    #[test]
+    fn test_with_options() {
+        let options = Options { prelude: Prelude::Disabled, lexer_mode: LexerMode::Characters, ..Options::default() };
+        let mut bprog = Interpreter::with_options(&options).unwrap();
+        assert_eq!(bprog.eval(r#""a\tb""#).unwrap(), Token::from("a\tb"));
+        assert_eq!(bprog.eval("1 2 nip").unwrap_err().to_string(), "ProgramFinishedWithMultipleValues");
+        bprog.clear_stack();
+        bprog.load_prelude(&Prelude::Bundled).unwrap();
+        assert_eq!(bprog.eval("1 2 nip").unwrap(), Token::Int(2));
+
+        let options = Options { prelude: Prelude::File("does_not_exist.bprog".to_string()), ..Options::default() };
+        assert!(matches!(Interpreter::with_options(&options), Err(Error::Prelude(_))));
+    }
+
+    #[test]
+    fn test_max_steps_per_call() {
+        let options = Options { limits: Limits { max_steps: Some(10), ..Limits::default() }, ..Options::default() };
+        let mut bprog = Interpreter::with_options(&options).unwrap();
+        assert_eq!(bprog.eval("1 2 3 4 + + +").unwrap(), Token::Int(10));
+        assert_eq!(bprog.eval("1 2 3 4 + + +").unwrap(), Token::Int(10));
+        assert!(matches!(bprog.eval("0 20 times { 1 + }").unwrap_err(),
+                         Error::Program(e) if matches!(e.kind(), ProgramError::StepLimitExceeded)));
+    }
+
+    #[test]
+    fn test_failed_prelude_keeps_limits() {
+        let limits = Limits { max_steps: Some(10), max_stack_depth: Some(5), max_collection_size: Some(3), ..Limits::default() };
+        let options = Options { limits, ..Options::default() };
+        let mut bprog = Interpreter::with_options(&options).unwrap();
+        let path = std::env::temp_dir().join("bprog_malformed_prelude.bprog");
+        std::fs::write(&path, "f { 1 2").unwrap();
+        let prelude = Prelude::File(path.to_string_lossy().to_string());
+        assert!(matches!(bprog.load_prelude(&prelude), Err(Error::Prelude(_))));
+        assert_eq!(bprog.limits().max_steps, Some(10));
+        assert_eq!(bprog.limits().max_stack_depth, Some(5));
+        assert_eq!(bprog.limits().max_collection_size, Some(3));
+        assert!(matches!(bprog.eval("0 20 times { 1 + }").unwrap_err(),
+                         Error::Program(e) if matches!(e.kind(), ProgramError::StepLimitExceeded)));
+        std::fs::write(&path, "1 0 div").unwrap();
+        assert!(matches!(bprog.load_prelude(&prelude), Err(Error::Prelude(_))));
+        assert_eq!(bprog.limits().max_steps, Some(10));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_errors_implement_std_error() {
+        fn source_of(e: &dyn std::error::Error) -> Option<String> {
+            e.source().map(|s| s.to_string())
+        }
+        let e = Interpreter::new().eval("1 0 div").unwrap_err();
+        assert!(source_of(&e).unwrap().starts_with("DivisionByZero in `div`"));
+        let boxed: Box<dyn std::error::Error> = Box::new(e);
+        assert!(boxed.to_string().starts_with("DivisionByZero"));
+    }
+}
+
+mod test_native_words {
+    use bprog::{Error, Interpreter, ProgramError, Token};
+
+    #[test]
+    fn test_register_binary() {
+        let mut bprog = Interpreter::new();
+        bprog.register_binary("hypot", |a, b, _| {
+            let (a, b) = (f64::try_from(a)?, f64::try_from(b)?);
+            Ok(Some(Token::Float((a * a + b * b).sqrt())))
+        });
+        assert_eq!(bprog.eval("3 4 hypot").unwrap(), Token::Float(5.0));
+        assert_eq!(bprog.eval("[ [ 6 8 ] ] map { dup head swap tail head hypot }").unwrap(), Token::from(vec![10.0]));
+    }
+
+    #[test]
+    fn test_register_unary() {
+        let mut bprog = Interpreter::new();
+        bprog.register_unary("shout", |token, _| Ok(Some(Token::from(String::try_from(token)?.to_uppercase()))));
+        assert_eq!(bprog.eval("\" hello \" shout").unwrap(), Token::from("HELLO"));
+        let e = bprog.eval("1 shout").unwrap_err();
+        assert!(e.to_string().starts_with("ExpectedString in `shout` at line 1, column 3 (found Int)"));
+    }
+
+    #[test]
+    fn test_register_with_state() {
+        let mut bprog = Interpreter::new();
+        bprog.register("depth", 0, |state| Ok(Some(Token::Int(state.len() as i128))));
+        bprog.register("clear", 0, |state| {
+            while !state.is_empty() {
+                state.stack_pop()?;
+            }
+            Ok(None)
+        });
+        bprog.run("1 2 3 depth").unwrap();
+        assert_eq!(bprog.pop::<i64>().unwrap(), 3);
+        assert_eq!(bprog.eval("clear 7").unwrap(), Token::Int(7));
+    }
+
+    #[test]
+    fn test_arity_is_checked() {
+        let mut bprog = Interpreter::new();
+        bprog.register_binary("pair", |a, b, _| Ok(Some(Token::List(vec![a, b]))));
+        assert!(matches!(bprog.eval("1 pair"), Err(Error::Program(e)) if matches!(e.kind(), ProgramError::StackEmpty)));
+        // the operand is left on the stack
+        assert_eq!(bprog.stack(), &[Token::Int(1)]);
+    }
+
+    #[test]
+    fn test_replace_and_remove_builtins() {
+        let mut bprog = Interpreter::new();
+        assert!(bprog.builtins().contains("print"));
+        bprog.register_unary("print", |_, _| Ok(Some(Token::from("suppressed"))));
+        assert_eq!(bprog.eval("1 print").unwrap(), Token::from("suppressed"));
+        assert!(bprog.builtins_mut().remove("read"));
+        // an unknown word is pushed onto the stack as a symbol
+        assert_eq!(bprog.eval("read").unwrap().to_string(), "read");
+    }
+
+    #[test]
+    fn test_registries_are_independent() {
+        let mut first = Interpreter::new();
+        let mut second = first.clone();
+        first.register_unary("twice", |token, _| token.clone() + token);
+        assert_eq!(first.eval("21 twice").unwrap(), Token::Int(42));
+        assert!(!second.builtins().contains("twice"));
+        assert!(matches!(second.eval("21 twice"), Err(Error::Program(ProgramError::ProgramFinishedWithMultipleValues))));
+    }
+}
+
+mod test_limits {
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use bprog::{run_with, CancelFlag, Interpreter, Limits};
+
+    /// Evaluates a program with the given limits and returns the name of the error
+    fn limited(limits: Limits, program: &str) -> String {
+        run_with(|bprog| bprog.set_limits(limits), program)
+    }
+
+    #[test]
+    fn test_no_limits_by_default() {
+        assert_eq!(limited(Limits::default(), "0 10000 times { 1 + }"), "10000");
+    }
+
+    #[test]
+    fn test_step_limit() {
+        let limits = Limits { max_steps: Some(1000), ..Limits::default() };
+        assert_eq!(limited(limits.clone(), "0 loop { False } { 1 + }"), "StepLimitExceeded");
+        // instructions executed by map count as well
+        assert_eq!(limited(limits, "[ 1 ] map { loop { False } { } }"), "StepLimitExceeded");
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let limits = Limits { max_stack_depth: Some(100), ..Limits::default() };
+        assert_eq!(limited(limits.clone(), "1 99 times { dup }"), "ProgramFinishedWithMultipleValues");
+        assert_eq!(limited(limits.clone(), "1 loop { False } { dup }"), "StackLimitExceeded");
+        let mut bprog = Interpreter::new();
+        bprog.set_limits(limits);
+        assert!(bprog.eval("1 2 3 100 times { dup }").unwrap_err().to_string()
+            .starts_with("StackLimitExceeded in `dup` at line 1, column 19"));
+    }
+
+    #[test]
+    fn test_collection_limit() {
+        let limits = Limits { max_collection_size: Some(10), ..Limits::default() };
+        assert_eq!(limited(limits.clone(), "[ ] 10 times { 1 swap cons } length"), "10");
+        assert_eq!(limited(limits.clone(), "[ ] loop { False } { 1 swap cons }"), "CollectionLimitExceeded");
+        assert_eq!(limited(limits.clone(), "[ 1 2 3 4 5 6 7 8 9 10 11 ]"), "CollectionLimitExceeded");
+        assert_eq!(limited(limits, "\" a b c d e f \""), "CollectionLimitExceeded");
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let limits = Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() };
+        let start = Instant::now();
+        assert_eq!(limited(limits.clone(), "forever { forever } fun forever"), "TimeLimitExceeded");
+        assert!(start.elapsed() < Duration::from_secs(5));
+        // the clock starts again for every program
+        let mut bprog = Interpreter::new();
+        bprog.set_limits(limits);
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(bprog.eval("1 2 +").unwrap().to_string(), "3");
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let flag = CancelFlag::new();
+        let limits = Limits { cancel: Some(flag.clone()), ..Limits::default() };
+        let canceller = {
+            let flag = flag.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                flag.cancel();
+            })
+        };
+        assert_eq!(limited(limits.clone(), "0 loop { False } { 1 + }"), "Cancelled");
+        canceller.join().unwrap();
+        assert!(flag.is_cancelled());
+        flag.reset();
+        assert_eq!(limited(limits, "1 2 +"), "3");
+    }
+
+    #[test]
+    fn test_limits_from_command_line() {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_bprog"))
+            .args(["--timeout", "50", "eval", "forever { forever } fun forever"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("TimeLimitExceeded"));
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_bprog"))
+            .args(["--max-stack", "3", "--max-collection", "2", "eval", "[ 1 2 3 ]"])
+            .output()
+            .unwrap();
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("CollectionLimitExceeded"));
+    }
+}
+
+mod test_capabilities {
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+    use bprog::{run_with, Capabilities, Capability, Interpreter, Token};
+
+    /// Evaluates a program in an interpreter with the given capabilities
+    fn with_capabilities(capabilities: Capabilities, program: &str) -> String {
+        run_with(|bprog| bprog.set_capabilities(capabilities), program)
+    }
+
+    #[test]
+    fn test_pure_sandbox_denies_side_effects() {
+        for program in ["1 print 2", "read", ":b 1", ":f 1", ":q", "\" Cargo.toml \" readFile",
+                        "\" x \" \" out.txt \" writeFile 1", "\" HOME \" getEnv"] {
+            assert_eq!(with_capabilities(Capabilities::pure(), program), "CapabilityDenied", "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_pure_sandbox_allows_computation() {
+        assert_eq!(with_capabilities(Capabilities::pure(), "[ 1 2 3 ] map { 2 * } sum"), "12");
+    }
+
+    #[test]
+    fn test_denied_error_names_the_word() {
+        let mut bprog = Interpreter::new();
+        bprog.set_capabilities(Capabilities::pure());
+        assert!(bprog.eval("1 print").unwrap_err().to_string().starts_with("CapabilityDenied in `print` at line 1, column 3"));
+        // the operand stays on the stack
+        assert_eq!(bprog.stack(), &[Token::Int(1)]);
+    }
+
+    #[test]
+    fn test_files_and_environment_are_denied_by_default() {
+        let bprog = Interpreter::new();
+        assert_eq!(bprog.capabilities(), Capabilities::default());
+        assert!(bprog.capabilities().allows(Capability::Io));
+        assert!(!bprog.capabilities().allows(Capability::Files));
+        assert_eq!(run_with(|_| (), "\" Cargo.toml \" readFile"), "CapabilityDenied");
+        assert_eq!(run_with(|_| (), "\" HOME \" getEnv"), "CapabilityDenied");
+        assert_eq!(run_with(|_| (), "\" x \" \" out.txt \" writeFile 1"), "CapabilityDenied");
+    }
+
+    #[test]
+    fn test_individual_capabilities() {
+        let mut capabilities = Capabilities::pure();
+        capabilities.set(Capability::Env, true);
+        assert!(capabilities.allows(Capability::Env));
+        assert!(!capabilities.allows(Capability::Files));
+        assert_eq!(with_capabilities(capabilities, "\" BPROG_SURELY_UNSET \" getEnv"), "False");
+        assert_eq!(with_capabilities(capabilities, "\" Cargo.toml \" readFile"), "CapabilityDenied");
+        assert_eq!(Capability::from_name("files"), Some(Capability::Files));
+        assert_eq!(Capability::from_name("network"), None);
+    }
+
+    #[test]
+    fn test_files_and_environment() {
+        let path = env::temp_dir().join("bprog_capabilities.txt");
+        let path = path.to_string_lossy();
+        let program = format!("\" hello file \" \" {} \" writeFile \" {} \" readFile", path, path);
+        assert_eq!(with_capabilities(Capabilities::all(), &program), "\"hello file\"");
+        assert_eq!(fs::read_to_string(path.as_ref()).unwrap(), "hello file");
+        assert_eq!(with_capabilities(Capabilities::all(), "\" does_not_exist.txt \" readFile"), "IoError");
+        assert_eq!(with_capabilities(Capabilities::all(), "1 readFile"), "ExpectedString");
+        let expected = env::var("PATH").map_or("False".to_string(), |path| format!("\"{}\"", path));
+        assert_eq!(with_capabilities(Capabilities::all(), "\" PATH \" getEnv"), expected);
+    }
+
+    #[test]
+    fn test_host_words_can_require_capabilities() {
+        let mut bprog = Interpreter::new();
+        bprog.register("now", 0, |_| Ok(Some(Token::Int(0))));
+        assert!(bprog.builtins_mut().require("now", Capability::Env));
+        assert!(!bprog.builtins_mut().require("missing", Capability::Env));
+        bprog.set_capabilities(Capabilities::pure());
+        assert!(bprog.eval("now").is_err());
+    }
+
+    #[test]
+    fn test_sandbox_from_command_line() {
+        let run = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_bprog")).args(args).output().unwrap();
+        let output = run(&["--sandbox", "eval", ":q"]);
+        assert_eq!(output.status.code(), Some(3));
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("CapabilityDenied"));
+        let output = run(&["--sandbox", "--allow", "io", "eval", "1 print 2"]);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n2\n");
+        let output = run(&["--deny", "io,exit", "eval", "1 print 2"]);
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(run(&["--allow", "network", "eval", "1"]).status.code(), Some(1));
+        assert!(String::from_utf8_lossy(&run(&["eval", "\" PATH \" getEnv"]).stderr).starts_with("CapabilityDenied"));
+        assert_eq!(run(&["--allow", "env", "eval", "\" BPROG_SURELY_UNSET \" getEnv"]).stdout, b"False\n");
+    }
+}
+
+mod test_streams {
+    use std::io::{self, Cursor};
+    use bprog::{Interpreter, Options, SharedBuffer, Streams, Token};
+
+    /// Creates an interpreter that reads the given input and writes to the returned buffers
+    fn scripted(input: &str) -> (Interpreter, SharedBuffer, SharedBuffer) {
+        let (output, error) = (SharedBuffer::new(), SharedBuffer::new());
+        let mut bprog = Interpreter::new();
+        bprog.set_streams(Streams::new(Cursor::new(input.to_string()), output.clone(), error.clone()));
+        (bprog, output, error)
+    }
+
+    #[test]
+    fn test_print() {
+        let (mut bprog, output, _) = scripted("");
+        assert_eq!(bprog.eval("1 print [ 1 2 ] print \" text \" print 3").unwrap(), Token::Int(3));
+        assert_eq!(output.contents(), "1\n[1,2]\n\"text\"\n");
+    }
+
+    #[test]
+    fn test_print_inside_quotations() {
+        let (mut bprog, output, _) = scripted("");
+        bprog.run("[ 1 2 3 ] each { print } 2 times { 0 print }").unwrap();
+        assert_eq!(output.contents(), "1\n2\n3\n0\n0\n");
+    }
+
+    #[test]
+    fn test_read() {
+        let (mut bprog, output, _) = scripted("first line\r\nsecond line\n42");
+        assert_eq!(bprog.eval("read").unwrap(), Token::from("first line"));
+        assert_eq!(bprog.eval("read words").unwrap(), Token::from(vec!["second", "line"]));
+        assert_eq!(bprog.eval("read parseInteger 1 +").unwrap(), Token::Int(43));
+        // at the end of the input `read` returns an empty string
+        assert_eq!(bprog.eval("read").unwrap(), Token::from(""));
+        assert_eq!(output.contents(), "");
+    }
+
+    #[test]
+    fn test_read_with_prompt() {
+        let output = SharedBuffer::new();
+        let mut streams = Streams::new(Cursor::new("Ada\n"), output.clone(), io::sink());
+        streams.prompt = Some("name".to_string());
+        let mut bprog = Interpreter::new();
+        bprog.set_streams(streams);
+        bprog.run("read print").unwrap();
+        assert_eq!(output.contents(), "name > \"Ada\"\n");
+    }
+
+    #[test]
+    fn test_guess_the_number() {
+        let (mut bprog, output, _) = scripted("50\n20\n30\n");
+        let program = "
+            secret 30 :=
+            guess { read parseInteger } fun
+            guess loop { dup secret == } {
+                secret > if { \" too big \" print } { \" too small \" print } guess
+            }";
+        assert_eq!(bprog.eval(program).unwrap(), Token::Int(30));
+        assert_eq!(output.contents(), "\"too big\"\n\"too small\"\n");
+    }
+
+    #[test]
+    fn test_bindings_and_functions_listing() {
+        let output = SharedBuffer::new();
+        let options = Options { prelude: bprog::Prelude::Disabled, ..Options::default() };
+        let mut bprog = Interpreter::with_options(&options).unwrap();
+        bprog.set_streams(Streams::new(io::empty(), output.clone(), io::sink()));
+        bprog.run("b 2 := a 1 := inc { 1 + } fun :b :f").unwrap();
+        assert_eq!(output.contents(), "bindings : [ a = 1 ] [ b = 2 ] \nfunctions : [ inc = { 1 + } ] \n");
+    }
+
+    #[test]
+    fn test_trace_goes_to_error_stream() {
+        let options = Options { trace: true, ..Options::default() };
+        let (output, error) = (SharedBuffer::new(), SharedBuffer::new());
+        let mut bprog = Interpreter::with_options(&options).unwrap();
+        bprog.set_streams(Streams::new(io::empty(), output.clone(), error.clone()));
+        bprog.eval("1 print 2").unwrap();
+        assert_eq!(output.contents(), "1\n");
+        assert_eq!(error.contents().lines().count(), 3);
+        assert!(error.contents().starts_with("trace : 1 "));
+    }
+
+    #[test]
+    fn test_failing_output_is_an_error() {
+        struct Broken;
+        impl io::Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken pipe"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let mut bprog = Interpreter::new();
+        bprog.set_streams(Streams::new(io::empty(), Broken, io::sink()));
+        assert!(bprog.eval("1 print").unwrap_err().to_string().starts_with("IoError in `print`"));
+    }
+}
+
+mod test_arithmetic_words {
+    use bprog::t;
+
+    #[test]
+    fn test_mod_and_rem() {
+        assert_eq!(t("7 3 mod"), "1");
+        assert_eq!(t("-7 3 mod"), "2");
+        assert_eq!(t("7 -3 mod"), "-2");
+        assert_eq!(t("-7 3 rem"), "-1");
+        assert_eq!(t("7 -3 rem"), "1");
+        assert_eq!(t("-7 3 div 3 * -7 3 rem +"), "-7");
+        assert_eq!(t("7.5 2 mod"), "1.5");
+        assert_eq!(t("-7.5 2 mod"), "0.5");
+        assert_eq!(t("-7.5 2 rem"), "-1.5");
+    }
+
+    #[test]
+    fn test_mod_and_rem_of_rationals() {
+        assert_eq!(t("7 2 rational 1 mod"), "1/2");
+        assert_eq!(t("-7 2 rational 2 mod"), "1/2");
+        assert_eq!(t("-7 2 rational 2 rem"), "-3/2");
+        assert_eq!(t("7 2 rational -1 3 rational mod"), "-1/6");
+        assert_eq!(t("7 2 rational 1 2 rational rem"), "0");
+        assert_eq!(t("1 2 rational 0 mod"), "DivisionByZero");
+    }
+
+    #[test]
+    fn test_mod_and_rem_by_zero() {
+        assert_eq!(t("1 0 mod"), "DivisionByZero");
+        assert_eq!(t("1 0 rem"), "DivisionByZero");
+        assert_eq!(t("1.5 0.0 mod"), "DivisionByZero");
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(t("2 10 pow"), "1024");
+        assert_eq!(t("2 10 **"), "1024");
+        assert_eq!(t("-3 3 pow"), "-27");
+        assert_eq!(t("5 0 pow"), "1");
+        assert_eq!(t("2 -1 pow"), "0.5");
+        assert_eq!(t("2.0 3 pow"), "8.0");
+        assert_eq!(t("4 0.5 pow"), "2.0");
+        assert_eq!(t("10 40 pow"), "10000000000000000000000000000000000000000");
+    }
+
+    #[test]
+    fn test_negative_power_of_zero() {
+        assert_eq!(t("0 -1 pow"), "DivisionByZero");
+        assert_eq!(t("0.0 -1 pow"), "DivisionByZero");
+        assert_eq!(t("0.0d -2 pow"), "DivisionByZero");
+        assert_eq!(t("0 1 2 rational neg pow"), "DivisionByZero");
+        assert_eq!(t("0 0 pow"), "1");
+    }
+
+    #[test]
+    fn test_abs_neg_sign() {
+        assert_eq!(t("-5 abs"), "5");
+        assert_eq!(t("-2.5 abs"), "2.5");
+        assert_eq!(t("5 neg"), "-5");
+        assert_eq!(t("-2.5 neg"), "2.5");
+        assert_eq!(t("-5 sign"), "-1");
+        assert_eq!(t("0 sign"), "0");
+        assert_eq!(t("2.5 sign"), "1.0");
+        assert_eq!(t("0.0 sign"), "0.0");
+        assert_eq!(t("True abs"), "ExpectedNumber");
+        assert_eq!(t("\" 1 \" neg"), "ExpectedNumber");
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(t("3 7 min"), "3");
+        assert_eq!(t("3 7 max"), "7");
+        assert_eq!(t("3 2.5 min"), "2.5");
+        assert_eq!(t("3 2.5 max"), "3.0");
+        assert_eq!(t("[ 4 9 2 ] 0 foldl max"), "9");
+        assert_eq!(t("1 True max"), "NumberConversionError");
+    }
+
+    #[test]
+    fn test_fizzbuzz() {
+        assert_eq!(t("fizzbuzz { dup 15 mod 0 == if { pop \" FizzBuzz \" } \
+                                { dup 3 mod 0 == if { pop \" Fizz \" } \
+                                { dup 5 mod 0 == if { pop \" Buzz \" } { } } } } fun \
+                      [ 9 10 14 15 ] map { fizzbuzz }"), "[\"Fizz\",\"Buzz\",14,\"FizzBuzz\"]");
+    }
+
+    #[test]
+    fn test_definitions_shadow_builtins() {
+        assert_eq!(t("' max 10 := max"), "10");
+        assert_eq!(t("' abs { pop 42 } fun -1 abs"), "42");
+    }
+}
+
+mod test_strings {
+    use bprog::t;
+
+    #[test]
+    fn test_concatenation() {
+        assert_eq!(t("\" ab \" \" cd \" append"), "\"abcd\"");
+        assert_eq!(t("\" a \" \" bc \" cons"), "\"abc\"");
+        assert_eq!(t("\" ab \" [ 1 ] append"), "ExpectedList");
+    }
+
+    #[test]
+    fn test_unicode_length() {
+        assert_eq!(t("\" héllo \" length"), "5");
+        assert_eq!(t("\" 日本語 \" length"), "3");
+        assert_eq!(t("\" 日本語 \" chars"), "[\"日\",\"本\",\"語\"]");
+    }
+
+    #[test]
+    fn test_substring_and_index_of() {
+        assert_eq!(t("\" hello \" 1 3 substring"), "\"el\"");
+        assert_eq!(t("\" héllo \" 1 5 substring"), "\"éllo\"");
+        assert_eq!(t("\" hello \" 2 2 substring"), "\"\"");
+        assert_eq!(t("\" hello \" 3 9 substring"), "IndexOutOfRange");
+        assert_eq!(t("\" hello \" 3 1 substring"), "IndexOutOfRange");
+        assert_eq!(t("\" hello \" -1 2 substring"), "IndexOutOfRange");
+        assert_eq!(t("\" héllo \" \" llo \" indexOf"), "2");
+        assert_eq!(t("\" hello \" \" xyz \" indexOf"), "-1");
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        assert_eq!(t("\" a,b,,c \" \" , \" split"), "[\"a\",\"b\",\"\",\"c\"]");
+        assert_eq!(t("\" abc \" \" \" split"), "[\"a\",\"b\",\"c\"]");
+        assert_eq!(t("[ \" a \" \" b \" ] \" - \" join"), "\"a-b\"");
+        assert_eq!(t("[ ] \" - \" join"), "\"\"");
+        assert_eq!(t("\" a,b \" \" , \" split \" ; \" join"), "\"a;b\"");
+        assert_eq!(t("[ \" a \" 1 ] \" - \" join"), "ExpectedString");
+    }
+
+    #[test]
+    fn test_case_and_whitespace() {
+        // the lexer trims string literals, so the space comes from splitting
+        assert_eq!(t("\" a b \" \" a \" split tail head"), "\" b\"");
+        assert_eq!(t("\" a b \" \" a \" split tail head trim"), "\"b\"");
+        assert_eq!(t("\" Hello \" upper"), "\"HELLO\"");
+        assert_eq!(t("\" Hello \" lower"), "\"hello\"");
+        assert_eq!(t("\" éa \" upper"), "\"ÉA\"");
+        assert_eq!(t("5 upper"), "ExpectedString");
+    }
+
+    #[test]
+    fn test_replace_and_predicates() {
+        assert_eq!(t("\" aXbXc \" \" X \" \" -- \" replace"), "\"a--b--c\"");
+        assert_eq!(t("\" hello \" \" he \" startsWith"), "True");
+        assert_eq!(t("\" hello \" \" lo \" endsWith"), "True");
+        assert_eq!(t("\" hello \" \" he \" endsWith"), "False");
+        assert_eq!(t("\" hello \" \" ell \" contains"), "True");
+        assert_eq!(t("\" hello \" \" elo \" contains"), "False");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(t("\" abc \" chars fromChars"), "\"abc\"");
+        assert_eq!(t("[ \" ab \" \" c \" ] fromChars"), "\"abc\"");
+        assert_eq!(t("[ 1 ] fromChars"), "ExpectedString");
+        assert_eq!(t("42 toString"), "\"42\"");
+        assert_eq!(t("[ 1 2.5 ] toString"), "\"[1,2.5]\"");
+        assert_eq!(t("\" x \" toString"), "\"x\"");
+        assert_eq!(t("True toString \" ! \" append"), "\"True!\"");
+    }
+}
+
+mod test_format {
+    use bprog::t;
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(t("\" apples \" 3 \" {} has {} items \" format"), "\"apples has 3 items\"");
+        assert_eq!(t("[ 1 2.5 [ 3 ] ] \" {}; {}; {} \" format"), "\"1; 2.5; [3]\"");
+        assert_eq!(t("2 \" {{{}}} \" format"), "\"{2}\"");
+        assert_eq!(t("\" no placeholders \" format"), "\"no placeholders\"");
+        assert_eq!(t("[ [ 1 2 ] ] \" {} \" format"), "\"[1,2]\"");
+    }
+
+    #[test]
+    fn test_width_and_alignment() {
+        assert_eq!(t("42 \" [{:5}] \" format"), "\"[   42]\"");
+        assert_eq!(t("\" ab \" \" [{:5}] \" format"), "\"[ab   ]\"");
+        assert_eq!(t("42 \" [{:<5}] \" format"), "\"[42   ]\"");
+        assert_eq!(t("\" ab \" \" [{:*^6}] \" format"), "\"[**ab**]\"");
+        assert_eq!(t("7 \" {:0>3} \" format"), "\"007\"");
+    }
+
+    #[test]
+    fn test_precision() {
+        assert_eq!(t("3.14159 \" {:.2} \" format"), "\"3.14\"");
+        assert_eq!(t("5 \" {:.2} \" format"), "\"5.00\"");
+        assert_eq!(t("1 3 rational \" {:.3} \" format"), "\"0.333\"");
+        assert_eq!(t("2.345d \" {:.2} \" format"), "\"2.34\"");
+        assert_eq!(t("2.5 \" {:8.3} \" format"), "\"   2.500\"");
+        assert_eq!(t("\" abcdef \" \" {:.3} \" format"), "\"abc\"");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(t("1 \" {} {} \" format"), "StackEmpty");
+        assert_eq!(t("[ 1 ] \" {} {} \" format"), "IndexOutOfRange");
+        assert_eq!(t("1 \" { \" format"), "InvalidFormat");
+        assert_eq!(t("1 \" } \" format"), "InvalidFormat");
+        assert_eq!(t("1 \" {:x} \" format"), "InvalidFormat");
+        assert_eq!(t("1 2 format"), "ExpectedString");
+    }
+
+    #[test]
+    fn test_bounded_specifiers() {
+        assert_eq!(t("1 \" {:>99999999999999999} \" format"), "InvalidFormat");
+        assert_eq!(t("1.5 \" {:.4294967297} \" format"), "InvalidFormat");
+        assert_eq!(t("1 \" {:.10001} \" format"), "InvalidFormat");
+        assert_eq!(t("1 \" {:10000} \" format length"), "10000");
+    }
+
+    #[test]
+    fn test_list_is_kept_without_placeholders() {
+        assert_eq!(t("[ 1 2 ] \" hello \" format pop"), "[1,2]");
+    }
+}
+
+mod test_regex {
+    use bprog::t;
+
+    #[test]
+    fn test_matches() {
+        assert_eq!(t("\" abc123def \" \" \\d+ \" matches?"), "True");
+        assert_eq!(t("\" abcdef \" \" ^\\w+\\d$ \" matches?"), "False");
+        assert_eq!(t("\" hello world \" \" \\bwor \" matches?"), "True");
+        assert_eq!(t("\" hello world \" \" \\blo \" matches?"), "False");
+        assert_eq!(t("\" Grey \" \" gr(a|e)y|Gr[ae]y \" matches?"), "True");
+    }
+
+    #[test]
+    fn test_match_groups() {
+        assert_eq!(t("\" key=value \" \" (\\w+)=(\\w+) \" match"), "[\"key=value\",\"key\",\"value\"]");
+        assert_eq!(t("\" xay \" \" a|(z) \" match"), "[\"a\",\"\"]");
+        assert_eq!(t("\" abc \" \" \\d \" match"), "[]");
+        assert_eq!(t("\" aaaa \" \" a{2,}? \" match"), "[\"aa\"]");
+        assert_eq!(t("\" <a><b> \" \" <.*> \" match"), "[\"<a><b>\"]");
+        assert_eq!(t("\" <a><b> \" \" <.*?> \" match"), "[\"<a>\"]");
+        assert_eq!(t("\" naïve \" \" (?:ï)(v) \" match"), "[\"ïv\",\"v\"]");
+    }
+
+    #[test]
+    fn test_match_all() {
+        assert_eq!(t("\" a1b22c333 \" \" [a-z](\\d+) \" matchAll"), "[[\"a1\",\"1\"],[\"b22\",\"22\"],[\"c333\",\"333\"]]");
+        assert_eq!(t("\" abc \" \" x \" matchAll"), "[]");
+        assert_eq!(t("\" ab \" \" x* \" matchAll length"), "3");
+    }
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(t("\" 2024-01-15 \" \" (\\d+)-(\\d+)-(\\d+) \" \" $3/$2/$1 \" regexReplace"), "\"15/01/2024\"");
+        assert_eq!(t("\" a b  c \" \" \\s+ \" \" _ \" regexReplace"), "\"a_b_c\"");
+        assert_eq!(t("\" 5 \" \" \\d \" \" $$$0 \" regexReplace"), "\"$5\"");
+        assert_eq!(t("\" 5 \" \" \\d \" \" $1 \" regexReplace"), "InvalidRegex");
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(t("\" a, b;c \" \" [,;] * \" regexSplit"), "[\"a\",\"b\",\"c\"]");
+        assert_eq!(t("\" abc \" \" x* \" regexSplit"), "[\"a\",\"b\",\"c\"]");
+        assert_eq!(t("\" a1b \" \" \\d \" regexSplit"), "[\"a\",\"b\"]");
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert_eq!(t("\" abc \" \" ( \" matches?"), "InvalidRegex");
+        assert_eq!(t("\" abc \" \" a) \" matches?"), "InvalidRegex");
+        assert_eq!(t("\" abc \" \" [a \" matches?"), "InvalidRegex");
+        assert_eq!(t("\" abc \" \" *a \" matches?"), "InvalidRegex");
+        assert_eq!(t("\" abc \" \" a{3,1} \" matches?"), "InvalidRegex");
+        assert_eq!(t("\" abc \" \" [z-a] \" matches?"), "InvalidRegex");
+        assert_eq!(t("\" abc \" 1 matches?"), "ExpectedString");
+    }
+
+    #[test]
+    fn test_pathological_pattern() {
+        // backtracking without memoisation would take exponential time here
+        assert_eq!(t("\" aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa! \" \" ^(a+)+$ \" matches?"), "False");
+    }
+}
+
+mod test_maps {
+    use bprog::{t, t_err, Map, Token};
+
+    #[test]
+    fn test_literal_and_display() {
+        assert_eq!(t("#{ \" b \" 2 \" a \" 1 }"), "#{\"a\":1,\"b\":2}");
+        assert_eq!(t("#{ }"), "#{}");
+        assert_eq!(t("#{ \" s \" 1 2 2 true 3 }"), "#{True:3,2:2,\"s\":1}");
+        assert_eq!(t("#{ 1 #{ 2 [ 3 ] } }"), "#{1:#{2:[3]}}");
+        assert_eq!(t("x 5 := #{ 1 x }"), "#{1:5}");
+        assert_eq!(t("{ #{ 1 2 } } exec"), "#{1:2}");
+    }
+
+    #[test]
+    fn test_literal_errors() {
+        assert!(t_err("#{ 1 }").starts_with("IncompleteMap at line 1, column 1"));
+        assert!(t_err("1\n#{ 1 2").starts_with("IncompleteMap at line 2, column 1"));
+        assert!(t_err("#{ x 2 }").starts_with("InvalidMapKey at line 1, column 1"));
+        assert!(t_err("#{ 1.5 2 }").starts_with("InvalidMapKey at line 1, column 1"));
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(t("#{ \" a \" 1 } \" a \" get"), "1");
+        assert_eq!(t("#{ \" a \" 1 } \" z \" get"), "KeyNotFound");
+        assert_eq!(t("#{ 1 2 } 1 hasKey"), "True");
+        assert_eq!(t("#{ 1 2 } 2 hasKey"), "False");
+        assert_eq!(t("[ 1 2 ] 1 get"), "ExpectedMap");
+    }
+
+    #[test]
+    fn test_updates() {
+        assert_eq!(t("#{ } \" a \" 1 put 2 \" x \" put"), "#{2:\"x\",\"a\":1}");
+        assert_eq!(t("#{ 1 2 } 1 3 put"), "#{1:3}");
+        assert_eq!(t("#{ 1 2 3 4 } 1 remove"), "#{3:4}");
+        assert_eq!(t("#{ 1 2 } 5 remove"), "#{1:2}");
+        assert_eq!(t("#{ } [ 1 ] 2 put"), "ExpectedKey");
+    }
+
+    #[test]
+    fn test_keys_values_entries() {
+        assert_eq!(t("#{ 3 4 1 2 } keys"), "[1,3]");
+        assert_eq!(t("#{ 3 4 1 2 } values"), "[2,4]");
+        assert_eq!(t("#{ 3 4 1 2 } entries"), "[[1,2],[3,4]]");
+        assert_eq!(t("#{ 3 4 1 2 } size"), "2");
+    }
+
+    #[test]
+    fn test_equality() {
+        assert_eq!(t("#{ 1 2 3 4 } #{ 3 4 1 2 } =="), "True");
+        assert_eq!(t("#{ 1 2 } #{ 1 3 } =="), "False");
+        assert_eq!(t("#{ } 1 2 put #{ 1 2 } =="), "True");
+    }
+
+    #[test]
+    fn test_iteration() {
+        assert_eq!(t("#{ 1 2 3 4 } map { tail head 10 * }"), "[20,40]");
+        assert_eq!(t("0 #{ 1 2 3 4 } each { tail head + }"), "6");
+    }
+
+    #[test]
+    fn test_map_api() {
+        let mut map = Map::new();
+        map.insert(Token::from("b"), Token::from(2)).unwrap();
+        map.insert(Token::from("a"), Token::from(1)).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&Token::from("a")), Some(&Token::from(1)));
+        assert!(map.insert(Token::from(1.5), Token::from(1)).is_err());
+        assert_eq!(Token::from(map).to_string(), "#{\"a\":1,\"b\":2}");
+    }
+}
+
+mod test_list_library {
+    use bprog::t;
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(t("[ 1 2 3 4 5 ] filter { 2 > }"), "[3,4,5]");
+        assert_eq!(t("[ { 1 } { 2 } ] filter { exec 1 > }"), "[{ 2 }]");
+        assert_eq!(t("[ 1 2 3 4 5 ] { 2 > } filter"), "InstructionListEmpty");
+        assert_eq!(t("[ ] filter { 2 > }"), "[]");
+        assert_eq!(t("[ 1 2 ] filter { 1 + }"), "ExpectedBool");
+        assert_eq!(t("1 filter { 2 > }"), "ExpectedList");
+    }
+
+    #[test]
+    fn test_reverse_and_range() {
+        assert_eq!(t("[ 1 2 3 ] reverse"), "[3,2,1]");
+        assert_eq!(t("1 5 range"), "[1,2,3,4]");
+        assert_eq!(t("5 1 range"), "[]");
+        assert_eq!(t("1 2.5 range"), "NumberConversionError");
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(t("[ 3 1 2 ] sort"), "[1,2,3]");
+        assert_eq!(t("[ 3 1.5 2 ] sort"), "[1.5,2,3]");
+        assert_eq!(t("[ \" b \" \" a \" ] sort"), "[\"a\",\"b\"]");
+        assert_eq!(t("[ [ 2 1 ] [ 1 5 ] [ 1 ] ] sort"), "[[1],[1,5],[2,1]]");
+        assert_eq!(t("[ 1 \" a \" ] sort"), "NumberConversionError");
+        assert_eq!(t("[ \" ccc \" \" a \" \" bb \" ] sortBy { length }"), "[\"a\",\"bb\",\"ccc\"]");
+        assert_eq!(t("[ 3 -1 2 ] sortBy abs"), "[-1,2,3]");
+    }
+
+    #[test]
+    fn test_zip() {
+        assert_eq!(t("[ 1 2 ] [ 3 4 5 ] zip"), "[[1,3],[2,4]]");
+        assert_eq!(t("[ 1 2 ] [ 3 4 5 ] zipWith +"), "[4,6]");
+        assert_eq!(t("[ 1 2 ] [ 3 4 ] zipWith { * }"), "[3,8]");
+    }
+
+    #[test]
+    fn test_take_drop_nth_last() {
+        assert_eq!(t("[ 1 2 3 ] 2 take"), "[1,2]");
+        assert_eq!(t("[ 1 2 3 ] 5 take"), "[1,2,3]");
+        assert_eq!(t("[ 1 2 3 ] 2 drop"), "[3]");
+        assert_eq!(t("[ 1 2 3 ] -1 drop"), "IndexOutOfRange");
+        assert_eq!(t("[ 1 2 3 ] 1 nth"), "2");
+        assert_eq!(t("[ 1 2 3 ] 3 nth"), "IndexOutOfRange");
+        assert_eq!(t("[ 1 2 3 ] last"), "3");
+        assert_eq!(t("[ ] last"), "ExpectedEnumerable");
+    }
+
+    #[test]
+    fn test_find_any_all() {
+        assert_eq!(t("[ 1 2 3 ] find { 1 > }"), "[2]");
+        assert_eq!(t("[ 1 2 3 ] find { 5 > }"), "[]");
+        assert_eq!(t("[ 1 2 3 ] any { 2 == }"), "True");
+        assert_eq!(t("[ 1 2 3 ] any { 5 == }"), "False");
+        assert_eq!(t("[ 1 2 3 ] all { 0 > }"), "True");
+        assert_eq!(t("[ 1 2 3 ] all { 1 > }"), "False");
+        assert_eq!(t("[ ] all { 0 > }"), "True");
+    }
+
+    #[test]
+    fn test_flatten_and_unique() {
+        assert_eq!(t("[ 1 [ 2 [ 3 ] ] ] flatten"), "[1,2,[3]]");
+        assert_eq!(t("[ 1 2 1 3 2 ] unique"), "[1,2,3]");
+    }
+
+    #[test]
+    fn test_group_by_and_partition() {
+        assert_eq!(t("[ 1 2 3 4 5 ] groupBy { 2 mod }"), "#{0:[2,4],1:[1,3,5]}");
+        assert_eq!(t("[ 1.5 ] groupBy { }"), "ExpectedKey");
+        assert_eq!(t("[ 1 2 3 4 5 ] partition { 2 > }"), "[[3,4,5],[1,2]]");
+    }
+
+    #[test]
+    fn test_index_of_and_contains() {
+        assert_eq!(t("[ 1 2 3 ] 2 indexOf"), "1");
+        assert_eq!(t("[ 1 2 3 ] 5 indexOf"), "-1");
+        assert_eq!(t("[ 1 [ 2 ] ] [ 2 ] contains"), "True");
+        assert_eq!(t("[ 1 2 3 ] 5 contains"), "False");
+        assert_eq!(t("\" abc \" \" b \" contains"), "True");
+    }
+
+    #[test]
+    fn test_quotation_may_name_a_function() {
+        assert_eq!(t("big { 2 > } fun [ 1 2 3 4 ] filter big"), "[3,4]");
+    }
+}
+
+mod test_scopes {
+    use bprog::{t, Interpreter, Token};
+
+    #[test]
+    fn test_named_parameters() {
+        assert_eq!(t("3 4 { a b -> a b - } exec"), "-1");
+        assert_eq!(t("sub { a b -> a b - } fun 10 3 sub"), "7");
+        assert_eq!(t("g { x -> [ x x ] } fun 5 g"), "[5,5]");
+        assert_eq!(t("{ -> 1 } exec"), "1");
+    }
+
+    #[test]
+    fn test_named_parameters_errors() {
+        assert_eq!(t("{ a -> a } exec"), "StackEmpty");
+        assert_eq!(t("1 { 1 a -> a } exec"), "ExpectedVariable");
+    }
+
+    #[test]
+    fn test_recursion() {
+        assert_eq!(t("fact { n -> n 2 < if { 1 } { n 1 - fact n * } } fun 5 fact"), "120");
+    }
+
+    #[test]
+    fn test_quotations_with_parameters() {
+        assert_eq!(t("[ 1 2 3 ] map { n -> n n * }"), "[1,4,9]");
+        assert_eq!(t("[ 1 2 3 ] 0 foldl { acc x -> acc x + }"), "6");
+        assert_eq!(t("[ 1 2 ] [ 3 4 ] zipWith { a b -> a b * }"), "[3,8]");
+    }
+
+    #[test]
+    fn test_parameters_are_local() {
+        assert_eq!(t("f { a -> a } fun 7 f a"), "ProgramFinishedWithMultipleValues");
+        assert_eq!(t("a 1 := f { a -> a } fun 7 f a +"), "8");
+        assert_eq!(t("f { x -> x } fun g { x -> 1 f x + } fun 10 g"), "11");
+    }
+
+    #[test]
+    fn test_let() {
+        assert_eq!(t("5 let x x"), "5");
+        assert_eq!(t("f { a -> 10 let b a b + } fun 1 f"), "11");
+        assert_eq!(t("f { -> 10 let b b } fun f b"), "ProgramFinishedWithMultipleValues");
+        assert_eq!(t("x 1 := f { -> 2 let x x } fun f x +"), "3");
+        assert_eq!(t("5 let 1"), "ExpectedVariable");
+    }
+
+    #[test]
+    fn test_let_does_not_escape_blocks() {
+        assert_eq!(t("[ 1 2 3 ] map { let x x x + }"), "[2,4,6]");
+        assert_eq!(t("[ 1 2 3 ] map { let x x } pop x"), "x");
+        assert_eq!(t("{ 1 let z } exec z"), "z");
+        assert_eq!(t("True if { 5 let q } { } q"), "q");
+        assert_eq!(t("[ 1 2 ] each { let item } item"), "item");
+    }
+
+    #[test]
+    fn test_functions_without_parameters_do_not_see_locals_of_caller() {
+        assert_eq!(t("f { a -> g } fun g { a } fun 1 f"), "a");
+        assert_eq!(t("a 2 := f { a -> g } fun g { a } fun 1 f"), "2");
+    }
+
+    #[test]
+    fn test_assignment_updates_enclosing_scope() {
+        assert_eq!(t("f { -> 0 let acc [ 1 2 3 ] each { acc + ' acc swap := } acc } fun f"), "6");
+        assert_eq!(t("x 1 := [ 1 2 3 ] each { x + ' x swap := } x"), "7");
+        assert_eq!(t("n 0 := [ 1 2 ] map { ' n over := } n swap cons"), "[2,1,2]");
+        assert_eq!(t("f { a -> ' a 5 := a } fun a 1 := 2 f a +"), "6");
+    }
+
+    #[test]
+    fn test_scopes_are_left_after_errors() {
+        let mut bprog = Interpreter::new();
+        assert!(bprog.run("1 { a -> a 0 div } exec").is_err());
+        bprog.clear_stack();
+        bprog.run("2 let a").unwrap();
+        assert_eq!(bprog.binding("a"), Some(Token::Int(2)));
+    }
+}
+
+mod test_closures {
+    use bprog::t;
+
+    #[test]
+    fn test_closure_captures_arguments() {
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder add5 swap := 10 add5 exec"), "15");
+        assert_eq!(t("adder { n -> { n + } } fun 1 adder 2 adder 100 swap exec swap exec"), "103");
+        assert_eq!(t("x 1 := f { x -> { x } } fun 2 f exec x +"), "3");
+        assert_eq!(t("adder { n -> { x -> x n + } } fun 5 adder 1 swap exec"), "6");
+    }
+
+    #[test]
+    fn test_closure_as_quotation() {
+        assert_eq!(t("adder { n -> { n + } } fun 10 adder a10 swap := [ 1 2 3 ] map a10"), "[11,12,13]");
+        assert_eq!(t("above { n -> { n > } } fun a4 4 above := [ 1 5 9 ] filter a4"), "[5,9]");
+        assert_eq!(t("twice { f -> { f exec f exec } } fun 3 { 2 * } twice exec"), "12");
+    }
+
+    #[test]
+    fn test_partial_application() {
+        assert_eq!(t("partial { f a -> { a f exec } } fun { 10 - } 3 partial exec"), "-7");
+    }
+
+    #[test]
+    fn test_closures_share_captured_variables() {
+        let counter = "counter { -> 0 let c { ' c c 1 + := c } } fun ";
+        assert_eq!(t(&format!("{}counter k swap := k exec k exec + k exec +", counter)), "6");
+        assert_eq!(t(&format!("{}counter a swap := counter b swap := a exec a exec b exec [ ] cons cons cons", counter)), "[1,2,1]");
+    }
+
+    #[test]
+    fn test_closures_in_collections() {
+        assert_eq!(t("adders { n -> [ { n + } ] } fun 5 adders head 1 swap exec"), "6");
+        assert_eq!(t("adders { n -> #{ \" add \" { n + } } } fun 5 adders \" add \" get 2 swap exec"), "7");
+        assert_eq!(t("f { n -> [ [ { n * } ] ] } fun 3 f head head 4 swap exec"), "12");
+    }
+
+    #[test]
+    fn test_closure_equality() {
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder dup =="), "True");
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder 5 adder =="), "True");
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder 6 adder =="), "False");
+        assert_eq!(t("{ 1 + } { 1 + } =="), "True");
+        assert_eq!(t("{ 1 + } { 2 + } =="), "False");
+        assert_eq!(t("{ 1 } closure { 1 } =="), "True");
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder { n + } =="), "False");
+        assert_eq!(t("f { n -> 0 let g ' g { n g } := g } fun 1 f 1 f =="), "True");
+        assert_eq!(t("{ 1 } { 2 } <"), "ExpectedBoolOrNumber");
+    }
+
+    #[test]
+    fn test_blocks_without_locals_are_not_closures() {
+        assert_eq!(t("True if { { 1 } } { { 2 } } { 1 } =="), "True");
+        assert_eq!(t("f { { 1 } } fun f f =="), "True");
+        assert_eq!(t("True if { [ { 1 } ] } { } [ { 1 } ] =="), "True");
+        assert_eq!(t("f { n -> { 1 } } fun 5 f { 1 } =="), "True");
+    }
+
+    #[test]
+    fn test_closure_word() {
+        assert_eq!(t("{ 1 } closure exec"), "1");
+        assert_eq!(t("f { n -> { n } closure closure } fun 4 f exec"), "4");
+        assert_eq!(t("1 closure"), "ExpectedQuotation");
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder"), "{ n + }");
+        assert_eq!(t("adder { n -> { n + } } fun 5 adder length"), "2");
+    }
+}
+
+mod test_folds {
+    use bprog::t;
+
+    #[test]
+    fn test_foldl_any_accumulator() {
+        assert_eq!(t("[ 1 2 3 ] [ ] foldl { swap cons }"), "[3,2,1]");
+        assert_eq!(t("[ \" a \" \" b \" ] \" > \" foldl { append }"), "\">ab\"");
+        assert_eq!(t("[ 1 2 ] 0.5 foldl +"), "3.5");
+        assert_eq!(t("[ True False ] True foldl { && }"), "False");
+        assert_eq!(t("[ ] 7 foldl +"), "7");
+    }
+
+    #[test]
+    fn test_foldl_errors() {
+        assert_eq!(t("1 0 foldl +"), "ExpectedList");
+        assert_eq!(t("[ 1 2 ] 0 foldl 5"), "ExpectedQuotation");
+    }
+
+    #[test]
+    fn test_foldr() {
+        assert_eq!(t("[ 1 2 3 ] [ ] foldr cons"), "[1,2,3]");
+        assert_eq!(t("[ 100 20 ] 2 foldr { div }"), "10");
+        assert_eq!(t("[ 1 2 3 ] 0 foldr { swap - }"), "-6");
+    }
+
+    #[test]
+    fn test_scanl() {
+        assert_eq!(t("[ 1 2 3 ] 0 scanl +"), "[0,1,3,6]");
+        assert_eq!(t("[ ] 0 scanl +"), "[0]");
+    }
+
+    #[test]
+    fn test_reduce() {
+        assert_eq!(t("[ 4 9 2 ] reduce max"), "9");
+        assert_eq!(t("[ 5 ] reduce +"), "5");
+        assert_eq!(t("[ ] reduce +"), "ExpectedEnumerable");
+    }
+
+    #[test]
+    fn test_fold_while() {
+        assert_eq!(t("[ 1 2 3 4 5 ] 0 foldWhile { 5 < } +"), "6");
+        assert_eq!(t("[ 1 2 3 ] 0 foldWhile { 100 < } +"), "6");
+        assert_eq!(t("[ 1 2 ] 0 foldWhile { 1 + } +"), "ExpectedBool");
+    }
+}
+
+mod test_math {
+    use bprog::t;
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(t("16 sqrt"), "4.0");
+        assert_eq!(t("0 exp"), "1.0");
+        assert_eq!(t("1 ln"), "0.0");
+        assert_eq!(t("1000 log10"), "3.0");
+        assert_eq!(t("0 sin"), "0.0");
+        assert_eq!(t("0.0 cos"), "1.0");
+        assert_eq!(t("0 tan"), "0.0");
+        assert_eq!(t("1 1 atan2 4 * pi =="), "True");
+        assert_eq!(t("1 -1.0 atan2"), "2.356194490192345");
+    }
+
+    #[test]
+    fn test_constants() {
+        assert_eq!(t("pi"), "3.141592653589793");
+        assert_eq!(t("e"), "2.718281828459045");
+        assert_eq!(t("e ln"), "1.0");
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(t("2.5 floor"), "2");
+        assert_eq!(t("-2.5 floor"), "-3");
+        assert_eq!(t("2.1 ceil"), "3");
+        assert_eq!(t("-2.5 ceil"), "-2");
+        assert_eq!(t("2.5 round"), "3");
+        assert_eq!(t("-2.5 round"), "-3");
+        assert_eq!(t("2.4 round"), "2");
+        assert_eq!(t("-2.7 truncate"), "-2");
+        assert_eq!(t("7 floor"), "7");
+        assert_eq!(t("2.0 130 pow floor"), "1361129467683753853853498429727072845824");
+        assert_eq!(t("1000 exp floor"), "NumberConversionError");
+    }
+
+    #[test]
+    fn test_nan_and_infinity() {
+        assert_eq!(t("-1 sqrt"), "DomainError");
+        assert_eq!(t("-1 ln"), "DomainError");
+        assert_eq!(t("1000 exp"), "inf");
+        assert_eq!(t("0 ln"), "-inf");
+        assert_eq!(t("0 ln isInfinite"), "True");
+        assert_eq!(t("1000 exp isNaN"), "False");
+        assert_eq!(t("1000 exp dup - isNaN"), "True");
+        // NaN operands give NaN results instead of an error
+        assert_eq!(t("1000 exp dup - sqrt isNaN"), "True");
+        assert_eq!(t("5 isNaN"), "False");
+        assert_eq!(t("5 isInfinite"), "False");
+    }
+
+    #[test]
+    fn test_non_numbers() {
+        assert_eq!(t("\" 4 \" sqrt"), "ExpectedNumber");
+        assert_eq!(t("[ ] floor"), "ExpectedNumber");
+        assert_eq!(t("True isNaN"), "ExpectedNumber");
+        assert_eq!(t("1 \" 2 \" atan2"), "NumberConversionError");
+    }
+}
+
+mod test_rationals {
+    use bprog::{run_with, t, BigInt, Division, Interpreter, ProgramError, Rational, Token};
+
+    /// Evaluates a program with exact division and returns the result or the name of the error
+    fn exact(program: &str) -> String {
+        run_with(|bprog| bprog.set_division(Division::Exact), program)
+    }
+
+    #[test]
+    fn test_rational_word() {
+        assert_eq!(t("1 3 rational"), "1/3");
+        assert_eq!(t("2 6 rational"), "1/3");
+        assert_eq!(t("2 -6 rational"), "-1/3");
+        assert_eq!(t("6 3 rational"), "2");
+        assert_eq!(t("1 3 rational 2 rational"), "1/6");
+        assert_eq!(t("1 0 rational"), "DivisionByZero");
+        assert_eq!(t("1.5 2 rational"), "NumberConversionError");
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(t("1 3 rational 3 *"), "1");
+        assert_eq!(t("1 3 rational 1 6 rational +"), "1/2");
+        assert_eq!(t("1 3 rational 1 -"), "-2/3");
+        assert_eq!(t("2 3 rational 4 /"), "1/6");
+        assert_eq!(t("2 3 rational 0 /"), "DivisionByZero");
+        assert_eq!(t("7 2 rational 2 div"), "1");
+        assert_eq!(t("2 3 rational -2 pow"), "9/4");
+        assert_eq!(t("1 3 rational neg abs"), "1/3");
+        assert_eq!(t("-1 3 rational sign"), "-1");
+        // mixing with floats gives floats
+        assert_eq!(t("1 2 rational 0.25 +"), "0.75");
+    }
+
+    #[test]
+    fn test_comparison() {
+        assert_eq!(t("1 3 rational 1 2 rational <"), "True");
+        assert_eq!(t("2 6 rational 1 3 rational =="), "True");
+        assert_eq!(t("1 2 rational 0.5 =="), "True");
+        assert_eq!(t("7 2 rational 3 >"), "True");
+        assert_eq!(t("1 3 rational 1 4 rational max"), "1/3");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(t("1 4 rational toFloat"), "0.25");
+        assert_eq!(t("3 toFloat"), "3.0");
+        assert_eq!(t("7 2 rational toInt"), "3");
+        assert_eq!(t("-7 2 rational toInt"), "-3");
+        assert_eq!(t("-7.9 toInt"), "-7");
+        assert_eq!(t("-7 2 rational floor"), "-4");
+        assert_eq!(t("7 2 rational ceil"), "4");
+        assert_eq!(t("-7 2 rational round"), "-4");
+        assert_eq!(t("1 3 rational round"), "0");
+        assert_eq!(t("\" 1 \" toFloat"), "ExpectedNumber");
+    }
+
+    #[test]
+    fn test_exact_rounding() {
+        assert_eq!(t("49999999999999999999 100000000000000000000 rational round"), "0");
+        assert_eq!(t("-49999999999999999999 100000000000000000000 rational round"), "0");
+        assert_eq!(t("50000000000000000001 100000000000000000000 rational round"), "1");
+        assert_eq!(t("1 2 rational round"), "1");
+        assert_eq!(t("-1 2 rational round"), "-1");
+        assert_eq!(t("-1 2 400 pow rational floor"), "-1");
+        assert_eq!(t("1 2 400 pow rational ceil"), "1");
+    }
+
+    #[test]
+    fn test_exact_division() {
+        assert_eq!(t("1 3 / 3 *"), "1.0");
+        assert_eq!(exact("1 3 / 3 *"), "1");
+        assert_eq!(exact("1 10 / 2 10 / + 3 10 / =="), "True");
+        assert_eq!(exact("6 3 /"), "2");
+        assert_eq!(exact("1 0 /"), "DivisionByZero");
+        assert_eq!(exact("1.0 4 /"), "0.25");
+        assert_eq!(exact("[ 1 2 3 ] map { 1 swap / }"), "[1,1/2,1/3]");
+    }
+
+    #[test]
+    fn test_token_conversions() {
+        let third = Rational::new(BigInt::from(2), BigInt::from(6)).unwrap();
+        assert_eq!(third.to_string(), "1/3");
+        assert_eq!(Token::from(third.clone()).to_json(), "\"1/3\"");
+        assert_eq!(Token::from(third.clone()).type_name(), "Rational");
+        assert!(matches!(Token::from(Rational::from(BigInt::from(4))), Token::Int(4)));
+        assert!(Rational::new(BigInt::from(1), BigInt::from(0)).is_none());
+        assert_eq!(Rational::try_from(Token::Int(5)).unwrap(), Rational::from(BigInt::from(5)));
+        assert!(matches!(i128::try_from(Token::from(third.clone())), Err(ProgramError::NumberConversionError)));
+        let mut bprog = Interpreter::new();
+        bprog.push(Token::from(third));
+        assert_eq!(bprog.eval("3 *").unwrap(), Token::Int(1));
+    }
+}
+
+mod test_decimals {
+    use bprog::{run_with, t, BigInt, Decimal, DecimalContext, Interpreter, Rounding, Token};
+
+    /// Evaluates a program with the given decimal context and returns the result or the
+    /// name of the error
+    fn with_context(scale: u32, rounding: Rounding, program: &str) -> String {
+        run_with(|bprog| bprog.set_decimal_context(DecimalContext { scale, rounding }), program)
+    }
+
+    #[test]
+    fn test_literals() {
+        assert_eq!(t("12.50d"), "12.50d");
+        assert_eq!(t("-0.05d"), "-0.05d");
+        assert_eq!(t("3d"), "3d");
+        assert_eq!(t("\" 19.990 \" parseDecimal"), "19.990d");
+        assert_eq!(t("\" -.5 \" parseDecimal"), "-0.5d");
+        assert_eq!(t("\" 1.2.3 \" parseDecimal"), "NumberConversionError");
+        assert_eq!(t("\" 12.50d \" parseDecimal"), "NumberConversionError");
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        assert_eq!(t("0.1d 0.2d +"), "0.3d");
+        assert_eq!(t("0.1d 0.2d + 0.3d =="), "True");
+        assert_eq!(t("0.1 0.2 + 0.3 =="), "False");
+        assert_eq!(t("12.50d 1 -"), "11.50d");
+        assert_eq!(t("19.99d 3 *"), "59.97d");
+        assert_eq!(t("1.5d 1.25d *"), "1.875d");
+        assert_eq!(t("1.1d 3 pow"), "1.331d");
+        assert_eq!(t("-2.50d neg abs"), "2.50d");
+        assert_eq!(t("-2.50d sign"), "-1");
+        assert_eq!(t("7.5d 2 div"), "3");
+        assert_eq!(t("7.5d 2 rem"), "1.5d");
+        assert_eq!(t("-7.5d 2 mod"), "0.5d");
+        assert_eq!(t("7.5d 0 rem"), "DivisionByZero");
+    }
+
+    #[test]
+    fn test_division() {
+        assert_eq!(t("10.00d 4 /"), "2.5000000000d");
+        assert_eq!(t("10.00d 3 /"), "3.3333333333d");
+        assert_eq!(t("1d 0 /"), "DivisionByZero");
+        assert_eq!(with_context(2, Rounding::HalfEven, "2.00d 3 /"), "0.67d");
+        assert_eq!(with_context(0, Rounding::HalfEven, "5d 2 /"), "2d");
+        assert_eq!(with_context(0, Rounding::HalfEven, "7d 2 /"), "4d");
+        assert_eq!(with_context(0, Rounding::HalfUp, "5d 2 /"), "3d");
+        assert_eq!(with_context(0, Rounding::HalfDown, "5d 2 /"), "2d");
+        assert_eq!(with_context(0, Rounding::Down, "-5d 3 /"), "-1d");
+        assert_eq!(with_context(0, Rounding::Up, "-5d 3 /"), "-2d");
+        assert_eq!(with_context(0, Rounding::Floor, "-5d 3 /"), "-2d");
+        assert_eq!(with_context(0, Rounding::Ceiling, "-5d 3 /"), "-1d");
+        assert_eq!(with_context(3, Rounding::Down, "2 3.0d /"), "0.666d");
+        assert_eq!(with_context(4, Rounding::HalfUp, "[ 2d 1d ] 3d foldl /"), "1.5000d");
+        assert_eq!(with_context(1, Rounding::Down, "[ 1d 2d ] map { 3 / }"), "[0.3d,0.6d]");
+    }
+
+    #[test]
+    fn test_mixed_types() {
+        assert_eq!(t("12.50d 12.5d =="), "True");
+        assert_eq!(t("1.5d 2 <"), "True");
+        assert_eq!(t("1.10d 1.2d max"), "1.2d");
+        assert_eq!(t("1.5d 2.0 +"), "3.5");
+        assert_eq!(t("0.5d 1 4 rational +"), "3/4");
+        assert_eq!(t("1.5d 3 rational"), "1/2");
+        assert_eq!(t("2.5d round"), "3");
+        assert_eq!(t("0.49999999999999999999d round"), "0");
+        assert_eq!(t("-0.50000000000000000001d round"), "-1");
+        assert_eq!(t("-2.5d floor"), "-3");
+        assert_eq!(t("2.25d toFloat"), "2.25");
+        assert_eq!(t("0.25d sqrt"), "0.5");
+    }
+
+    #[test]
+    fn test_token_conversions() {
+        let price = Decimal::new(BigInt::from(1250), 2);
+        assert_eq!(price.to_string(), "12.50");
+        assert_eq!(Token::from(price.clone()).to_json(), "12.50");
+        assert_eq!(Token::from(price.clone()).type_name(), "Decimal");
+        assert_eq!(Decimal::try_from(Token::Int(3)).unwrap().to_string(), "3");
+        assert_eq!("-0.007".parse::<Decimal>().unwrap().to_string(), "-0.007");
+        let mut bprog = Interpreter::new();
+        bprog.push(Token::from(price));
+        assert_eq!(bprog.eval("2 *").unwrap().to_string(), "25.00d");
+    }
+}
+
+mod test_integer_overflow {
+    use bprog::{run_with, BigInt, Error, Interpreter, Overflow, ProgramError, Token};
+
+    /// Evaluates a program with the given overflow policy and returns the result or
+    /// the name of the error
+    fn overflowing(overflow: Overflow, program: &str) -> String {
+        run_with(|bprog| bprog.set_overflow(overflow), program)
+    }
+
+    fn promote(program: &str) -> String {
+        overflowing(Overflow::Promote, program)
+    }
+
+    #[test]
+    fn test_promotion() {
+        assert_eq!(promote("170141183460469231731687303715884105727 1 +"), "170141183460469231731687303715884105728");
+        assert_eq!(promote("-170141183460469231731687303715884105728 1 -"), "-170141183460469231731687303715884105729");
+        assert_eq!(promote("-170141183460469231731687303715884105728 neg"), "170141183460469231731687303715884105728");
+        assert_eq!(promote("-170141183460469231731687303715884105728 abs"), "170141183460469231731687303715884105728");
+        assert_eq!(promote("-170141183460469231731687303715884105728 -1 div"), "170141183460469231731687303715884105728");
+        assert_eq!(promote("2 127 pow 2 *"), "340282366920938463463374607431768211456");
+    }
+
+    #[test]
+    fn test_factorial() {
+        let factorial = "fact { dup 1 > if { dup 1 - fact * } { } } fun ";
+        assert_eq!(promote(format!("{}40 fact", factorial).as_str()), "815915283247897734345611269596115894272000000000");
+        assert_eq!(promote(format!("{}40 fact 39 fact div", factorial).as_str()), "40");
+    }
+
+    #[test]
+    fn test_results_that_fit_are_ints() {
+        assert_eq!(promote("2 200 pow 2 200 pow - 1 +"), "1");
+        assert_eq!(promote("2 200 pow 2 199 pow div"), "2");
+        let mut bprog = Interpreter::new();
+        assert_eq!(bprog.eval("2 130 pow 2 129 pow -").unwrap(), Token::BigInt(BigInt::from(2).pow(129)));
+        assert_eq!(bprog.eval("2 130 pow 2 130 pow 5 + -").unwrap(), Token::Int(-5));
+    }
+
+    #[test]
+    fn test_big_division() {
+        let (x, y) = ("12345678901234567890123456789012345678901234567890", "98765432109876543210");
+        assert_eq!(promote(format!("{} {} div", x, y).as_str()), "124999998860937500015488281238");
+        assert_eq!(promote(format!("{} {} rem", x, y).as_str()), "42607060205495273910");
+        assert_eq!(promote(format!("-{} {} rem", x, y).as_str()), "-42607060205495273910");
+        assert_eq!(promote(format!("-{} {} mod", x, y).as_str()), "56158371904381269300");
+        assert_eq!(promote("2 200 pow 0 div"), "DivisionByZero");
+        assert_eq!(promote("2 200 pow 0 mod"), "DivisionByZero");
+        assert_eq!(promote("2 200 pow 2 201 pow /"), "0.5");
+    }
+
+    #[test]
+    fn test_big_powers_are_bounded() {
+        assert_eq!(promote("3 3000000 pow 1 + pop 1"), "IntegerOverflow");
+        assert_eq!(promote("2 130 pow 100000 pow"), "IntegerOverflow");
+        assert_eq!(promote("1 3 rational 3000000 pow"), "IntegerOverflow");
+        assert_eq!(promote("1.5d 3000000 pow"), "IntegerOverflow");
+        assert_eq!(promote("2 100000 pow 2 99999 pow div"), "2");
+        assert_eq!(promote("-1 4000000001 pow"), "-1");
+    }
+
+    #[test]
+    fn test_big_comparison() {
+        assert_eq!(promote("2 130 pow 2 129 pow >"), "True");
+        assert_eq!(promote("2 130 pow neg 1 <"), "True");
+        assert_eq!(promote("2 130 pow 1.5 >"), "True");
+        assert_eq!(promote("2 130 pow neg 1.5 <"), "True");
+        assert_eq!(promote("2 130 pow 2 130 pow =="), "True");
+        assert_eq!(promote("2 130 pow 2 130 pow 1 + =="), "False");
+        assert_eq!(promote("2 130 pow 1 max 2 130 pow =="), "True");
+        assert_eq!(promote("2 130 pow neg sign"), "-1");
+    }
+
+    #[test]
+    fn test_big_literals_and_parsing() {
+        assert_eq!(promote("123456789012345678901234567890123456789012345678901234567890"),
+                   "123456789012345678901234567890123456789012345678901234567890");
+        assert_eq!(promote("-000000000000000000000000000000000000000000000000001"), "-1");
+        assert_eq!(promote("\" 1000000000000000000000000000000000000000 \" parseInteger"), "1000000000000000000000000000000000000000");
+        assert_eq!(promote("\" 1000000000000000000000000000000000000000x \" parseInteger"), "NumberConversionError");
+        assert_eq!(promote("2 130 pow 0.5 +"), "1.361129467683754e39");
+        assert_eq!(promote("[ 170141183460469231731687303715884105728 ]"), "[170141183460469231731687303715884105728]");
+    }
+
+    #[test]
+    fn test_overflow_error() {
+        let error = |program| overflowing(Overflow::Error, program);
+        assert_eq!(error("170141183460469231731687303715884105727 1 +"), "IntegerOverflow");
+        assert_eq!(error("-170141183460469231731687303715884105728 1 -"), "IntegerOverflow");
+        assert_eq!(error("2 64 pow 2 64 pow *"), "IntegerOverflow");
+        assert_eq!(error("-170141183460469231731687303715884105728 neg"), "IntegerOverflow");
+        assert_eq!(error("2 200 pow"), "IntegerOverflow");
+        assert_eq!(error("2 126 pow 2 *"), "IntegerOverflow");
+        assert_eq!(error("2 126 pow 2 div"), "42535295865117307932921825928971026432");
+    }
+
+    #[test]
+    fn test_overflow_error_is_located() {
+        let mut bprog = Interpreter::new();
+        bprog.set_overflow(Overflow::Error);
+        let error = bprog.eval("1 2 127 pow +").unwrap_err();
+        assert!(error.to_string().starts_with("IntegerOverflow in `pow` at line 1, column 9"));
+        assert!(matches!(error, Error::Program(e) if matches!(e.kind(), ProgramError::IntegerOverflow)));
+    }
+
+    #[test]
+    fn test_token_conversions() {
+        let big = Token::from(BigInt::from(i128::MAX) + BigInt::from(1));
+        assert!(matches!(big, Token::BigInt(_)));
+        assert!(matches!(Token::from(BigInt::from(7)), Token::Int(7)));
+        assert!(matches!(i128::try_from(big.clone()), Err(ProgramError::NumberConversionError)));
+        assert_eq!(f64::try_from(big.clone()).unwrap(), 2f64.powi(127));
+        assert_eq!(BigInt::try_from(big.clone()).unwrap().to_string(), "170141183460469231731687303715884105728");
+        assert_eq!(big.to_json(), "170141183460469231731687303715884105728");
+        assert_eq!(big.type_name(), "Int");
+    }
+}
+
+mod test_frames {
+    use bprog::{t, t_err};
+
+    #[test]
+    fn test_deep_recursion() {
+        assert_eq!(t("down { n -> n 0 == if { 0 } { n 1 - down } } fun 20000 down"), "0");
+        assert_eq!(t("total { n -> n 0 == if { 0 } { n n 1 - total + } } fun 20000 total"), "200010000");
+        assert_eq!(t("down { dup 0 == if { } { 1 - down } } fun 20000 down"), "0");
+        assert_eq!(t("c { n -> n 0 == if { { 1 } } { n 1 - c } } fun 20000 c exec"), "1");
+    }
+
+    #[test]
+    fn test_deep_exec_recursion() {
+        assert_eq!(t("' down { dup 0 == if { } { 1 - down exec } } := 20000 down exec"), "0");
+        assert_eq!(t("' down { n -> n 0 == if { 0 } { n 1 - down exec } } := 20000 down exec"), "0");
+        assert_eq!(t("' down { n -> n 0 == if { 0 } { n 1 - down exec } } := { 20000 down exec } exec"), "0");
+    }
+
+    #[test]
+    fn test_deep_recursion_through_quotations() {
+        assert_eq!(t("f { n -> n 0 == if { 0 } { [ n ] map { 1 - f } head } } fun 20000 f"), "0");
+        assert_eq!(t("f { n -> n 0 == if { 0 } { [ n ] 0 foldl { + 1 - f } } } fun 20000 f"), "0");
+        assert_eq!(t("f { n -> n 0 == if { True } { [ n ] any { 1 - f } } } fun 20000 f"), "True");
+        assert_eq!(t("f { n -> n 0 == if { 0 } { [ n ] each { 1 - f } } } fun 20000 f"), "0");
+    }
+
+    #[test]
+    fn test_functions_do_not_see_locals_of_caller() {
+        assert_eq!(t("g { -> y } fun f { y -> g } fun 1 f"), "y");
+        assert_eq!(t("y 2 := g { -> y } fun f { y -> g } fun 1 f"), "2");
+    }
+
+    #[test]
+    fn test_errors_in_deep_recursion() {
+        assert!(t_err("f { n -> n 0 == if { 1 0 div } { n 1 - f } } fun 10000 f").starts_with("DivisionByZero in `div` at line 1, column 26"));
+        assert!(t_err("[ 1 2 ] map { dup }").starts_with("ProgramFinishedWithMultipleValues in `map` at line 1, column 9"));
+        assert!(t_err("[ 1 2 ] 0 foldl { pop pop }").starts_with("StackEmpty in `foldl` at line 1, column 11"));
+    }
+
+    #[test]
+    fn test_block_in_list_is_not_called() {
+        assert_eq!(t("f { 1 } fun [ f ]"), "[{ 1 }]");
+    }
+}